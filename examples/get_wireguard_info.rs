@@ -8,7 +8,7 @@ use netlink_packet_core::{
 use netlink_packet_generic::GenlMessage;
 use netlink_packet_wireguard::{
     nlas::{WgAllowedIpAttrs, WgDeviceAttrs, WgPeerAttrs},
-    Wireguard, WireguardCmd,
+    WgKey, Wireguard, WireguardCmd,
 };
 use std::env::args;
 
@@ -56,7 +56,7 @@ fn print_wg_payload(wg: Wireguard) {
             WgDeviceAttrs::IfName(v) => println!("IfName: {}", v),
             WgDeviceAttrs::PrivateKey(_) => println!("PrivateKey: (hidden)"),
             WgDeviceAttrs::PublicKey(v) => {
-                println!("PublicKey: {}", base64::encode(v))
+                println!("PublicKey: {}", WgKey::from(v).to_base64())
             }
             WgDeviceAttrs::ListenPort(v) => println!("ListenPort: {}", v),
             WgDeviceAttrs::Fwmark(v) => println!("Fwmark: {}", v),
@@ -75,7 +75,7 @@ fn print_wg_peer(nlas: &[WgPeerAttrs]) {
     for nla in nlas {
         match nla {
             WgPeerAttrs::PublicKey(v) => {
-                println!("  PublicKey: {}", base64::encode(v))
+                println!("  PublicKey: {}", WgKey::from(v).to_base64())
             }
             WgPeerAttrs::PresharedKey(_) => {
                 println!("  PresharedKey: (hidden)")
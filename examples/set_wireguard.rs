@@ -2,7 +2,6 @@
 
 use futures::StreamExt;
 use genetlink::new_connection;
-use getrandom;
 use netlink_packet_core::{
     NetlinkMessage, NetlinkPayload, NLM_F_ACK, NLM_F_REQUEST,
 };
@@ -10,9 +9,8 @@ use netlink_packet_generic::GenlMessage;
 use netlink_packet_wireguard::constants::*;
 use netlink_packet_wireguard::{
     nlas::{WgAllowedIp, WgAllowedIpAttrs, WgDeviceAttrs, WgPeer, WgPeerAttrs},
-    Wireguard, WireguardCmd,
+    WgKey, Wireguard, WireguardCmd,
 };
-use std::convert::TryInto;
 use std::env::args;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 
@@ -31,13 +29,12 @@ async fn main() {
     let name = argv[1].clone();
     let priv_key = generate_priv_key();
     let peer_pub_key: [u8; WG_KEY_LEN] =
-        base64::decode("8bdQrVLqiw3ZoHCucNh1YfH0iCWuyStniRr8t7H24Fk=")
+        WgKey::from_base64("8bdQrVLqiw3ZoHCucNh1YfH0iCWuyStniRr8t7H24Fk=")
             .unwrap()
-            .try_into()
-            .unwrap();
+            .into();
 
     let (connection, mut handle, _) = new_connection().unwrap();
-    let _ = tokio::spawn(connection);
+    tokio::spawn(connection);
 
     let nlas = vec![
         WgDeviceAttrs::IfName(name),
@@ -79,22 +76,16 @@ async fn main() {
 
     if let Some(result) = res.next().await {
         let rx_packet = result.unwrap();
-        match rx_packet.payload {
-            NetlinkPayload::Error(e) => {
-                eprintln!("Error: {:?}", e.to_io());
-            }
-            _ => (),
+        if let NetlinkPayload::Error(e) = rx_packet.payload {
+            eprintln!("Error: {:?}", e.to_io());
         }
     }
 }
 
 fn generate_priv_key() -> [u8; WG_KEY_LEN] {
-    let mut key = [0u8; WG_KEY_LEN];
-    getrandom::getrandom(&mut key).unwrap();
-    // modify random bytes using algorithm described
-    // at https://cr.yp.to/ecdh.html.
-    key[0] = key[0] & 248;
-    key[31] = key[31] & 127;
-    key[31] = key[31] | 64;
-    key
+    let mut bytes = [0u8; WG_KEY_LEN];
+    getrandom::getrandom(&mut bytes).unwrap();
+    let mut key = WgKey::new(bytes);
+    key.clamp_private();
+    key.into()
 }
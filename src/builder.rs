@@ -0,0 +1,813 @@
+// SPDX-License-Identifier: MIT
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use netlink_packet_utils::Emitable;
+
+use crate::{
+    constants::{AF_INET, AF_INET6},
+    nlas::{WgAllowedIp, WgAllowedIpAttrs, WgDeviceAttrs, WgPeer, WgPeerAttrs},
+    Device, InterfaceId, Peer, SecretKey, WgDeviceFlags, WgError, WgKey,
+    WgPeerFlags, Wireguard, WireguardCmd,
+};
+
+/// Build a `SetDevice` message.
+///
+/// The attributes are emitted in the order described in
+/// [`Wireguard::sort_canonical`]. To build a [`Device`] that is kept
+/// around, compared or serialized before being sent, see
+/// [`DeviceBuilder`].
+///
+/// ```
+/// use netlink_packet_wireguard::{WgDeviceBuilder, WgPeerBuilder};
+///
+/// let wg = WgDeviceBuilder::new()
+///     .ifname("wg0")
+///     .private_key([0xaa; 32])
+///     .listen_port(51820)
+///     .peer(WgPeerBuilder::new([0x01; 32]).build())
+///     .build();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct WgDeviceBuilder {
+    ifindex: Option<u32>,
+    ifname: Option<String>,
+    private_key: Option<SecretKey>,
+    listen_port: Option<u16>,
+    fwmark: Option<u32>,
+    flags: Option<WgDeviceFlags>,
+    peers: Vec<WgPeer>,
+}
+
+impl WgDeviceBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn ifindex(mut self, index: u32) -> Self {
+        self.ifindex = Some(index);
+        self
+    }
+
+    pub fn ifname<T: Into<String>>(mut self, name: T) -> Self {
+        self.ifname = Some(name.into());
+        self
+    }
+
+    pub fn private_key<K: Into<WgKey>>(mut self, key: K) -> Self {
+        self.private_key = Some(SecretKey::new(key.into()));
+        self
+    }
+
+    pub fn listen_port(mut self, port: u16) -> Self {
+        self.listen_port = Some(port);
+        self
+    }
+
+    pub fn fwmark(mut self, fwmark: u32) -> Self {
+        self.fwmark = Some(fwmark);
+        self
+    }
+
+    /// Set the `WGDEVICE_F_*` flags of the message, replacing the ones set
+    /// so far.
+    pub fn flags(mut self, flags: WgDeviceFlags) -> Self {
+        self.flags = Some(flags);
+        self
+    }
+
+    /// Add `WGDEVICE_F_REPLACE_PEERS`: the peers missing from the message
+    /// are removed from the device.
+    pub fn replace_peers(mut self) -> Self {
+        self.flags =
+            Some(self.flags.unwrap_or_default() | WgDeviceFlags::REPLACE_PEERS);
+        self
+    }
+
+    pub fn peer(mut self, peer: WgPeer) -> Self {
+        self.peers.push(peer);
+        self
+    }
+
+    pub fn peers<I: IntoIterator<Item = WgPeer>>(mut self, peers: I) -> Self {
+        self.peers.extend(peers);
+        self
+    }
+
+    pub fn build(self) -> Wireguard {
+        let mut nlas = Vec::new();
+        if let Some(index) = self.ifindex {
+            nlas.push(WgDeviceAttrs::IfIndex(index));
+        }
+        if let Some(name) = self.ifname {
+            nlas.push(WgDeviceAttrs::IfName(name));
+        }
+        if let Some(key) = self.private_key {
+            nlas.push(WgDeviceAttrs::PrivateKey((*key.expose_secret()).into()));
+        }
+        if let Some(port) = self.listen_port {
+            nlas.push(WgDeviceAttrs::ListenPort(port));
+        }
+        if let Some(fwmark) = self.fwmark {
+            nlas.push(WgDeviceAttrs::Fwmark(fwmark));
+        }
+        if let Some(flags) = self.flags {
+            nlas.push(WgDeviceAttrs::Flags(flags.into()));
+        }
+        if !self.peers.is_empty() {
+            nlas.push(WgDeviceAttrs::Peers(self.peers));
+        }
+        Wireguard {
+            cmd: WireguardCmd::SetDevice,
+            nlas,
+        }
+    }
+
+    /// Build the message and emit its attributes, without the netlink and
+    /// generic netlink headers. See [`Wireguard::parse_nlas_at`] to parse
+    /// them back.
+    pub fn emit(self) -> Vec<u8> {
+        let wg = self.build();
+        let mut buf = vec![0; wg.buffer_len()];
+        wg.emit(&mut buf);
+        buf
+    }
+}
+
+/// Build a [`WgPeer`] for a `SetDevice` message.
+#[derive(Clone, Debug)]
+pub struct WgPeerBuilder {
+    public_key: WgKey,
+    flags: Option<WgPeerFlags>,
+    preshared_key: Option<SecretKey>,
+    endpoint: Option<SocketAddr>,
+    unresolved_endpoint: Option<(String, u16)>,
+    persistent_keepalive: Option<u16>,
+    protocol_version: Option<u32>,
+    allowed_ips: Vec<WgAllowedIp>,
+}
+
+impl WgPeerBuilder {
+    pub fn new<K: Into<WgKey>>(public_key: K) -> Self {
+        Self {
+            public_key: public_key.into(),
+            flags: None,
+            preshared_key: None,
+            endpoint: None,
+            unresolved_endpoint: None,
+            persistent_keepalive: None,
+            protocol_version: None,
+            allowed_ips: Vec::new(),
+        }
+    }
+
+    /// Set the `WGPEER_F_*` flags of the peer, replacing the ones set so
+    /// far.
+    pub fn flags(mut self, flags: WgPeerFlags) -> Self {
+        self.flags = Some(flags);
+        self
+    }
+
+    pub fn preshared_key<K: Into<WgKey>>(mut self, key: K) -> Self {
+        self.preshared_key = Some(SecretKey::new(key.into()));
+        self
+    }
+
+    pub fn endpoint(mut self, endpoint: SocketAddr) -> Self {
+        self.endpoint = Some(endpoint);
+        self.unresolved_endpoint = None;
+        self
+    }
+
+    /// Set the endpoint from a string as written in `wg` configurations:
+    /// `ip:port`, `[ipv6]:port` or `host:port`.
+    ///
+    /// Host names are not resolved: the kernel only accepts addresses, so
+    /// `host:port` is kept aside, without setting the endpoint, for the
+    /// caller to resolve it (see [`WgPeerBuilder::unresolved_endpoint`])
+    /// and call [`WgPeerBuilder::endpoint`] with the result. Only a
+    /// malformed string is an error.
+    pub fn endpoint_str(mut self, endpoint: &str) -> Result<Self, WgError> {
+        if let Ok(addr) = endpoint.parse() {
+            return Ok(self.endpoint(addr));
+        }
+        let invalid = || WgError::InvalidSocketAddr(endpoint.to_string());
+        let (host, port) = endpoint.rsplit_once(':').ok_or_else(invalid)?;
+        let port = port.parse().map_err(|_| invalid())?;
+        let valid_host = !host.is_empty()
+            && host
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.'));
+        if !valid_host {
+            return Err(invalid());
+        }
+        self.endpoint = None;
+        self.unresolved_endpoint = Some((host.to_string(), port));
+        Ok(self)
+    }
+
+    /// The host name and port given to [`WgPeerBuilder::endpoint_str`],
+    /// if they still have to be resolved. [`WgPeerBuilder::build`] leaves
+    /// them out of the peer.
+    pub fn unresolved_endpoint(&self) -> Option<(&str, u16)> {
+        self.unresolved_endpoint
+            .as_ref()
+            .map(|(host, port)| (host.as_str(), *port))
+    }
+
+    pub fn persistent_keepalive(mut self, interval: u16) -> Self {
+        self.persistent_keepalive = Some(interval);
+        self
+    }
+
+    /// Pin the peer to a protocol version. Only version 1 exists.
+    pub fn protocol_version(mut self, version: u32) -> Self {
+        self.protocol_version = Some(version);
+        self
+    }
+
+    /// Append an allowed IP. When it has an address but no
+    /// `WGALLOWEDIP_A_FAMILY` attribute, e.g. when it was assembled by hand,
+    /// the family of the address is prepended: the kernel rejects allowed
+    /// IPs without one.
+    pub fn allowed_ip(mut self, mut allowed_ip: WgAllowedIp) -> Self {
+        let has_family = allowed_ip
+            .iter()
+            .any(|nla| matches!(nla, WgAllowedIpAttrs::Family(_)));
+        let addr = allowed_ip.iter().find_map(|nla| match nla {
+            WgAllowedIpAttrs::IpAddr(addr) => Some(*addr),
+            _ => None,
+        });
+        if let (false, Some(addr)) = (has_family, addr) {
+            let family = if addr.is_ipv4() { AF_INET } else { AF_INET6 };
+            allowed_ip.0.insert(0, WgAllowedIpAttrs::Family(family));
+        }
+        self.allowed_ips.push(allowed_ip);
+        self
+    }
+
+    /// Append an allowed IP built with [`WgAllowedIp::new_unchecked`]: the
+    /// prefix length is not checked.
+    pub fn allowed_ip_unchecked(mut self, addr: IpAddr, cidr: u8) -> Self {
+        self.allowed_ips
+            .push(WgAllowedIp::new_unchecked(addr, cidr));
+        self
+    }
+
+    /// Parse and append allowed IPs written as in `wg` configurations,
+    /// e.g. `10.0.0.0/24`, with the [`FromStr`](std::str::FromStr)
+    /// implementation of [`WgAllowedIp`].
+    pub fn allowed_cidrs<'a, I>(mut self, cidrs: I) -> Result<Self, WgError>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        for cidr in cidrs {
+            self.allowed_ips.push(cidr.parse()?);
+        }
+        Ok(self)
+    }
+
+    pub fn build(self) -> WgPeer {
+        let mut nlas = vec![WgPeerAttrs::PublicKey(self.public_key.into())];
+        if let Some(flags) = self.flags {
+            nlas.push(WgPeerAttrs::Flags(flags.into()));
+        }
+        if let Some(key) = self.preshared_key {
+            nlas.push(WgPeerAttrs::PresharedKey((*key.expose_secret()).into()));
+        }
+        if let Some(endpoint) = self.endpoint {
+            nlas.push(WgPeerAttrs::Endpoint(endpoint));
+        }
+        if let Some(interval) = self.persistent_keepalive {
+            nlas.push(WgPeerAttrs::PersistentKeepalive(interval));
+        }
+        if let Some(version) = self.protocol_version {
+            nlas.push(WgPeerAttrs::ProtocolVersion(version));
+        }
+        if !self.allowed_ips.is_empty() {
+            nlas.push(WgPeerAttrs::AllowedIps(self.allowed_ips));
+        }
+        WgPeer(nlas)
+    }
+}
+
+/// Build a [`Device`], the configuration of an interface with named
+/// fields.
+///
+/// Use it when the configuration is the value the program works with, and
+/// turn it into a message with [`Wireguard::reset_to`] when it is
+/// applied. To send a message changing only some settings, e.g. adding a
+/// peer without replacing the others, use [`WgDeviceBuilder`].
+///
+/// ```
+/// use netlink_packet_wireguard::{DeviceBuilder, Peer, Wireguard};
+///
+/// let device = DeviceBuilder::new()
+///     .ifname("wg0")
+///     .listen_port(51820)
+///     .peer(Peer {
+///         public_key: [0x01; 32].into(),
+///         ..Default::default()
+///     })
+///     .build();
+/// let wg = Wireguard::reset_to(&device);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct DeviceBuilder {
+    device: Device,
+}
+
+impl DeviceBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn ifindex(mut self, index: u32) -> Self {
+        self.device.ifindex = Some(index);
+        self
+    }
+
+    pub fn ifname<T: Into<String>>(mut self, name: T) -> Self {
+        self.device.ifname = Some(name.into());
+        self
+    }
+
+    pub fn private_key<K: Into<WgKey>>(mut self, key: K) -> Self {
+        self.device.private_key = Some(SecretKey::new(key.into()));
+        self
+    }
+
+    pub fn listen_port(mut self, port: u16) -> Self {
+        self.device.listen_port = Some(port);
+        self
+    }
+
+    pub fn fwmark(mut self, fwmark: u32) -> Self {
+        self.device.fwmark = Some(fwmark);
+        self
+    }
+
+    pub fn peer(mut self, peer: Peer) -> Self {
+        self.device.peers.push(peer);
+        self
+    }
+
+    pub fn peers<I: IntoIterator<Item = Peer>>(mut self, peers: I) -> Self {
+        self.device.peers.extend(peers);
+        self
+    }
+
+    pub fn build(self) -> Device {
+        self.device
+    }
+}
+
+impl Wireguard {
+    /// The `SetDevice` message of a typical VPN client, as built by hand in
+    /// the `set_wireguard` example: the interface routes all the IPv4
+    /// (`0.0.0.0/0`) and IPv6 (`::/0`) traffic through a single peer.
+    pub fn full_tunnel<T, K, P>(
+        ifname: T,
+        private_key: K,
+        listen_port: u16,
+        peer_public_key: P,
+        endpoint: SocketAddr,
+    ) -> Self
+    where
+        T: Into<String>,
+        K: Into<WgKey>,
+        P: Into<WgKey>,
+    {
+        let peer = WgPeerBuilder::new(peer_public_key)
+            .endpoint(endpoint)
+            .allowed_ip_unchecked(Ipv4Addr::UNSPECIFIED.into(), 0)
+            .allowed_ip_unchecked(Ipv6Addr::UNSPECIFIED.into(), 0)
+            .build();
+        WgDeviceBuilder::new()
+            .ifname(ifname)
+            .private_key(private_key)
+            .listen_port(listen_port)
+            .fwmark(0)
+            .peer(peer)
+            .build()
+    }
+
+    /// A `SetDevice` message carrying only the identity of the device and
+    /// `peers`, to send after a first message when the peers don't fit in
+    /// a single one.
+    ///
+    /// No flags are set: `WGDEVICE_F_REPLACE_PEERS` in a follow-up message
+    /// would remove the peers added by the previous ones.
+    pub fn peers_continuation<I>(ifname_or_index: I, peers: Vec<WgPeer>) -> Self
+    where
+        I: Into<InterfaceId>,
+    {
+        Self {
+            cmd: WireguardCmd::SetDevice,
+            nlas: vec![
+                ifname_or_index.into().to_attr(),
+                WgDeviceAttrs::Peers(peers),
+            ],
+        }
+    }
+
+    /// A `SetDevice` message removing the peers `public_keys` from the
+    /// device, each flagged `WGPEER_F_REMOVE_ME`. The other peers are
+    /// kept.
+    pub fn remove_peers<I, K, T>(ifname_or_index: I, public_keys: T) -> Self
+    where
+        I: Into<InterfaceId>,
+        K: Into<WgKey>,
+        T: IntoIterator<Item = K>,
+    {
+        let peers = public_keys
+            .into_iter()
+            .map(|key| {
+                WgPeerBuilder::new(key)
+                    .flags(WgPeerFlags::REMOVE_ME)
+                    .build()
+            })
+            .collect();
+        Self::peers_continuation(ifname_or_index, peers)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{constants::*, AllowedIp};
+
+    #[test]
+    fn test_device_builder() {
+        let peer = Peer {
+            public_key: [0x01; WG_KEY_LEN].into(),
+            persistent_keepalive: Some(25),
+            allowed_ips: vec![AllowedIp {
+                addr: [10, 0, 0, 0].into(),
+                cidr: 24,
+            }],
+            ..Default::default()
+        };
+        let device = DeviceBuilder::new()
+            .ifname("wg0")
+            .private_key([0xaa; WG_KEY_LEN])
+            .listen_port(51820)
+            .peer(peer.clone())
+            .build();
+        assert_eq!(device.ifname.as_deref(), Some("wg0"));
+        assert_eq!(device.peers, vec![peer]);
+
+        let wg = Wireguard::reset_to(&device);
+        assert_eq!(
+            wg.nlas,
+            vec![
+                WgDeviceAttrs::IfName("wg0".to_string()),
+                WgDeviceAttrs::PrivateKey([0xaa; WG_KEY_LEN]),
+                WgDeviceAttrs::ListenPort(51820),
+                WgDeviceAttrs::Flags(WGDEVICE_F_REPLACE_PEERS),
+                WgDeviceAttrs::Peers(vec![WgPeer(vec![
+                    WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN]),
+                    WgPeerAttrs::Flags(WGPEER_F_REPLACE_ALLOWEDIPS),
+                    WgPeerAttrs::PersistentKeepalive(25),
+                    WgPeerAttrs::AllowedIps(vec!["10.0.0.0/24"
+                        .parse()
+                        .unwrap()]),
+                ])]),
+            ]
+        );
+        assert_eq!(
+            Device::from(&wg).peers[0].allowed_ips,
+            device.peers[0].allowed_ips
+        );
+    }
+
+    #[test]
+    fn test_device_emit() {
+        let builder = WgDeviceBuilder::new()
+            .ifname("wg0")
+            .listen_port(51820)
+            .replace_peers()
+            .peer(
+                WgPeerBuilder::new([0x01; WG_KEY_LEN])
+                    .allowed_ip("10.0.0.0/24".parse().unwrap())
+                    .build(),
+            );
+        let bytes = builder.clone().emit();
+        assert_eq!(
+            Wireguard::parse_nlas_at(&bytes).unwrap(),
+            builder.build().nlas
+        );
+    }
+
+    #[test]
+    fn test_protocol_version() {
+        let peer = WgPeerBuilder::new([0x01; WG_KEY_LEN])
+            .protocol_version(1)
+            .build();
+        assert_eq!(
+            peer.0,
+            vec![
+                WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN]),
+                WgPeerAttrs::ProtocolVersion(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_allowed_ip_unchecked() {
+        let addr = IpAddr::from([10, 0, 0, 0]);
+        assert_eq!(
+            WgPeerBuilder::new([0x01; WG_KEY_LEN])
+                .allowed_ip_unchecked(addr, 24)
+                .build(),
+            WgPeerBuilder::new([0x01; WG_KEY_LEN])
+                .allowed_ip(WgAllowedIp::new(addr, 24).unwrap())
+                .build()
+        );
+    }
+
+    #[test]
+    fn test_allowed_ip_sets_family() {
+        fn allowed_ips(peer: &WgPeer) -> Vec<WgAllowedIp> {
+            peer.iter()
+                .filter_map(|nla| match nla {
+                    WgPeerAttrs::AllowedIps(ips) => Some(ips.clone()),
+                    _ => None,
+                })
+                .flatten()
+                .collect()
+        }
+        let peer = WgPeerBuilder::new([0x01; WG_KEY_LEN])
+            .allowed_ip(WgAllowedIp(vec![
+                WgAllowedIpAttrs::IpAddr([10, 0, 0, 0].into()),
+                WgAllowedIpAttrs::Cidr(24),
+            ]))
+            .allowed_ip(WgAllowedIp(vec![
+                WgAllowedIpAttrs::Cidr(64),
+                WgAllowedIpAttrs::IpAddr("fd00::".parse().unwrap()),
+            ]))
+            .allowed_ip("10.0.1.0/24".parse().unwrap())
+            .allowed_ip_unchecked("fd00:1::".parse().unwrap(), 64)
+            .build();
+        let ips = allowed_ips(&peer);
+        let families: Vec<_> = ips
+            .iter()
+            .map(|ip| {
+                ip.iter()
+                    .filter_map(|nla| match nla {
+                        WgAllowedIpAttrs::Family(family) => Some(*family),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        assert_eq!(families, [[AF_INET], [AF_INET6], [AF_INET], [AF_INET6]]);
+        assert_eq!(ips[0], WgAllowedIp::new([10, 0, 0, 0].into(), 24).unwrap());
+
+        // An explicit family, even a wrong one, is kept.
+        let ip = WgAllowedIp::with_family([10, 0, 0, 0].into(), 24, AF_INET6);
+        let peer = WgPeerBuilder::new([0x01; WG_KEY_LEN])
+            .allowed_ip(ip.clone())
+            .build();
+        assert_eq!(allowed_ips(&peer), [ip]);
+    }
+
+    #[test]
+    fn test_flags() {
+        let wg = WgDeviceBuilder::new()
+            .ifname("wg0")
+            .replace_peers()
+            .fwmark(1)
+            .replace_peers()
+            .peer(WgPeerBuilder::new([0x01; WG_KEY_LEN]).build())
+            .build();
+        assert_eq!(
+            wg.nlas
+                .iter()
+                .filter(|nla| matches!(nla, WgDeviceAttrs::Flags(_)))
+                .collect::<Vec<_>>(),
+            vec![&WgDeviceAttrs::Flags(WGDEVICE_F_REPLACE_PEERS)]
+        );
+        // The flags come after the fwmark and before the peers.
+        assert_eq!(wg.nlas[2], WgDeviceAttrs::Flags(WGDEVICE_F_REPLACE_PEERS));
+
+        let flags = WgDeviceFlags::from_bits_retain(1 << 4);
+        let wg = WgDeviceBuilder::new()
+            .ifname("wg0")
+            .flags(flags)
+            .replace_peers()
+            .build();
+        assert_eq!(
+            wg.nlas[1],
+            WgDeviceAttrs::Flags(WGDEVICE_F_REPLACE_PEERS | 1 << 4)
+        );
+        let wg = WgDeviceBuilder::new()
+            .ifname("wg0")
+            .replace_peers()
+            .flags(WgDeviceFlags::empty())
+            .build();
+        assert_eq!(wg.nlas[1], WgDeviceAttrs::Flags(0));
+        assert!((flags | WgDeviceFlags::REPLACE_PEERS)
+            .contains(WgDeviceFlags::REPLACE_PEERS));
+        assert!(!flags.contains(WgDeviceFlags::REPLACE_PEERS));
+    }
+
+    #[test]
+    fn test_peer_flags() {
+        let flags = WgPeerFlags::REPLACE_ALLOWEDIPS | WgPeerFlags::UPDATE_ONLY;
+        let peer = WgPeerBuilder::new([0x01; WG_KEY_LEN]).flags(flags).build();
+        assert_eq!(
+            peer.raw_flags(),
+            Some(WGPEER_F_REPLACE_ALLOWEDIPS | WGPEER_F_UPDATE_ONLY)
+        );
+        assert!(flags.contains(WgPeerFlags::UPDATE_ONLY));
+        assert!(!flags.contains(WgPeerFlags::REMOVE_ME));
+
+        let peer = WgPeerBuilder::new([0x01; WG_KEY_LEN])
+            .flags(WgPeerFlags::REMOVE_ME)
+            .flags(WgPeerFlags::from_bits_retain(1 << 4))
+            .build();
+        assert_eq!(peer.raw_flags(), Some(1 << 4));
+    }
+
+    #[test]
+    fn test_allowed_cidrs() {
+        let peer = WgPeerBuilder::new([0x01; WG_KEY_LEN])
+            .allowed_cidrs(vec!["10.0.0.0/24", "fd00::/64"])
+            .unwrap()
+            .build();
+        assert_eq!(
+            peer,
+            WgPeerBuilder::new([0x01; WG_KEY_LEN])
+                .allowed_ip_unchecked([10, 0, 0, 0].into(), 24)
+                .allowed_ip_unchecked("fd00::".parse().unwrap(), 64)
+                .build()
+        );
+        assert_eq!(
+            WgPeerBuilder::new([0x01; WG_KEY_LEN])
+                .allowed_cidrs(vec!["10.0.0.0/24", "fd00::/129"])
+                .unwrap_err(),
+            WgError::InvalidCidr {
+                addr: "fd00::".parse().unwrap(),
+                cidr: 129
+            }
+        );
+    }
+
+    #[test]
+    fn test_key_setters_accept_arrays_keys_and_references() {
+        let bytes: &[u8; WG_KEY_LEN] = &[0x03; WG_KEY_LEN];
+        let peers = vec![
+            WgPeerBuilder::new([0x01; WG_KEY_LEN]).build(),
+            WgPeerBuilder::new(WgKey::new([0x02; WG_KEY_LEN]))
+                .preshared_key(bytes)
+                .build(),
+        ];
+        let wg = WgDeviceBuilder::new()
+            .ifname("wg0")
+            .private_key(bytes)
+            .peers(peers)
+            .build();
+        assert_eq!(
+            wg.nlas,
+            vec![
+                WgDeviceAttrs::IfName("wg0".to_string()),
+                WgDeviceAttrs::PrivateKey([0x03; WG_KEY_LEN]),
+                WgDeviceAttrs::Peers(vec![
+                    WgPeer(vec![WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN])]),
+                    WgPeer(vec![
+                        WgPeerAttrs::PublicKey([0x02; WG_KEY_LEN]),
+                        WgPeerAttrs::PresharedKey([0x03; WG_KEY_LEN]),
+                    ]),
+                ]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_peers_continuation() {
+        let peers = vec![
+            WgPeerBuilder::new([0x01; WG_KEY_LEN]).build(),
+            WgPeerBuilder::new([0x02; WG_KEY_LEN]).build(),
+        ];
+        let wg = Wireguard::peers_continuation("wg0", peers.clone());
+        assert_eq!(wg.cmd, WireguardCmd::SetDevice);
+        assert_eq!(
+            wg.nlas,
+            vec![
+                WgDeviceAttrs::IfName("wg0".to_string()),
+                WgDeviceAttrs::Peers(peers.clone()),
+            ]
+        );
+        let wg = Wireguard::peers_continuation(3, peers.clone());
+        assert_eq!(
+            wg.nlas,
+            vec![WgDeviceAttrs::IfIndex(3), WgDeviceAttrs::Peers(peers)]
+        );
+        assert_eq!(wg.validate_set(), Ok(()));
+    }
+
+    #[test]
+    fn test_remove_peers() {
+        let keys = vec![[0x01; WG_KEY_LEN], [0x02; WG_KEY_LEN]];
+        let wg = Wireguard::remove_peers("wg0", keys.clone());
+        assert_eq!(wg.cmd, WireguardCmd::SetDevice);
+        assert_eq!(wg.nlas[0], WgDeviceAttrs::IfName("wg0".to_string()));
+        let peers: Vec<_> = wg.peers().collect();
+        assert_eq!(peers.len(), keys.len());
+        for (peer, key) in peers.iter().zip(&keys) {
+            assert_eq!(peer.public_key(), Some(key));
+            assert_eq!(peer.raw_flags(), Some(WGPEER_F_REMOVE_ME));
+        }
+        assert_eq!(wg.validate_set(), Ok(()));
+    }
+
+    #[test]
+    fn test_endpoint_str() {
+        let peer = WgPeerBuilder::new([0x01; WG_KEY_LEN]);
+        let built = peer
+            .clone()
+            .endpoint_str("192.168.1.1:51820")
+            .unwrap()
+            .build();
+        assert_eq!(
+            built.endpoint(),
+            Some(&"192.168.1.1:51820".parse().unwrap())
+        );
+        let built = peer.clone().endpoint_str("[fd00::1]:51820").unwrap();
+        assert_eq!(built.unresolved_endpoint(), None);
+        assert_eq!(
+            built.build().endpoint(),
+            Some(&"[fd00::1]:51820".parse().unwrap())
+        );
+
+        let deferred =
+            peer.clone().endpoint_str("vpn.example.com:51820").unwrap();
+        assert_eq!(
+            deferred.unresolved_endpoint(),
+            Some(("vpn.example.com", 51820))
+        );
+        assert_eq!(deferred.clone().build().endpoint(), None);
+        let resolved = deferred.endpoint("203.0.113.1:51820".parse().unwrap());
+        assert_eq!(resolved.unresolved_endpoint(), None);
+
+        for malformed in [
+            "",
+            "192.168.1.1",
+            "192.168.1.1:",
+            "192.168.1.1:65536",
+            ":51820",
+            "vpn.example.com",
+            "vpn example.com:51820",
+            "fd00::1:51820x",
+            "[fd00::1]:51820:1",
+        ] {
+            assert_eq!(
+                peer.clone().endpoint_str(malformed).unwrap_err(),
+                WgError::InvalidSocketAddr(malformed.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_full_tunnel() {
+        let endpoint =
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 10, 10, 1)), 51820);
+        // The attributes of the set_wireguard example.
+        let nlas = vec![
+            WgDeviceAttrs::IfName("wg0".to_string()),
+            WgDeviceAttrs::PrivateKey([0xaa; WG_KEY_LEN]),
+            WgDeviceAttrs::ListenPort(51820),
+            WgDeviceAttrs::Fwmark(0),
+            WgDeviceAttrs::Peers(vec![WgPeer(vec![
+                WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN]),
+                WgPeerAttrs::Endpoint(endpoint),
+                WgPeerAttrs::AllowedIps(vec![
+                    WgAllowedIp(vec![
+                        WgAllowedIpAttrs::Family(AF_INET),
+                        WgAllowedIpAttrs::IpAddr("0.0.0.0".parse().unwrap()),
+                        WgAllowedIpAttrs::Cidr(0),
+                    ]),
+                    WgAllowedIp(vec![
+                        WgAllowedIpAttrs::Family(AF_INET6),
+                        WgAllowedIpAttrs::IpAddr("::".parse().unwrap()),
+                        WgAllowedIpAttrs::Cidr(0),
+                    ]),
+                ]),
+            ])]),
+        ];
+        assert_eq!(
+            Wireguard::full_tunnel(
+                "wg0",
+                [0xaa; WG_KEY_LEN],
+                51820,
+                [0x01; WG_KEY_LEN],
+                endpoint
+            ),
+            Wireguard {
+                cmd: WireguardCmd::SetDevice,
+                nlas,
+            }
+        );
+    }
+}
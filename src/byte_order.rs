@@ -0,0 +1,204 @@
+// SPDX-License-Identifier: MIT
+
+use byteorder::ByteOrder;
+use netlink_packet_utils::{
+    nla::{Nla, NLA_F_NESTED, NLA_HEADER_SIZE},
+    Emitable,
+};
+
+use crate::{
+    nlas::{WgAllowedIp, WgAllowedIpAttrs, WgDeviceAttrs, WgPeer, WgPeerAttrs},
+    raw::{emit_ip, emit_socket_addr_in, emit_timespec_in},
+    Wireguard,
+};
+
+impl Wireguard {
+    /// Emit the attributes of the message like [`Emitable::emit`], with the
+    /// attribute headers and the scalar attributes written in the byte
+    /// order `B` rather than in the byte order of the host. The endpoint
+    /// port is written in network byte order whatever `B` is, as the
+    /// kernel expects. The buffer must be at least
+    /// [`Emitable::buffer_len`] bytes long.
+    ///
+    /// This is only meant to build messages for a host of another
+    /// architecture, e.g. test vectors for a big endian target generated
+    /// on a little endian machine. Netlink is always in host byte order:
+    /// a message emitted in a foreign byte order is garbage to the local
+    /// kernel, and this crate can't parse it back on the same host. Use
+    /// [`Emitable::emit`] for anything sent to the local kernel.
+    pub fn emit_with_byte_order<B: ByteOrder>(&self, buffer: &mut [u8]) {
+        let mut len = 0;
+        for nla in &self.nlas {
+            len += emit_nla::<B, _>(nla, &mut buffer[len..], |buf| {
+                emit_device_attr::<B>(nla, buf)
+            });
+        }
+    }
+}
+
+/// Emit the header of `nla` in the byte order `B`, its value with
+/// `emit_value` and the padding. Returns the number of bytes written.
+fn emit_nla<B: ByteOrder, T: Nla>(
+    nla: &T,
+    buffer: &mut [u8],
+    emit_value: impl FnOnce(&mut [u8]),
+) -> usize {
+    let value_len = nla.value_len();
+    let mut kind = nla.kind();
+    if nla.is_nested() {
+        kind |= NLA_F_NESTED;
+    }
+    B::write_u16(&mut buffer[..2], (NLA_HEADER_SIZE + value_len) as u16);
+    B::write_u16(&mut buffer[2..4], kind);
+    emit_value(&mut buffer[NLA_HEADER_SIZE..NLA_HEADER_SIZE + value_len]);
+    let len = nla.buffer_len();
+    buffer[NLA_HEADER_SIZE + value_len..len].fill(0);
+    len
+}
+
+fn emit_device_attr<B: ByteOrder>(nla: &WgDeviceAttrs, buffer: &mut [u8]) {
+    match nla {
+        WgDeviceAttrs::IfIndex(v) => B::write_u32(buffer, *v),
+        WgDeviceAttrs::ListenPort(v) => B::write_u16(buffer, *v),
+        WgDeviceAttrs::Fwmark(v) => B::write_u32(buffer, *v),
+        WgDeviceAttrs::Flags(v) => B::write_u32(buffer, *v),
+        WgDeviceAttrs::Peers(peers) => {
+            let mut len = 0;
+            for peer in peers {
+                len += emit_nla::<B, _>(peer, &mut buffer[len..], |buf| {
+                    emit_peer::<B>(peer, buf)
+                });
+            }
+        }
+        // Strings and keys have no byte order.
+        WgDeviceAttrs::Unspec(_)
+        | WgDeviceAttrs::IfName(_)
+        | WgDeviceAttrs::PrivateKey(_)
+        | WgDeviceAttrs::PublicKey(_) => nla.emit_value(buffer),
+    }
+}
+
+fn emit_peer<B: ByteOrder>(peer: &WgPeer, buffer: &mut [u8]) {
+    let mut len = 0;
+    for nla in peer.iter() {
+        len += emit_nla::<B, _>(nla, &mut buffer[len..], |buf| {
+            emit_peer_attr::<B>(nla, buf)
+        });
+    }
+}
+
+fn emit_peer_attr<B: ByteOrder>(nla: &WgPeerAttrs, buffer: &mut [u8]) {
+    match nla {
+        WgPeerAttrs::Endpoint(v) => emit_socket_addr_in::<B>(v, buffer),
+        WgPeerAttrs::PersistentKeepalive(v) => B::write_u16(buffer, *v),
+        WgPeerAttrs::LastHandshake(v) => emit_timespec_in::<B>(v, buffer),
+        WgPeerAttrs::RxBytes(v) => B::write_u64(buffer, *v),
+        WgPeerAttrs::TxBytes(v) => B::write_u64(buffer, *v),
+        WgPeerAttrs::ProtocolVersion(v) => B::write_u32(buffer, *v),
+        WgPeerAttrs::Flags(v) => B::write_u32(buffer, *v),
+        WgPeerAttrs::AllowedIps(ips) => {
+            let mut len = 0;
+            for ip in ips {
+                len += emit_nla::<B, _>(ip, &mut buffer[len..], |buf| {
+                    emit_allowed_ip::<B>(ip, buf)
+                });
+            }
+        }
+        WgPeerAttrs::Unspec(_)
+        | WgPeerAttrs::PublicKey(_)
+        | WgPeerAttrs::PresharedKey(_) => nla.emit_value(buffer),
+    }
+}
+
+fn emit_allowed_ip<B: ByteOrder>(ip: &WgAllowedIp, buffer: &mut [u8]) {
+    let mut len = 0;
+    for nla in ip.iter() {
+        len += emit_nla::<B, _>(nla, &mut buffer[len..], |buf| match nla {
+            WgAllowedIpAttrs::Family(v) => B::write_u16(buf, *v),
+            WgAllowedIpAttrs::IpAddr(v) => emit_ip(v, buf),
+            WgAllowedIpAttrs::Cidr(_) | WgAllowedIpAttrs::Unspec(_) => {
+                nla.emit_value(buf)
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        net::SocketAddr,
+        time::{Duration, SystemTime},
+    };
+
+    use byteorder::{BigEndian, LittleEndian, NativeEndian};
+
+    use super::*;
+    use crate::{constants::WG_KEY_LEN, WgDeviceBuilder, WgPeerBuilder};
+
+    fn emit_in<B: ByteOrder>(wg: &Wireguard) -> Vec<u8> {
+        let mut buf = vec![0xff; wg.buffer_len()];
+        wg.emit_with_byte_order::<B>(&mut buf);
+        buf
+    }
+
+    #[test]
+    fn test_emit_native_byte_order_matches_emit() {
+        let mut wg = WgDeviceBuilder::new()
+            .ifname("wg0")
+            .private_key([1; WG_KEY_LEN])
+            .listen_port(51820)
+            .fwmark(0x1234)
+            .replace_peers()
+            .peer(
+                WgPeerBuilder::new([2; WG_KEY_LEN])
+                    .endpoint("[fe80::1%3]:51820".parse().unwrap())
+                    .persistent_keepalive(25)
+                    .allowed_ip("fd00::/64".parse().unwrap())
+                    .allowed_ip("10.0.0.0/8".parse().unwrap())
+                    .build(),
+            )
+            .build();
+        if let Some(WgDeviceAttrs::Peers(peers)) = wg.nlas.last_mut() {
+            peers[0].0.push(WgPeerAttrs::LastHandshake(
+                SystemTime::UNIX_EPOCH + Duration::new(1_600_000_000, 42),
+            ));
+            peers[0].0.push(WgPeerAttrs::RxBytes(1 << 40));
+        }
+        let mut expected = vec![0; wg.buffer_len()];
+        wg.emit(&mut expected);
+        assert_eq!(emit_in::<NativeEndian>(&wg), expected);
+    }
+
+    #[test]
+    fn test_emit_little_and_big_endian() {
+        let wg = WgDeviceBuilder::new()
+            .ifindex(3)
+            .listen_port(51820)
+            .peer(WgPeer(vec![WgPeerAttrs::Endpoint(SocketAddr::from((
+                [1, 2, 3, 4],
+                51820,
+            )))]))
+            .build();
+
+        let endpoint = [1, 2, 3, 4, 0, 0, 0, 0, 0, 0, 0, 0];
+        let mut le = vec![
+            8, 0, 1, 0, 3, 0, 0, 0, // IfIndex
+            6, 0, 6, 0, 0x6c, 0xca, 0, 0, // ListenPort
+            28, 0, 8, 0x80, // Peers
+            24, 0, 0, 0x80, // peer
+            20, 0, 4, 0, 2, 0, 0xca, 0x6c, // Endpoint, port in BE
+        ];
+        le.extend(endpoint);
+        let mut be = vec![
+            0, 8, 0, 1, 0, 0, 0, 3, // IfIndex
+            0, 6, 0, 6, 0xca, 0x6c, 0, 0, // ListenPort
+            0, 28, 0x80, 8, // Peers
+            0, 24, 0x80, 0, // peer
+            0, 20, 0, 4, 0, 2, 0xca, 0x6c, // Endpoint, port in BE
+        ];
+        be.extend(endpoint);
+
+        assert_eq!(emit_in::<LittleEndian>(&wg), le);
+        assert_eq!(emit_in::<BigEndian>(&wg), be);
+    }
+}
@@ -0,0 +1,658 @@
+// SPDX-License-Identifier: MIT
+
+use std::net::IpAddr;
+
+use netlink_packet_utils::Emitable;
+#[cfg(feature = "crypto")]
+use sha2::{Digest, Sha256};
+
+use crate::{
+    constants::WG_KEY_LEN,
+    nlas::{
+        EmitToVec, WgAllowedIp, WgAllowedIpAttrs, WgDeviceAttrs, WgPeer,
+        WgPeerAttrs,
+    },
+    Wireguard,
+};
+
+#[cfg(not(feature = "crypto"))]
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+#[cfg(not(feature = "crypto"))]
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+impl Wireguard {
+    /// Reorder the device attributes the way `wg setconf` emits them:
+    /// identity (`IfIndex` then `IfName`), `PrivateKey`, `PublicKey`,
+    /// `ListenPort`, `Fwmark`, `Flags` and finally `Peers`. `Unspec`
+    /// attributes go last. Attributes of the same kind keep their relative
+    /// order.
+    ///
+    /// The kernel accepts any order, this only keeps messages close to
+    /// the ones `wg` sends, which are the most exercised.
+    pub fn sort_canonical(&mut self) {
+        self.nlas.sort_by_key(device_attr_rank);
+    }
+
+    /// A copy of the message for audit logs: without its secrets (see
+    /// [`Wireguard::clone_without_secrets`]) and in the order of
+    /// [`Wireguard::canonicalize`]. Messages describing the same
+    /// configuration in different orders give the same copy, and so the
+    /// same `Debug` output.
+    pub fn sanitize_for_logging(&self) -> Wireguard {
+        let mut wg = self.clone_without_secrets();
+        wg.canonicalize();
+        wg
+    }
+
+    /// Put the whole message in a deterministic order, e.g. before
+    /// [`Wireguard::to_wg_conf`] or before comparing the output with a
+    /// golden file:
+    ///
+    /// - the device attributes as in [`Wireguard::sort_canonical`],
+    /// - the peers of each `WGDEVICE_A_PEERS` by public key, the peers
+    ///   without one last,
+    /// - the attributes of each peer in the order of [`WgPeerBuilder`]
+    ///   (`PublicKey`, `Flags`, `PresharedKey`, `Endpoint`,
+    ///   `PersistentKeepalive`, then the statistics, `ProtocolVersion` and
+    ///   `AllowedIps`),
+    /// - the allowed IPs of each `WGPEER_A_ALLOWEDIPS` by address, IPv4
+    ///   first, then by prefix length, and their attributes as `Family`,
+    ///   `IpAddr` and `Cidr`.
+    ///
+    /// Nothing is merged or removed: attributes that compare equal keep
+    /// their relative order, and peers and allowed IPs stay in their
+    /// attribute.
+    ///
+    /// [`WgPeerBuilder`]: crate::WgPeerBuilder
+    pub fn canonicalize(&mut self) {
+        self.sort_canonical();
+        for peers in self.nlas.iter_mut().filter_map(|nla| match nla {
+            WgDeviceAttrs::Peers(peers) => Some(peers),
+            _ => None,
+        }) {
+            sort_peers(peers);
+            for peer in peers.iter_mut() {
+                peer.0.sort_by_key(peer_attr_rank);
+                for nla in peer.0.iter_mut() {
+                    if let WgPeerAttrs::AllowedIps(ips) = nla {
+                        ips.sort_by_key(addr_and_cidr_last);
+                        for ip in ips.iter_mut() {
+                            ip.0.sort_by_key(allowed_ip_attr_rank);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// A fingerprint of the configuration of the device, to tell whether
+    /// it changed between two dumps.
+    ///
+    /// Only the attributes [`Wireguard::into_set_template`] keeps are
+    /// hashed, plus the device public key, after putting the peers of
+    /// every `WGDEVICE_A_PEERS` together and ordering everything as
+    /// [`Wireguard::canonicalize`] does: the peer statistics, the order of
+    /// the peers and allowed IPs and how the kernel split the dump don't
+    /// change the fingerprint.
+    ///
+    /// With the `crypto` feature, the fingerprint is a SHA-256 hash, and
+    /// the private key and the preshared keys are hashed through their
+    /// own SHA-256 digest, so that a new key changes the fingerprint
+    /// without it revealing the key.
+    ///
+    /// Without it, the fingerprint is a single 64-bit FNV-1a hash, in the
+    /// first 8 bytes, the others being zero. It isn't a cryptographic hash
+    /// and must not be relied on against an attacker. The private key and
+    /// the preshared keys are left out: a new private key only shows
+    /// through the public key of a dump, and a new preshared key doesn't
+    /// change the fingerprint.
+    ///
+    /// Either way the fingerprint is stable across runs and architectures
+    /// with the same endianness.
+    pub fn config_fingerprint(&self) -> [u8; 32] {
+        let public_key = self
+            .nlas
+            .iter()
+            .find(|nla| matches!(nla, WgDeviceAttrs::PublicKey(_)))
+            .cloned();
+        let template = self.clone().into_set_template();
+        let mut wg = template.clone().without_peers();
+        wg.nlas.extend(public_key);
+        wg.extend(template.into_peers());
+        mask_secrets(&mut wg);
+        wg.canonicalize();
+        let mut buf = vec![0; wg.buffer_len()];
+        wg.emit(&mut buf);
+        digest(&buf)
+    }
+
+    /// Whether both messages carry the same command and the same
+    /// attributes, regardless of their order.
+    ///
+    /// The device attributes are compared as multisets, and so are the
+    /// peers, matched by public key, and the attributes and allowed IPs of
+    /// each peer. The peers of several `WGDEVICE_A_PEERS` attributes are
+    /// put together, and so are the allowed IPs of several
+    /// `WGPEER_A_ALLOWEDIPS` attributes. Unlike
+    /// [`Wireguard::config_fingerprint`], the peer statistics are
+    /// compared.
+    pub fn equivalent(&self, other: &Wireguard) -> bool {
+        self.cmd == other.cmd && unordered(self) == unordered(other)
+    }
+}
+
+/// The device attributes and the peers of `wg` as sorted lists, the peers
+/// sorted by public key. Attributes are compared through their emitted
+/// bytes, which identify them without requiring `Ord`.
+type Unordered = (Vec<Vec<u8>>, Vec<(Option<[u8; WG_KEY_LEN]>, Vec<Vec<u8>>)>);
+
+fn unordered(wg: &Wireguard) -> Unordered {
+    let mut attrs = Vec::new();
+    let mut peers = Vec::new();
+    for nla in &wg.nlas {
+        match nla {
+            WgDeviceAttrs::Peers(group) => {
+                peers.extend(group.iter().map(unordered_peer))
+            }
+            nla => attrs.push(nla.emit_to_vec()),
+        }
+    }
+    attrs.sort_unstable();
+    peers.sort_unstable();
+    (attrs, peers)
+}
+
+fn unordered_peer(peer: &WgPeer) -> (Option<[u8; WG_KEY_LEN]>, Vec<Vec<u8>>) {
+    let mut attrs = Vec::new();
+    for nla in peer.iter() {
+        match nla {
+            WgPeerAttrs::AllowedIps(ips) => {
+                attrs.extend(ips.iter().map(EmitToVec::emit_to_vec))
+            }
+            nla => attrs.push(nla.emit_to_vec()),
+        }
+    }
+    attrs.sort_unstable();
+    (peer.public_key().copied(), attrs)
+}
+
+// Replace the secret keys with their digest.
+#[cfg(feature = "crypto")]
+fn mask_secrets(wg: &mut Wireguard) {
+    let sha256 = |key: &[u8; WG_KEY_LEN]| -> [u8; WG_KEY_LEN] {
+        Sha256::digest(key).into()
+    };
+    for nla in wg.nlas.iter_mut() {
+        if let WgDeviceAttrs::PrivateKey(key) = nla {
+            *key = sha256(key);
+        }
+    }
+    for nla in wg.peers_iter_mut().flat_map(|peer| peer.0.iter_mut()) {
+        if let WgPeerAttrs::PresharedKey(key) = nla {
+            *key = sha256(key);
+        }
+    }
+}
+
+// Without a one-way hash, leave the secret keys out.
+#[cfg(not(feature = "crypto"))]
+fn mask_secrets(wg: &mut Wireguard) {
+    wg.nlas
+        .retain(|nla| !matches!(nla, WgDeviceAttrs::PrivateKey(_)));
+    for peer in wg.peers_iter_mut() {
+        peer.0
+            .retain(|nla| !matches!(nla, WgPeerAttrs::PresharedKey(_)));
+    }
+}
+
+#[cfg(feature = "crypto")]
+fn digest(buf: &[u8]) -> [u8; 32] {
+    Sha256::digest(buf).into()
+}
+
+#[cfg(not(feature = "crypto"))]
+fn digest(buf: &[u8]) -> [u8; 32] {
+    let hash = buf.iter().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME)
+    });
+    let mut fingerprint = [0; 32];
+    fingerprint[..8].copy_from_slice(&hash.to_be_bytes());
+    fingerprint
+}
+
+// Peers without a public key go last.
+fn sort_peers(peers: &mut [WgPeer]) {
+    peers.sort_by_key(|peer| {
+        (peer.public_key().is_none(), peer.public_key().copied())
+    });
+}
+
+fn device_attr_rank(nla: &WgDeviceAttrs) -> u8 {
+    match nla {
+        WgDeviceAttrs::IfIndex(_) => 0,
+        WgDeviceAttrs::IfName(_) => 1,
+        WgDeviceAttrs::PrivateKey(_) => 2,
+        WgDeviceAttrs::PublicKey(_) => 3,
+        WgDeviceAttrs::ListenPort(_) => 4,
+        WgDeviceAttrs::Fwmark(_) => 5,
+        WgDeviceAttrs::Flags(_) => 6,
+        WgDeviceAttrs::Peers(_) => 7,
+        WgDeviceAttrs::Unspec(_) => 8,
+    }
+}
+
+fn peer_attr_rank(nla: &WgPeerAttrs) -> u8 {
+    match nla {
+        WgPeerAttrs::PublicKey(_) => 0,
+        WgPeerAttrs::Flags(_) => 1,
+        WgPeerAttrs::PresharedKey(_) => 2,
+        WgPeerAttrs::Endpoint(_) => 3,
+        WgPeerAttrs::PersistentKeepalive(_) => 4,
+        WgPeerAttrs::LastHandshake(_) => 5,
+        WgPeerAttrs::RxBytes(_) => 6,
+        WgPeerAttrs::TxBytes(_) => 7,
+        WgPeerAttrs::ProtocolVersion(_) => 8,
+        WgPeerAttrs::AllowedIps(_) => 9,
+        WgPeerAttrs::Unspec(_) => 10,
+    }
+}
+
+fn allowed_ip_attr_rank(nla: &WgAllowedIpAttrs) -> u8 {
+    match nla {
+        WgAllowedIpAttrs::Family(_) => 0,
+        WgAllowedIpAttrs::IpAddr(_) => 1,
+        WgAllowedIpAttrs::Cidr(_) => 2,
+        WgAllowedIpAttrs::Unspec(_) => 3,
+    }
+}
+
+// Allowed IPs without an address or a prefix length go last.
+fn addr_and_cidr_last(ip: &WgAllowedIp) -> (bool, Option<(IpAddr, u8)>) {
+    let key = ip.addr_and_cidr();
+    (key.is_none(), key)
+}
+
+#[cfg(test)]
+mod test {
+    use netlink_packet_utils::nla::Nla;
+
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+    use crate::{
+        constants::*,
+        nlas::{WgPeer, WgPeerAttrs},
+        WireguardCmd,
+    };
+
+    #[test]
+    fn test_sort_canonical() {
+        let mut wg = Wireguard {
+            cmd: WireguardCmd::SetDevice,
+            nlas: vec![
+                WgDeviceAttrs::Peers(vec![]),
+                WgDeviceAttrs::Flags(WGDEVICE_F_REPLACE_PEERS),
+                WgDeviceAttrs::Fwmark(1),
+                WgDeviceAttrs::ListenPort(51820),
+                WgDeviceAttrs::PrivateKey([0xaa; WG_KEY_LEN]),
+                WgDeviceAttrs::IfName("wg0".to_string()),
+            ],
+        };
+        wg.sort_canonical();
+        let kinds: Vec<u16> = wg.nlas.iter().map(|nla| nla.kind()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                WGDEVICE_A_IFNAME,
+                WGDEVICE_A_PRIVATE_KEY,
+                WGDEVICE_A_LISTEN_PORT,
+                WGDEVICE_A_FWMARK,
+                WGDEVICE_A_FLAGS,
+                WGDEVICE_A_PEERS,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let nlas = vec![
+            WgDeviceAttrs::Flags(WGDEVICE_F_REPLACE_PEERS),
+            WgDeviceAttrs::ListenPort(51820),
+            WgDeviceAttrs::IfName("wg0".to_string()),
+            WgDeviceAttrs::Peers(vec![
+                WgPeer(vec![
+                    WgPeerAttrs::AllowedIps(vec![
+                        "fd00::/64".parse().unwrap(),
+                        "10.0.1.0/24".parse().unwrap(),
+                        "10.0.0.0/24".parse().unwrap(),
+                    ]),
+                    WgPeerAttrs::PersistentKeepalive(25),
+                    WgPeerAttrs::PublicKey([0x02; WG_KEY_LEN]),
+                ]),
+                WgPeer(vec![
+                    WgPeerAttrs::Endpoint("192.0.2.1:51820".parse().unwrap()),
+                    WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN]),
+                    WgPeerAttrs::AllowedIps(vec![WgAllowedIp(vec![
+                        WgAllowedIpAttrs::Cidr(32),
+                        WgAllowedIpAttrs::IpAddr([10, 0, 2, 1].into()),
+                        WgAllowedIpAttrs::Family(AF_INET),
+                    ])]),
+                ]),
+                WgPeer(vec![WgPeerAttrs::PersistentKeepalive(0)]),
+            ]),
+        ];
+        let mut expected = Wireguard {
+            cmd: WireguardCmd::SetDevice,
+            nlas,
+        };
+        expected.canonicalize();
+        assert_eq!(
+            expected.nlas[..3],
+            [
+                WgDeviceAttrs::IfName("wg0".to_string()),
+                WgDeviceAttrs::ListenPort(51820),
+                WgDeviceAttrs::Flags(WGDEVICE_F_REPLACE_PEERS),
+            ]
+        );
+        match &expected.nlas[3] {
+            WgDeviceAttrs::Peers(peers) => {
+                assert_eq!(
+                    peers[0].0,
+                    [
+                        WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN]),
+                        WgPeerAttrs::Endpoint(
+                            "192.0.2.1:51820".parse().unwrap()
+                        ),
+                        WgPeerAttrs::AllowedIps(vec!["10.0.2.1/32"
+                            .parse()
+                            .unwrap()]),
+                    ]
+                );
+                assert_eq!(
+                    peers[1].0,
+                    [
+                        WgPeerAttrs::PublicKey([0x02; WG_KEY_LEN]),
+                        WgPeerAttrs::PersistentKeepalive(25),
+                        WgPeerAttrs::AllowedIps(vec![
+                            "10.0.0.0/24".parse().unwrap(),
+                            "10.0.1.0/24".parse().unwrap(),
+                            "fd00::/64".parse().unwrap(),
+                        ]),
+                    ]
+                );
+                assert_eq!(peers[2].public_key(), None);
+            }
+            nla => panic!("unexpected attribute {:?}", nla),
+        }
+
+        // Every shuffle of the input gives the same message.
+        let emitted = |wg: &Wireguard| {
+            let mut buf = vec![0; wg.buffer_len()];
+            wg.emit(&mut buf);
+            buf
+        };
+        for shift in 0..4 {
+            let mut wg = expected.clone();
+            wg.nlas.rotate_left(shift);
+            for nla in wg.nlas.iter_mut() {
+                if let WgDeviceAttrs::Peers(peers) = nla {
+                    let len = peers.len();
+                    peers.rotate_right(shift % len);
+                    for peer in peers.iter_mut() {
+                        peer.0.reverse();
+                        for nla in peer.0.iter_mut() {
+                            if let WgPeerAttrs::AllowedIps(ips) = nla {
+                                let len = ips.len();
+                                ips.rotate_left(shift % len);
+                                for ip in ips.iter_mut() {
+                                    ip.0.rotate_right(shift % 3);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            wg.canonicalize();
+            assert_eq!(wg, expected);
+            assert_eq!(emitted(&wg), emitted(&expected));
+            assert_eq!(wg.to_wg_conf(), expected.to_wg_conf());
+        }
+    }
+
+    #[test]
+    fn test_sanitize_for_logging() {
+        let peer = |key| {
+            WgPeer(vec![
+                WgPeerAttrs::PublicKey([key; WG_KEY_LEN]),
+                WgPeerAttrs::PresharedKey([0xee; WG_KEY_LEN]),
+                WgPeerAttrs::PersistentKeepalive(25),
+            ])
+        };
+        let wg = Wireguard {
+            cmd: WireguardCmd::SetDevice,
+            nlas: vec![
+                WgDeviceAttrs::Peers(vec![peer(0x02), peer(0x01)]),
+                WgDeviceAttrs::ListenPort(51820),
+                WgDeviceAttrs::PrivateKey([0xaa; WG_KEY_LEN]),
+                WgDeviceAttrs::IfName("wg0".to_string()),
+            ],
+        };
+        let mut shuffled = peer(0x01);
+        shuffled.0.reverse();
+        let reordered = Wireguard {
+            cmd: WireguardCmd::SetDevice,
+            nlas: vec![
+                WgDeviceAttrs::IfName("wg0".to_string()),
+                WgDeviceAttrs::PrivateKey([0xaa; WG_KEY_LEN]),
+                WgDeviceAttrs::ListenPort(51820),
+                WgDeviceAttrs::Peers(vec![shuffled, peer(0x02)]),
+            ],
+        };
+        let sanitized = wg.sanitize_for_logging();
+        assert_eq!(sanitized, reordered.sanitize_for_logging());
+        assert_eq!(
+            format!("{:?}", sanitized),
+            format!("{:?}", reordered.sanitize_for_logging())
+        );
+        let strip_psk = |key| {
+            let mut peer = peer(key);
+            peer.0.remove(1);
+            peer
+        };
+        assert_eq!(
+            sanitized.nlas,
+            vec![
+                WgDeviceAttrs::IfName("wg0".to_string()),
+                WgDeviceAttrs::ListenPort(51820),
+                WgDeviceAttrs::Peers(vec![strip_psk(0x01), strip_psk(0x02)]),
+            ]
+        );
+        let debug = format!("{:?}", sanitized);
+        assert!(!debug.contains("PrivateKey"));
+        assert!(!debug.contains("PresharedKey"));
+    }
+
+    #[test]
+    fn test_equivalent() {
+        let peer = |key, ips: &[&str]| {
+            WgPeer(vec![
+                WgPeerAttrs::PublicKey([key; WG_KEY_LEN]),
+                WgPeerAttrs::PersistentKeepalive(25),
+                WgPeerAttrs::AllowedIps(
+                    ips.iter().map(|ip| ip.parse().unwrap()).collect(),
+                ),
+            ])
+        };
+        let wg = Wireguard {
+            cmd: WireguardCmd::SetDevice,
+            nlas: vec![
+                WgDeviceAttrs::IfName("wg0".to_string()),
+                WgDeviceAttrs::ListenPort(51820),
+                WgDeviceAttrs::Peers(vec![
+                    peer(0x01, &["10.0.0.0/24", "fd00::/64"]),
+                    peer(0x02, &["10.0.1.0/24"]),
+                ]),
+            ],
+        };
+        let mut second = peer(0x01, &["fd00::/64", "10.0.0.0/24"]);
+        second.0.reverse();
+        let reordered = Wireguard {
+            cmd: WireguardCmd::SetDevice,
+            nlas: vec![
+                WgDeviceAttrs::Peers(vec![peer(0x02, &["10.0.1.0/24"])]),
+                WgDeviceAttrs::ListenPort(51820),
+                WgDeviceAttrs::Peers(vec![second]),
+                WgDeviceAttrs::IfName("wg0".to_string()),
+            ],
+        };
+        assert_ne!(wg, reordered);
+        assert!(wg.equivalent(&reordered));
+        assert!(reordered.equivalent(&wg));
+
+        let mut changed = reordered.clone();
+        changed.nlas[1] = WgDeviceAttrs::ListenPort(51821);
+        assert!(!wg.equivalent(&changed));
+        let mut changed = reordered.clone();
+        changed.nlas[0] =
+            WgDeviceAttrs::Peers(vec![peer(0x02, &["10.0.2.0/24"])]);
+        assert!(!wg.equivalent(&changed));
+        let mut changed = reordered;
+        changed.cmd = WireguardCmd::GetDevice;
+        assert!(!wg.equivalent(&changed));
+    }
+
+    #[test]
+    fn test_config_fingerprint_ignores_stats() {
+        let dump = |rx_bytes, handshake| Wireguard {
+            cmd: WireguardCmd::GetDevice,
+            nlas: vec![
+                WgDeviceAttrs::IfIndex(3),
+                WgDeviceAttrs::IfName("wg0".to_string()),
+                WgDeviceAttrs::PrivateKey([0xaa; WG_KEY_LEN]),
+                WgDeviceAttrs::ListenPort(51820),
+                WgDeviceAttrs::Peers(vec![WgPeer(vec![
+                    WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN]),
+                    WgPeerAttrs::PresharedKey([0xbb; WG_KEY_LEN]),
+                    WgPeerAttrs::LastHandshake(
+                        SystemTime::UNIX_EPOCH + Duration::from_secs(handshake),
+                    ),
+                    WgPeerAttrs::RxBytes(rx_bytes),
+                    WgPeerAttrs::TxBytes(1024),
+                    WgPeerAttrs::AllowedIps(vec!["10.0.0.0/24"
+                        .parse()
+                        .unwrap()]),
+                ])]),
+            ],
+        };
+        let fingerprint = dump(0, 1_700_000_000).config_fingerprint();
+        assert_eq!(dump(4096, 1_700_000_120).config_fingerprint(), fingerprint);
+        assert_ne!(fingerprint, [0; 32]);
+
+        let mut changed = dump(0, 1_700_000_000);
+        changed.nlas[3] = WgDeviceAttrs::ListenPort(51821);
+        assert_ne!(changed.config_fingerprint(), fingerprint);
+        let mut changed = dump(0, 1_700_000_000);
+        if let WgDeviceAttrs::Peers(peers) = &mut changed.nlas[4] {
+            peers[0].0.push(WgPeerAttrs::PersistentKeepalive(25));
+        }
+        assert_ne!(changed.config_fingerprint(), fingerprint);
+    }
+
+    fn secrets_dump(
+        private_key: [u8; WG_KEY_LEN],
+        preshared_key: [u8; WG_KEY_LEN],
+    ) -> Wireguard {
+        Wireguard {
+            cmd: WireguardCmd::GetDevice,
+            nlas: vec![
+                WgDeviceAttrs::IfName("wg0".to_string()),
+                WgDeviceAttrs::PrivateKey(private_key),
+                WgDeviceAttrs::Peers(vec![WgPeer(vec![
+                    WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN]),
+                    WgPeerAttrs::PresharedKey(preshared_key),
+                ])]),
+            ],
+        }
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_config_fingerprint_secrets() {
+        let wg = secrets_dump([0xaa; WG_KEY_LEN], [0xbb; WG_KEY_LEN]);
+        let fingerprint = wg.config_fingerprint();
+        assert_eq!(wg.clone().config_fingerprint(), fingerprint);
+        let psk_rotated = secrets_dump([0xaa; WG_KEY_LEN], [0xbc; WG_KEY_LEN]);
+        assert_ne!(psk_rotated.config_fingerprint(), fingerprint);
+        let key_rotated = secrets_dump([0xab; WG_KEY_LEN], [0xbb; WG_KEY_LEN]);
+        assert_ne!(key_rotated.config_fingerprint(), fingerprint);
+
+        // The hashed bytes hold the digests, not the keys.
+        let mut masked = wg.into_set_template();
+        mask_secrets(&mut masked);
+        let mut buf = vec![0; masked.buffer_len()];
+        masked.emit(&mut buf);
+        assert!(!buf.windows(WG_KEY_LEN).any(|w| w == [0xaa; WG_KEY_LEN]));
+        assert!(!buf.windows(WG_KEY_LEN).any(|w| w == [0xbb; WG_KEY_LEN]));
+    }
+
+    #[cfg(not(feature = "crypto"))]
+    #[test]
+    fn test_config_fingerprint_ignores_secrets() {
+        let fingerprint = secrets_dump([0xaa; WG_KEY_LEN], [0xbb; WG_KEY_LEN])
+            .config_fingerprint();
+        assert_eq!(&fingerprint[8..], [0; 24]);
+        let mut changed = secrets_dump([0xab; WG_KEY_LEN], [0xbc; WG_KEY_LEN]);
+        assert_eq!(changed.config_fingerprint(), fingerprint);
+
+        changed
+            .nlas
+            .push(WgDeviceAttrs::PublicKey([0xcd; WG_KEY_LEN]));
+        assert_ne!(changed.config_fingerprint(), fingerprint);
+    }
+
+    #[test]
+    fn test_config_fingerprint_ignores_order() {
+        let peer = |key, ips: &[&str]| {
+            WgPeer(vec![
+                WgPeerAttrs::PublicKey([key; WG_KEY_LEN]),
+                WgPeerAttrs::AllowedIps(
+                    ips.iter().map(|ip| ip.parse().unwrap()).collect(),
+                ),
+                WgPeerAttrs::PersistentKeepalive(25),
+            ])
+        };
+        let wg = Wireguard {
+            cmd: WireguardCmd::GetDevice,
+            nlas: vec![
+                WgDeviceAttrs::IfName("wg0".to_string()),
+                WgDeviceAttrs::ListenPort(51820),
+                WgDeviceAttrs::Peers(vec![
+                    peer(0x01, &["10.0.1.0/24", "fd00:1::/64"]),
+                    peer(0x02, &["10.0.2.0/24"]),
+                    peer(0x03, &["10.0.3.0/24"]),
+                ]),
+            ],
+        };
+        let mut reordered_peer = peer(0x01, &["fd00:1::/64", "10.0.1.0/24"]);
+        reordered_peer.0.reverse();
+        let reordered = Wireguard {
+            cmd: WireguardCmd::GetDevice,
+            nlas: vec![
+                WgDeviceAttrs::Peers(vec![peer(0x03, &["10.0.3.0/24"])]),
+                WgDeviceAttrs::ListenPort(51820),
+                WgDeviceAttrs::IfName("wg0".to_string()),
+                WgDeviceAttrs::Peers(vec![
+                    peer(0x02, &["10.0.2.0/24"]),
+                    reordered_peer,
+                ]),
+            ],
+        };
+        assert_eq!(reordered.config_fingerprint(), wg.config_fingerprint());
+
+        let mut changed = wg.clone();
+        changed.retain_peers(|peer| {
+            peer.public_key() != Some(&[0x02; WG_KEY_LEN])
+        });
+        assert_ne!(changed.config_fingerprint(), wg.config_fingerprint());
+    }
+}
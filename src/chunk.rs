@@ -0,0 +1,271 @@
+// SPDX-License-Identifier: MIT
+
+use netlink_packet_core::{
+    NetlinkMessage, NETLINK_HEADER_LEN, NLM_F_ACK, NLM_F_DUMP, NLM_F_REQUEST,
+};
+use netlink_packet_generic::{constants::GENL_HDRLEN, GenlMessage};
+use netlink_packet_utils::{nla::NLA_HEADER_SIZE, Emitable};
+
+use crate::{
+    nlas::{WgDeviceAttrs, WgPeer},
+    InterfaceId, Wireguard, WireguardCmd,
+};
+
+impl Wireguard {
+    /// Split the message so that each netlink message, headers included,
+    /// takes at most `max_bytes`, and wrap the parts in netlink messages
+    /// flagged `NLM_F_REQUEST | NLM_F_ACK`, ready to be sent one after the
+    /// other.
+    ///
+    /// The first message carries every device attribute and as many peers
+    /// as fit. The following ones are built with
+    /// [`Wireguard::peers_continuation`] and only carry the identity of the
+    /// device and more peers. Peers are never split: a peer larger than
+    /// `max_bytes` is sent alone in a message exceeding the limit.
+    ///
+    /// The messages are finalized, but the generic netlink family id is
+    /// only known at runtime: it has to be set, and the messages
+    /// finalized again, before sending them.
+    pub fn into_netlink_messages(
+        self,
+        max_bytes: usize,
+    ) -> Vec<NetlinkMessage<GenlMessage<Wireguard>>> {
+        self.into_chunks(max_bytes)
+            .into_iter()
+            .map(|wg| {
+                let mut msg =
+                    NetlinkMessage::from(GenlMessage::from_payload(wg));
+                msg.header.flags = NLM_F_REQUEST | NLM_F_ACK;
+                msg.finalize();
+                msg
+            })
+            .collect()
+    }
+
+    /// A `GetDevice` request for the device `ifname_or_index`, wrapped in
+    /// a netlink message flagged `NLM_F_REQUEST | NLM_F_DUMP`.
+    ///
+    /// The kernel only answers `GetDevice` as a dump, since a device with
+    /// many peers doesn't fit in a single message: without `NLM_F_DUMP`,
+    /// the request fails with `EOPNOTSUPP`. The reply ends with an
+    /// `NLMSG_DONE` message.
+    ///
+    /// Like with [`Wireguard::into_netlink_messages`], the generic netlink
+    /// family id has to be set and the message finalized again before
+    /// sending it.
+    pub fn get_device_request<I>(
+        ifname_or_index: I,
+    ) -> NetlinkMessage<GenlMessage<Wireguard>>
+    where
+        I: Into<InterfaceId>,
+    {
+        let wg = Wireguard {
+            cmd: WireguardCmd::GetDevice,
+            nlas: vec![ifname_or_index.into().to_attr()],
+        };
+        let mut msg = NetlinkMessage::from(GenlMessage::from_payload(wg));
+        msg.header.flags = NLM_F_REQUEST | NLM_F_DUMP;
+        msg.finalize();
+        msg
+    }
+
+    fn into_chunks(self, max_bytes: usize) -> Vec<Wireguard> {
+        if message_len(&self) <= max_bytes {
+            return vec![self];
+        }
+        let identity = self.nlas.iter().find_map(InterfaceId::from_attr);
+        let mut device = Vec::new();
+        let mut peers = Vec::new();
+        for nla in self.nlas {
+            match nla {
+                WgDeviceAttrs::Peers(p) => peers.extend(p),
+                nla => device.push(nla),
+            }
+        }
+
+        let mut chunks = vec![Wireguard {
+            cmd: self.cmd,
+            nlas: device,
+        }];
+        let mut current: Vec<WgPeer> = Vec::new();
+        let mut len = message_len(&chunks[0]) + NLA_HEADER_SIZE;
+        for peer in peers {
+            if !current.is_empty() && len + peer.buffer_len() > max_bytes {
+                let next = match &identity {
+                    Some(id) => {
+                        Wireguard::peers_continuation(id.clone(), vec![])
+                    }
+                    None => Wireguard {
+                        cmd: self.cmd,
+                        nlas: vec![WgDeviceAttrs::Peers(vec![])],
+                    },
+                };
+                push_peers(chunks.last_mut().unwrap(), current);
+                current = Vec::new();
+                // The continuation already holds an empty Peers attribute.
+                len = message_len(&next);
+                chunks.push(next);
+            }
+            len += peer.buffer_len();
+            current.push(peer);
+        }
+        push_peers(chunks.last_mut().unwrap(), current);
+        chunks
+    }
+}
+
+fn message_len(wg: &Wireguard) -> usize {
+    NETLINK_HEADER_LEN + GENL_HDRLEN + wg.buffer_len()
+}
+
+// Put the peers in the Peers attribute of the message, adding one if
+// there is none.
+fn push_peers(wg: &mut Wireguard, peers: Vec<WgPeer>) {
+    if let Some(WgDeviceAttrs::Peers(p)) = wg.nlas.last_mut() {
+        p.extend(peers);
+    } else {
+        wg.nlas.push(WgDeviceAttrs::Peers(peers));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use netlink_packet_core::NetlinkPayload;
+
+    use crate::{
+        constants::*, nlas::WgAllowedIp, WgDeviceBuilder, WgPeerBuilder,
+    };
+
+    fn large_device(peers: u8) -> Wireguard {
+        WgDeviceBuilder::new()
+            .ifname("wg0")
+            .private_key([0xaa; WG_KEY_LEN])
+            .listen_port(51820)
+            .replace_peers()
+            .peers((0..peers).map(|i| {
+                WgPeerBuilder::new([i; WG_KEY_LEN])
+                    .allowed_ip_unchecked([10, 0, i, 0].into(), 24)
+                    .build()
+            }))
+            .build()
+    }
+
+    fn peers_of(wg: &Wireguard) -> Vec<WgPeer> {
+        wg.nlas
+            .iter()
+            .filter_map(|nla| match nla {
+                WgDeviceAttrs::Peers(peers) => Some(peers.clone()),
+                _ => None,
+            })
+            .flatten()
+            .collect()
+    }
+
+    #[test]
+    fn test_into_netlink_messages() {
+        let wg = large_device(100);
+        let all_peers = peers_of(&wg);
+        let peer_len = all_peers[0].buffer_len();
+        let msgs = wg.clone().into_netlink_messages(1024);
+
+        // The first message holds the device attributes, each following
+        // one the identity: both leave room for the same number of peers
+        // here, at most 1024 bytes each.
+        let first_len = message_len(&wg.clone().without_peers());
+        let first = (1024 - first_len - NLA_HEADER_SIZE) / peer_len;
+        let next_len =
+            message_len(&Wireguard::peers_continuation("wg0", vec![]));
+        let next = (1024 - next_len) / peer_len;
+        assert_eq!(msgs.len(), 1 + (100 - first + next - 1) / next);
+
+        let mut peers = Vec::new();
+        for (i, msg) in msgs.iter().enumerate() {
+            assert_eq!(msg.header.flags, NLM_F_REQUEST | NLM_F_ACK);
+            assert!(msg.buffer_len() <= 1024);
+            assert_eq!(msg.header.length as usize, msg.buffer_len());
+            let payload = match &msg.payload {
+                netlink_packet_core::NetlinkPayload::InnerMessage(genl) => {
+                    &genl.payload
+                }
+                _ => panic!("unexpected payload"),
+            };
+            assert_eq!(payload.cmd, WireguardCmd::SetDevice);
+            if i == 0 {
+                assert!(payload
+                    .nlas
+                    .contains(&WgDeviceAttrs::Flags(WGDEVICE_F_REPLACE_PEERS)));
+                assert_eq!(peers_of(payload).len(), first);
+            } else {
+                assert_eq!(
+                    payload.nlas[0],
+                    WgDeviceAttrs::IfName("wg0".to_string())
+                );
+                assert_eq!(payload.nlas.len(), 2);
+            }
+            assert_eq!(payload.validate_set(), Ok(()));
+            peers.extend(peers_of(payload));
+        }
+        assert_eq!(peers, all_peers);
+    }
+
+    #[test]
+    fn test_into_netlink_messages_fits() {
+        let wg = large_device(2);
+        let msgs = wg.clone().into_netlink_messages(4096);
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(
+            msgs[0].payload,
+            netlink_packet_core::NetlinkPayload::InnerMessage(
+                GenlMessage::from_payload(wg)
+            )
+        );
+    }
+
+    #[test]
+    fn test_into_netlink_messages_oversized_peer() {
+        let peer =
+            (0..50).fold(WgPeerBuilder::new([0x01; WG_KEY_LEN]), |peer, i| {
+                peer.allowed_ip(WgAllowedIp::new_unchecked(
+                    [10, i, 0, 0].into(),
+                    16,
+                ))
+            });
+        let wg = WgDeviceBuilder::new()
+            .ifindex(3)
+            .peer(peer.build())
+            .peer(WgPeerBuilder::new([0x02; WG_KEY_LEN]).build())
+            .build();
+        let msgs = wg.into_netlink_messages(256);
+        assert_eq!(msgs.len(), 2);
+        assert!(msgs[0].buffer_len() > 256);
+        assert!(msgs[1].buffer_len() <= 256);
+    }
+
+    #[test]
+    fn test_get_device_request() {
+        for (id, attr) in [
+            (
+                InterfaceId::from("wg0"),
+                WgDeviceAttrs::IfName("wg0".into()),
+            ),
+            (InterfaceId::from(3), WgDeviceAttrs::IfIndex(3)),
+        ] {
+            let msg = Wireguard::get_device_request(id);
+            assert_eq!(msg.header.flags, NLM_F_REQUEST | NLM_F_DUMP);
+            assert_eq!(msg.header.length as usize, msg.buffer_len());
+            let genl = match msg.payload {
+                NetlinkPayload::InnerMessage(genl) => genl,
+                payload => panic!("unexpected payload {:?}", payload),
+            };
+            assert_eq!(
+                genl.payload,
+                Wireguard {
+                    cmd: WireguardCmd::GetDevice,
+                    nlas: vec![attr],
+                }
+            );
+            assert_eq!(genl.payload.validate_get(), Ok(()));
+        }
+    }
+}
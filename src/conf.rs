@@ -0,0 +1,789 @@
+// SPDX-License-Identifier: MIT
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    net::{IpAddr, SocketAddr},
+};
+
+use crate::{
+    constants::*,
+    encoding,
+    nlas::{peer::mask, WgAllowedIp, WgDeviceAttrs, WgPeer, WgPeerAttrs},
+    WgDeviceBuilder, WgError, WgKey, WgPeerBuilder, WgPeerFlags, Wireguard,
+    WireguardCmd,
+};
+
+// The [Interface] keys of wg-quick(8) which are not kernel attributes.
+const WG_QUICK_KEYS: &[&str] = &[
+    "Address",
+    "DNS",
+    "MTU",
+    "Table",
+    "PreUp",
+    "PostUp",
+    "PreDown",
+    "PostDown",
+    "SaveConfig",
+];
+
+impl Wireguard {
+    /// Parse a configuration in the format of `wg setconf` into the
+    /// `SetDevice` message applying it to the interface `ifname`.
+    ///
+    /// Like `wg setconf`, the message carries `WGDEVICE_F_REPLACE_PEERS`
+    /// and every peer carries `WGPEER_F_REPLACE_ALLOWEDIPS`, so the
+    /// interface ends up with exactly the given configuration. Endpoints
+    /// must be IP addresses: host names have to be resolved beforehand.
+    ///
+    /// The configuration doesn't name the interface, `ifname` does, so
+    /// the output of `wg showconf` is accepted as is. So are `wg-quick`
+    /// configurations: the keys `wg-quick` adds to `[Interface]`
+    /// (`Address`, `DNS`, `MTU`, `Table`, `PreUp`, `PostUp`, `PreDown`,
+    /// `PostDown` and `SaveConfig`) configure the system rather than the
+    /// kernel device and are ignored. Other unknown keys are errors.
+    pub fn from_wg_conf(text: &str, ifname: &str) -> Result<Self, WgError> {
+        Self::parse_conf(text, ifname, true)
+    }
+
+    /// Same as [`Wireguard::from_wg_conf`], with the semantics of
+    /// `wg addconf`: the message doesn't carry `WGDEVICE_F_REPLACE_PEERS`,
+    /// so the peers of the interface missing from the configuration are
+    /// kept. The peers of the configuration still carry
+    /// `WGPEER_F_REPLACE_ALLOWEDIPS`, like with `wg addconf`.
+    pub fn from_wg_conf_additive(
+        text: &str,
+        ifname: &str,
+    ) -> Result<Self, WgError> {
+        Self::parse_conf(text, ifname, false)
+    }
+
+    fn parse_conf(
+        text: &str,
+        ifname: &str,
+        replace_peers: bool,
+    ) -> Result<Self, WgError> {
+        let mut section = Section::None;
+        let mut private_key = None;
+        let mut listen_port = None;
+        let mut fwmark = None;
+        let mut peers = Vec::new();
+        let mut peer: Option<ConfPeer> = None;
+
+        for (i, line) in text.lines().enumerate() {
+            let line_no = i + 1;
+            let err = |message: String| WgError::InvalidConf {
+                line: line_no,
+                message,
+            };
+            let line = match line.find('#') {
+                Some(pos) => &line[..pos],
+                None => line,
+            }
+            .trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                if let Some(peer) = peer.take() {
+                    peers.push(peer.build()?);
+                }
+                let name = line[1..line.len() - 1].trim();
+                section = if name.eq_ignore_ascii_case("Interface") {
+                    Section::Interface
+                } else if name.eq_ignore_ascii_case("Peer") {
+                    peer = Some(ConfPeer::new(line_no));
+                    Section::Peer
+                } else {
+                    return Err(err(format!("unknown section [{}]", name)));
+                };
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => {
+                    return Err(err(format!("expected key = value: {}", line)))
+                }
+            };
+            match (&section, &mut peer) {
+                (Section::Interface, _) => {
+                    if key.eq_ignore_ascii_case("PrivateKey") {
+                        private_key = Some(parse_key(value).map_err(err)?);
+                    } else if key.eq_ignore_ascii_case("ListenPort") {
+                        listen_port =
+                            Some(value.parse::<u16>().map_err(|e| {
+                                err(format!("invalid ListenPort: {}", e))
+                            })?);
+                    } else if key.eq_ignore_ascii_case("FwMark") {
+                        fwmark = Some(parse_fwmark(value).map_err(err)?);
+                    } else if WG_QUICK_KEYS
+                        .iter()
+                        .any(|k| key.eq_ignore_ascii_case(k))
+                    {
+                        // Not a kernel attribute, see the documentation.
+                    } else {
+                        return Err(err(format!(
+                            "unknown key in [Interface]: {}",
+                            key
+                        )));
+                    }
+                }
+                (Section::Peer, Some(peer)) => {
+                    peer.set(key, value).map_err(err)?
+                }
+                _ => {
+                    return Err(err(format!(
+                        "key outside of any section: {}",
+                        key
+                    )))
+                }
+            }
+        }
+        if let Some(peer) = peer.take() {
+            peers.push(peer.build()?);
+        }
+
+        let mut nlas = vec![WgDeviceAttrs::IfName(ifname.to_string())];
+        if let Some(key) = private_key {
+            nlas.push(WgDeviceAttrs::PrivateKey(key));
+        }
+        if let Some(port) = listen_port {
+            nlas.push(WgDeviceAttrs::ListenPort(port));
+        }
+        if let Some(fwmark) = fwmark {
+            nlas.push(WgDeviceAttrs::Fwmark(fwmark));
+        }
+        if replace_peers {
+            nlas.push(WgDeviceAttrs::Flags(WGDEVICE_F_REPLACE_PEERS));
+        }
+        nlas.push(WgDeviceAttrs::Peers(peers));
+        Ok(Wireguard {
+            cmd: WireguardCmd::SetDevice,
+            nlas,
+        })
+    }
+
+    /// Whether the configuration of the device, as dumped by `GetDevice`,
+    /// is the one described by `conf_text` (see
+    /// [`Wireguard::from_wg_conf`]). Statistics are ignored.
+    ///
+    /// A missing private key, preshared key, fwmark or persistent
+    /// keepalive interval stands for the zero value the kernel reports.
+    /// A missing listen port or endpoint matches any value, since the
+    /// kernel picks the port and learns the endpoints by itself.
+    pub fn matches_conf(
+        &self,
+        conf_text: &str,
+        ifname: &str,
+    ) -> Result<bool, WgError> {
+        let conf =
+            ConfigView::new(&Wireguard::from_wg_conf(conf_text, ifname)?);
+        Ok(conf.matches(&ConfigView::new(self)))
+    }
+
+    /// Parse a `SetDevice` message from whitespace separated `key=value`
+    /// pairs, for quick command line tools:
+    ///
+    /// ```text
+    /// ifname=wg0 private_key=<base64> listen_port=51820
+    ///     peer.public_key=<base64> peer.allowed_ips=10.0.0.2/32,fd00::2
+    /// ```
+    ///
+    /// The device keys are `ifname`, `ifindex`, `private_key` (base64),
+    /// `listen_port` and `fwmark`. Each `peer.public_key` (base64) starts
+    /// a new peer, which the following `peer.preshared_key` (base64),
+    /// `peer.endpoint` (`ip:port`), `peer.persistent_keepalive` and
+    /// `peer.allowed_ips` (comma separated) keys apply to. No flag is set.
+    pub fn from_kv(input: &str) -> Result<Self, WgError> {
+        let mut device = WgDeviceBuilder::new();
+        let mut peer: Option<WgPeerBuilder> = None;
+        let mut peers = Vec::new();
+        for token in input.split_whitespace() {
+            let err = |message: String| {
+                WgError::InvalidKv(format!("{}: {}", token, message))
+            };
+            let (key, value) = token
+                .split_once('=')
+                .ok_or_else(|| err("expected key=value".to_string()))?;
+            if let Some(key) = key.strip_prefix("peer.") {
+                if key == "public_key" {
+                    peers.extend(peer.take().map(WgPeerBuilder::build));
+                    peer = Some(WgPeerBuilder::new(
+                        parse_key(value).map_err(err)?,
+                    ));
+                    continue;
+                }
+                let builder = peer.take().ok_or_else(|| {
+                    err("peer key before any peer.public_key".to_string())
+                })?;
+                peer = Some(match key {
+                    "preshared_key" => {
+                        builder.preshared_key(parse_key(value).map_err(err)?)
+                    }
+                    "endpoint" => builder.endpoint(
+                        value
+                            .parse()
+                            .map_err(|_| err("expected ip:port".to_string()))?,
+                    ),
+                    "persistent_keepalive" => builder.persistent_keepalive(
+                        value.parse().map_err(|e| err(format!("{}", e)))?,
+                    ),
+                    "allowed_ips" => builder
+                        .allowed_cidrs(
+                            value.split(',').filter(|ip| !ip.is_empty()),
+                        )
+                        .map_err(|e| err(e.to_string()))?,
+                    _ => return Err(err("unknown peer key".to_string())),
+                });
+                continue;
+            }
+            device = match key {
+                "ifname" => device.ifname(value),
+                "ifindex" => device
+                    .ifindex(value.parse().map_err(|e| err(format!("{}", e)))?),
+                "private_key" => {
+                    device.private_key(parse_key(value).map_err(err)?)
+                }
+                "listen_port" => device.listen_port(
+                    value.parse().map_err(|e| err(format!("{}", e)))?,
+                ),
+                "fwmark" => device.fwmark(parse_fwmark(value).map_err(err)?),
+                _ => return Err(err("unknown key".to_string())),
+            };
+        }
+        peers.extend(peer.map(WgPeerBuilder::build));
+        Ok(device.peers(peers).build())
+    }
+}
+
+impl Wireguard {
+    /// Write the configuration of the device in the format of
+    /// `wg showconf`: `ListenPort`, `FwMark` and `PrivateKey` in an
+    /// `[Interface]` section, followed by one
+    /// [section](WgPeer::to_conf_section) per peer. Like `wg showconf`,
+    /// zero values stand for unset settings and are omitted.
+    pub fn to_wg_conf(&self) -> String {
+        let mut out = String::from("[Interface]\n");
+        let device = ConfigView::new(self);
+        if let Some(port) = device.listen_port.filter(|port| *port != 0) {
+            writeln!(out, "ListenPort = {}", port).unwrap();
+        }
+        if device.fwmark != 0 {
+            writeln!(out, "FwMark = {:#x}", device.fwmark).unwrap();
+        }
+        if device.private_key != [0; WG_KEY_LEN] {
+            writeln!(
+                out,
+                "PrivateKey = {}",
+                encoding::encode(&device.private_key)
+            )
+            .unwrap();
+        }
+        for peer in self.peers() {
+            out.push('\n');
+            out.push_str(&peer.to_conf_section());
+        }
+        out
+    }
+}
+
+impl WgPeer {
+    /// Write the peer as a `[Peer]` section of the format of
+    /// `wg showconf`. An all-zero preshared key and a zero persistent
+    /// keepalive interval are omitted.
+    pub fn to_conf_section(&self) -> String {
+        let mut out = String::from("[Peer]\n");
+        if let Some(key) = self.public_key() {
+            writeln!(out, "PublicKey = {}", encoding::encode(key)).unwrap();
+        }
+        if let Some(key) = self.preshared_key_bytes() {
+            if *key != [0; WG_KEY_LEN] {
+                writeln!(out, "PresharedKey = {}", encoding::encode(key))
+                    .unwrap();
+            }
+        }
+        let allowed_ips = self.allowed_ip_strings();
+        if !allowed_ips.is_empty() {
+            writeln!(out, "AllowedIPs = {}", allowed_ips.join(", ")).unwrap();
+        }
+        if let Some(endpoint) = self.endpoint() {
+            writeln!(out, "Endpoint = {}", endpoint).unwrap();
+        }
+        for nla in self.iter() {
+            if let WgPeerAttrs::PersistentKeepalive(interval) = nla {
+                if *interval != 0 {
+                    writeln!(out, "PersistentKeepalive = {}", interval)
+                        .unwrap();
+                }
+            }
+        }
+        out
+    }
+}
+
+enum Section {
+    None,
+    Interface,
+    Peer,
+}
+
+struct ConfPeer {
+    line: usize,
+    public_key: Option<[u8; WG_KEY_LEN]>,
+    preshared_key: Option<[u8; WG_KEY_LEN]>,
+    endpoint: Option<SocketAddr>,
+    persistent_keepalive: Option<u16>,
+    allowed_ips: Vec<WgAllowedIp>,
+}
+
+impl ConfPeer {
+    fn new(line: usize) -> Self {
+        Self {
+            line,
+            public_key: None,
+            preshared_key: None,
+            endpoint: None,
+            persistent_keepalive: None,
+            allowed_ips: Vec::new(),
+        }
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        if key.eq_ignore_ascii_case("PublicKey") {
+            self.public_key = Some(parse_key(value)?);
+        } else if key.eq_ignore_ascii_case("PresharedKey") {
+            self.preshared_key = Some(parse_key(value)?);
+        } else if key.eq_ignore_ascii_case("Endpoint") {
+            self.endpoint = Some(value.parse().map_err(|_| {
+                format!("invalid Endpoint, expected ip:port: {}", value)
+            })?);
+        } else if key.eq_ignore_ascii_case("PersistentKeepalive") {
+            self.persistent_keepalive = Some(if value == "off" {
+                0
+            } else {
+                value.parse().map_err(|e| {
+                    format!("invalid PersistentKeepalive: {}", e)
+                })?
+            });
+        } else if key.eq_ignore_ascii_case("AllowedIPs") {
+            self.allowed_ips.extend(
+                WgAllowedIp::parse_conf_line(value)
+                    .map_err(|e| e.to_string())?,
+            );
+        } else {
+            return Err(format!("unknown key in [Peer]: {}", key));
+        }
+        Ok(())
+    }
+
+    fn build(self) -> Result<WgPeer, WgError> {
+        let public_key = self.public_key.ok_or(WgError::InvalidConf {
+            line: self.line,
+            message: "[Peer] without PublicKey".to_string(),
+        })?;
+        let mut peer = WgPeerBuilder::new(public_key)
+            .flags(WgPeerFlags::REPLACE_ALLOWEDIPS);
+        if let Some(key) = self.preshared_key {
+            peer = peer.preshared_key(key);
+        }
+        if let Some(endpoint) = self.endpoint {
+            peer = peer.endpoint(endpoint);
+        }
+        if let Some(interval) = self.persistent_keepalive {
+            peer = peer.persistent_keepalive(interval);
+        }
+        for allowed_ip in self.allowed_ips {
+            peer = peer.allowed_ip(allowed_ip);
+        }
+        Ok(peer.build())
+    }
+}
+
+fn parse_key(value: &str) -> Result<[u8; WG_KEY_LEN], String> {
+    WgKey::from_base64(value)
+        .map(Into::into)
+        .map_err(|e| e.to_string())
+}
+
+fn parse_fwmark(value: &str) -> Result<u32, String> {
+    let fwmark = if value == "off" {
+        Ok(0)
+    } else if let Some(hex) = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        u32::from_str_radix(hex, 16)
+    } else {
+        value.parse()
+    };
+    fwmark.map_err(|e| format!("invalid FwMark: {}", e))
+}
+
+// The configuration part of a device, with the defaults of the kernel
+// filled in, to compare configurations regardless of attribute order.
+#[derive(Debug)]
+struct ConfigView {
+    private_key: [u8; WG_KEY_LEN],
+    listen_port: Option<u16>,
+    fwmark: u32,
+    peers: BTreeMap<[u8; WG_KEY_LEN], PeerView>,
+}
+
+#[derive(Debug)]
+struct PeerView {
+    preshared_key: [u8; WG_KEY_LEN],
+    endpoint: Option<SocketAddr>,
+    persistent_keepalive: u16,
+    allowed_ips: BTreeSet<(IpAddr, u8)>,
+}
+
+impl ConfigView {
+    fn new(wg: &Wireguard) -> Self {
+        let mut view = Self {
+            private_key: [0; WG_KEY_LEN],
+            listen_port: None,
+            fwmark: 0,
+            peers: BTreeMap::new(),
+        };
+        for nla in &wg.nlas {
+            match nla {
+                WgDeviceAttrs::PrivateKey(key) => view.private_key = *key,
+                WgDeviceAttrs::ListenPort(port) => {
+                    view.listen_port = Some(*port)
+                }
+                WgDeviceAttrs::Fwmark(fwmark) => view.fwmark = *fwmark,
+                _ => (),
+            }
+        }
+        for peer in wg.peers() {
+            let public_key = match peer.public_key() {
+                Some(key) => *key,
+                None => continue,
+            };
+            let view = view.peers.entry(public_key).or_insert(PeerView {
+                preshared_key: [0; WG_KEY_LEN],
+                endpoint: None,
+                persistent_keepalive: 0,
+                allowed_ips: BTreeSet::new(),
+            });
+            for nla in peer.iter() {
+                match nla {
+                    WgPeerAttrs::PresharedKey(key) => view.preshared_key = *key,
+                    WgPeerAttrs::Endpoint(endpoint) => {
+                        view.endpoint = Some(*endpoint)
+                    }
+                    WgPeerAttrs::PersistentKeepalive(interval) => {
+                        view.persistent_keepalive = *interval
+                    }
+                    WgPeerAttrs::AllowedIps(ips) => {
+                        view.allowed_ips.extend(ips.iter().filter_map(network))
+                    }
+                    _ => (),
+                }
+            }
+        }
+        view
+    }
+
+    // Whether `device` has this configuration, where the unset listen
+    // port and endpoints match any value.
+    fn matches(&self, device: &ConfigView) -> bool {
+        self.private_key == device.private_key
+            && self
+                .listen_port
+                .map_or(true, |p| device.listen_port == Some(p))
+            && self.fwmark == device.fwmark
+            && self.peers.len() == device.peers.len()
+            && self.peers.iter().all(|(key, peer)| {
+                device.peers.get(key).is_some_and(|other| {
+                    peer.preshared_key == other.preshared_key
+                        && peer
+                            .endpoint
+                            .map_or(true, |e| other.endpoint == Some(e))
+                        && peer.persistent_keepalive
+                            == other.persistent_keepalive
+                        && peer.allowed_ips == other.allowed_ips
+                })
+            })
+    }
+}
+
+// The allowed IP with its host bits cleared, as the kernel stores it.
+fn network(allowed_ip: &WgAllowedIp) -> Option<(IpAddr, u8)> {
+    let (addr, cidr) = allowed_ip.addr_and_cidr()?;
+    Some((mask(addr, cidr), cidr))
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::SystemTime;
+
+    use super::*;
+    use crate::{WgDeviceBuilder, WgKey};
+
+    const CONF: &str = "\
+[Interface]
+# The configuration of wg0
+PrivateKey = qqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqo=
+ListenPort = 51820
+
+[Peer]
+PublicKey = AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=
+AllowedIPs = 10.0.0.2/32, fd00::2/128
+
+[peer]
+publickey = AgICAgICAgICAgICAgICAgICAgICAgICAgICAgICAgI=
+Endpoint = 192.168.1.1:51820 # the hub
+PersistentKeepalive = 25
+AllowedIPs = 10.0.0.1/24
+";
+
+    // The device configured with CONF, as dumped by the kernel.
+    fn dumped_device() -> Wireguard {
+        let mut wg = WgDeviceBuilder::new()
+            .ifname("wg0")
+            .private_key([0xaa; WG_KEY_LEN])
+            .listen_port(51820)
+            .fwmark(0)
+            .peer(
+                WgPeerBuilder::new([0x01; WG_KEY_LEN])
+                    .preshared_key([0; WG_KEY_LEN])
+                    .endpoint("192.168.1.2:34567".parse().unwrap())
+                    .persistent_keepalive(0)
+                    .allowed_ip_unchecked("fd00::2".parse().unwrap(), 128)
+                    .allowed_ip_unchecked([10, 0, 0, 2].into(), 32)
+                    .build(),
+            )
+            .peer(
+                WgPeerBuilder::new([0x02; WG_KEY_LEN])
+                    .endpoint("192.168.1.1:51820".parse().unwrap())
+                    .persistent_keepalive(25)
+                    .allowed_ip_unchecked([10, 0, 0, 0].into(), 24)
+                    .build(),
+            )
+            .build();
+        wg.cmd = WireguardCmd::GetDevice;
+        if let WgDeviceAttrs::Peers(peers) = wg.nlas.last_mut().unwrap() {
+            peers[1].0.push(WgPeerAttrs::RxBytes(1024));
+            peers[1]
+                .0
+                .push(WgPeerAttrs::LastHandshake(SystemTime::UNIX_EPOCH));
+        }
+        wg
+    }
+
+    #[test]
+    fn test_from_wg_quick_conf() {
+        let wg_quick = format!(
+            "\
+[Interface]
+Address = 10.0.0.3/24, fd00::3/64
+DNS = 10.0.0.1
+MTU = 1420
+Table = off
+PostUp = iptables -A FORWARD -i %i -j ACCEPT
+postdown = iptables -D FORWARD -i %i -j ACCEPT
+SaveConfig = false
+{}",
+            CONF.trim_start_matches("[Interface]\n")
+        );
+        assert_eq!(
+            Wireguard::from_wg_conf(&wg_quick, "wg1").unwrap(),
+            Wireguard::from_wg_conf(CONF, "wg1").unwrap()
+        );
+        let typo = CONF.replace("ListenPort", "ListenPrt");
+        assert!(matches!(
+            Wireguard::from_wg_conf(&typo, "wg0"),
+            Err(WgError::InvalidConf { line: 4, .. })
+        ));
+        // wg-quick keys are only known in [Interface].
+        let misplaced = format!("{}DNS = 10.0.0.1\n", CONF);
+        assert!(Wireguard::from_wg_conf(&misplaced, "wg0").is_err());
+    }
+
+    #[test]
+    fn test_from_wg_conf() {
+        let wg = Wireguard::from_wg_conf(CONF, "wg0").unwrap();
+        assert_eq!(
+            wg,
+            Wireguard {
+                cmd: WireguardCmd::SetDevice,
+                nlas: vec![
+                    WgDeviceAttrs::IfName("wg0".to_string()),
+                    WgDeviceAttrs::PrivateKey([0xaa; WG_KEY_LEN]),
+                    WgDeviceAttrs::ListenPort(51820),
+                    WgDeviceAttrs::Flags(WGDEVICE_F_REPLACE_PEERS),
+                    WgDeviceAttrs::Peers(vec![
+                        WgPeerBuilder::new(WgKey::new([0x01; WG_KEY_LEN]))
+                            .flags(WgPeerFlags::REPLACE_ALLOWEDIPS)
+                            .allowed_ip_unchecked([10, 0, 0, 2].into(), 32)
+                            .allowed_ip_unchecked(
+                                "fd00::2".parse().unwrap(),
+                                128
+                            )
+                            .build(),
+                        WgPeerBuilder::new([0x02; WG_KEY_LEN])
+                            .flags(WgPeerFlags::REPLACE_ALLOWEDIPS)
+                            .endpoint("192.168.1.1:51820".parse().unwrap())
+                            .persistent_keepalive(25)
+                            .allowed_ip_unchecked([10, 0, 0, 1].into(), 24)
+                            .build(),
+                    ]),
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn test_from_wg_conf_additive() {
+        let wg = Wireguard::from_wg_conf_additive(CONF, "wg0").unwrap();
+        assert!(!wg
+            .nlas
+            .iter()
+            .any(|nla| matches!(nla, WgDeviceAttrs::Flags(_))));
+        let mut replace = Wireguard::from_wg_conf(CONF, "wg0").unwrap();
+        replace
+            .nlas
+            .retain(|nla| !matches!(nla, WgDeviceAttrs::Flags(_)));
+        assert_eq!(wg, replace);
+    }
+
+    #[test]
+    fn test_from_wg_conf_errors() {
+        let err = Wireguard::from_wg_conf(
+            "[Interface]\nListenPort = 1\n[Peer]\nAllowedIPs = 10.0.0.1\n",
+            "wg0",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            WgError::InvalidConf {
+                line: 3,
+                message: "[Peer] without PublicKey".to_string()
+            }
+        );
+        for (conf, line) in [
+            ("ListenPort = 1", 1),
+            ("[Interface]\nListenPort = 65536", 2),
+            ("[Interface]\n\nPeerKey = 10.0.0.1/24", 3),
+            ("[Interface]\nPrivateKey = AAAA", 2),
+            ("[Peer]\nAllowedIPs = 10.0.0.0/33", 2),
+            ("[Peer]\nEndpoint = vpn.example.com:51820", 2),
+            ("[Device]", 1),
+        ] {
+            match Wireguard::from_wg_conf(conf, "wg0") {
+                Err(WgError::InvalidConf { line: got, .. }) => {
+                    assert_eq!(got, line, "{}", conf)
+                }
+                other => panic!("{}: unexpected result {:?}", conf, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_kv() {
+        let input = format!(
+            "ifname=wg0 private_key={} listen_port=51820 fwmark=0x10 \
+             peer.public_key={} peer.allowed_ips=10.0.0.2/32,fd00::2 \
+             peer.public_key={} peer.endpoint=192.168.1.1:51820 \
+             peer.persistent_keepalive=25",
+            encoding::encode(&[0xaa; WG_KEY_LEN]),
+            encoding::encode(&[0x01; WG_KEY_LEN]),
+            encoding::encode(&[0x02; WG_KEY_LEN]),
+        );
+        assert_eq!(
+            Wireguard::from_kv(&input).unwrap(),
+            WgDeviceBuilder::new()
+                .ifname("wg0")
+                .private_key([0xaa; WG_KEY_LEN])
+                .listen_port(51820)
+                .fwmark(0x10)
+                .peer(
+                    WgPeerBuilder::new([0x01; WG_KEY_LEN])
+                        .allowed_ip_unchecked([10, 0, 0, 2].into(), 32)
+                        .allowed_ip_unchecked("fd00::2".parse().unwrap(), 128)
+                        .build()
+                )
+                .peer(
+                    WgPeerBuilder::new([0x02; WG_KEY_LEN])
+                        .endpoint("192.168.1.1:51820".parse().unwrap())
+                        .persistent_keepalive(25)
+                        .build()
+                )
+                .build()
+        );
+        for input in [
+            "ifname",
+            "name=wg0",
+            "listen_port=65536",
+            "peer.endpoint=192.168.1.1:51820",
+            "private_key=AAAA",
+        ] {
+            assert!(
+                matches!(Wireguard::from_kv(input), Err(WgError::InvalidKv(_))),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_to_conf_section() {
+        let peer = WgPeerBuilder::new([0x01; WG_KEY_LEN])
+            .preshared_key([0x02; WG_KEY_LEN])
+            .endpoint("[fd00::1]:51820".parse().unwrap())
+            .persistent_keepalive(25)
+            .allowed_ip_unchecked([10, 0, 0, 0].into(), 24)
+            .allowed_ip_unchecked("fd00::".parse().unwrap(), 64)
+            .build();
+        assert_eq!(
+            peer.to_conf_section(),
+            "[Peer]\n\
+             PublicKey = AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=\n\
+             PresharedKey = AgICAgICAgICAgICAgICAgICAgICAgICAgICAgICAgI=\n\
+             AllowedIPs = 10.0.0.0/24, fd00::/64\n\
+             Endpoint = [fd00::1]:51820\n\
+             PersistentKeepalive = 25\n"
+        );
+    }
+
+    #[test]
+    fn test_to_wg_conf() {
+        let device = dumped_device();
+        let conf = device.to_wg_conf();
+        assert!(conf.starts_with(
+            "[Interface]\n\
+             ListenPort = 51820\n\
+             PrivateKey = qqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqo=\n\n\
+             [Peer]\n\
+             PublicKey = AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=\n\
+             AllowedIPs = fd00::2/128, 10.0.0.2/32\n"
+        ));
+        assert_eq!(device.matches_conf(&conf, "wg0"), Ok(true));
+    }
+
+    #[test]
+    fn test_matches_conf() {
+        let device = dumped_device();
+        assert_eq!(device.matches_conf(CONF, "wg0"), Ok(true));
+        assert_eq!(
+            device.matches_conf(&CONF.replace("51820\n\n", "51821\n\n"), "wg0"),
+            Ok(false)
+        );
+        assert_eq!(
+            device.matches_conf(&CONF.replace(" = 25", " = 15"), "wg0"),
+            Ok(false)
+        );
+        assert_eq!(
+            device.matches_conf(&CONF.replace(", fd00::2/128", ""), "wg0"),
+            Ok(false)
+        );
+        let extra_peer = format!(
+            "{}[Peer]\nPublicKey = {}\n",
+            CONF,
+            encoding::encode(&[0x03; WG_KEY_LEN])
+        );
+        assert_eq!(device.matches_conf(&extra_peer, "wg0"), Ok(false));
+        assert!(device.matches_conf("[Interface", "wg0").is_err());
+    }
+}
@@ -0,0 +1,114 @@
+// SPDX-License-Identifier: MIT
+
+use std::{collections::HashMap, net::SocketAddr};
+
+use crate::{
+    constants::WG_KEY_LEN,
+    nlas::{WgAllowedIp, WgDeviceAttrs},
+    SecretKey, WgPeerBuilder, WgPeerFlags, Wireguard,
+};
+
+/// The settings of a peer, without its public key, e.g. as stored by a
+/// controller in a map keyed by public key.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PeerConfig {
+    /// The `WGPEER_F_*` flags of the peer.
+    pub flags: Option<u32>,
+    pub preshared_key: Option<SecretKey>,
+    pub endpoint: Option<SocketAddr>,
+    pub persistent_keepalive: Option<u16>,
+    pub allowed_ips: Vec<WgAllowedIp>,
+}
+
+impl Wireguard {
+    /// Replace the `WGDEVICE_A_PEERS` attributes of the message with a
+    /// single one holding the peers of `peers`.
+    ///
+    /// The peers are sorted by public key so that the same map always
+    /// produces the same message, whatever its iteration order.
+    pub fn set_peers_from_map(
+        mut self,
+        peers: HashMap<[u8; WG_KEY_LEN], PeerConfig>,
+    ) -> Self {
+        let mut peers: Vec<_> = peers.into_iter().collect();
+        peers.sort_unstable_by_key(|(public_key, _)| *public_key);
+        let peers = peers
+            .into_iter()
+            .map(|(public_key, config)| {
+                let mut peer = WgPeerBuilder::new(public_key);
+                if let Some(flags) = config.flags {
+                    peer = peer.flags(WgPeerFlags::from_bits_retain(flags));
+                }
+                if let Some(key) = config.preshared_key {
+                    peer = peer.preshared_key(*key.expose_secret());
+                }
+                if let Some(endpoint) = config.endpoint {
+                    peer = peer.endpoint(endpoint);
+                }
+                if let Some(interval) = config.persistent_keepalive {
+                    peer = peer.persistent_keepalive(interval);
+                }
+                for allowed_ip in config.allowed_ips {
+                    peer = peer.allowed_ip(allowed_ip);
+                }
+                peer.build()
+            })
+            .collect();
+        self.nlas
+            .retain(|nla| !matches!(nla, WgDeviceAttrs::Peers(_)));
+        self.nlas.push(WgDeviceAttrs::Peers(peers));
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::WgDeviceBuilder;
+
+    #[test]
+    fn test_set_peers_from_map() {
+        let keys = [[0x03; WG_KEY_LEN], [0x01; WG_KEY_LEN], [0x02; WG_KEY_LEN]];
+        let mut map = HashMap::new();
+        for key in keys {
+            map.insert(
+                key,
+                PeerConfig {
+                    persistent_keepalive: Some(key[0].into()),
+                    ..Default::default()
+                },
+            );
+        }
+        let wg = WgDeviceBuilder::new()
+            .ifname("wg0")
+            .peer(WgPeerBuilder::new([0xff; WG_KEY_LEN]).build())
+            .build()
+            .set_peers_from_map(map.clone());
+        assert_eq!(wg.nlas.len(), 2);
+        let peers: Vec<_> = wg.peers().cloned().collect();
+        assert_eq!(
+            peers,
+            (1..=3)
+                .map(|i| WgPeerBuilder::new([i; WG_KEY_LEN])
+                    .persistent_keepalive(i.into())
+                    .build())
+                .collect::<Vec<_>>()
+        );
+
+        // The order doesn't depend on the insertion order.
+        let mut reversed = HashMap::new();
+        for key in keys.iter().rev() {
+            reversed.insert(*key, map[key].clone());
+        }
+        assert_eq!(
+            WgDeviceBuilder::new()
+                .ifname("wg0")
+                .build()
+                .set_peers_from_map(reversed),
+            WgDeviceBuilder::new()
+                .ifname("wg0")
+                .build()
+                .set_peers_from_map(map)
+        );
+    }
+}
@@ -3,7 +3,13 @@
 pub const AF_INET: u16 = libc::AF_INET as u16;
 pub const AF_INET6: u16 = libc::AF_INET6 as u16;
 
+pub const WG_GENL_NAME: &str = "wireguard";
+pub const WG_GENL_VERSION: u8 = 1;
+
 pub const WG_KEY_LEN: usize = 32;
+// Every key of the protocol is a Curve25519 key (or a preshared key of the
+// same size), and the kernel rejects any other length.
+const _: () = assert!(WG_KEY_LEN == 32);
 
 pub const WG_CMD_GET_DEVICE: u8 = 0;
 pub const WG_CMD_SET_DEVICE: u8 = 1;
@@ -0,0 +1,178 @@
+// SPDX-License-Identifier: MIT
+
+use std::fmt::Write;
+
+use netlink_packet_utils::nla::Nla;
+
+use crate::{
+    nlas::{WgAllowedIp, WgAllowedIpAttrs, WgDeviceAttrs, WgPeer, WgPeerAttrs},
+    Wireguard, WireguardCmd,
+};
+
+impl Wireguard {
+    /// Render a low level view of the message: one line per attribute
+    /// with its kind, value length and value as hex. Nested attributes are
+    /// indented under their parent and the values of private and preshared
+    /// keys are replaced by `<redacted>`.
+    pub fn debug_dump(&self) -> String {
+        let mut out = String::new();
+        let cmd = match self.cmd {
+            WireguardCmd::GetDevice => "WG_CMD_GET_DEVICE",
+            WireguardCmd::SetDevice => "WG_CMD_SET_DEVICE",
+        };
+        writeln!(out, "{}", cmd).unwrap();
+        for nla in &self.nlas {
+            dump_device_attr(&mut out, nla);
+        }
+        out
+    }
+}
+
+fn dump_device_attr(out: &mut String, nla: &WgDeviceAttrs) {
+    let name = match nla {
+        WgDeviceAttrs::Unspec(_) => "WGDEVICE_A_UNSPEC",
+        WgDeviceAttrs::IfIndex(_) => "WGDEVICE_A_IFINDEX",
+        WgDeviceAttrs::IfName(_) => "WGDEVICE_A_IFNAME",
+        WgDeviceAttrs::PrivateKey(_) => "WGDEVICE_A_PRIVATE_KEY",
+        WgDeviceAttrs::PublicKey(_) => "WGDEVICE_A_PUBLIC_KEY",
+        WgDeviceAttrs::ListenPort(_) => "WGDEVICE_A_LISTEN_PORT",
+        WgDeviceAttrs::Fwmark(_) => "WGDEVICE_A_FWMARK",
+        WgDeviceAttrs::Peers(_) => "WGDEVICE_A_PEERS",
+        WgDeviceAttrs::Flags(_) => "WGDEVICE_A_FLAGS",
+    };
+    match nla {
+        WgDeviceAttrs::Peers(peers) => {
+            dump_nested(out, 0, name, nla);
+            for peer in peers {
+                dump_peer(out, 1, peer);
+            }
+        }
+        WgDeviceAttrs::PrivateKey(_) => dump_redacted(out, 0, name, nla),
+        _ => dump_value(out, 0, name, nla),
+    }
+}
+
+fn dump_peer(out: &mut String, depth: usize, peer: &WgPeer) {
+    dump_nested(out, depth, "peer", peer);
+    for nla in peer.iter() {
+        let name = match nla {
+            WgPeerAttrs::Unspec(_) => "WGPEER_A_UNSPEC",
+            WgPeerAttrs::PublicKey(_) => "WGPEER_A_PUBLIC_KEY",
+            WgPeerAttrs::PresharedKey(_) => "WGPEER_A_PRESHARED_KEY",
+            WgPeerAttrs::Endpoint(_) => "WGPEER_A_ENDPOINT",
+            WgPeerAttrs::PersistentKeepalive(_) => {
+                "WGPEER_A_PERSISTENT_KEEPALIVE_INTERVAL"
+            }
+            WgPeerAttrs::LastHandshake(_) => "WGPEER_A_LAST_HANDSHAKE_TIME",
+            WgPeerAttrs::RxBytes(_) => "WGPEER_A_RX_BYTES",
+            WgPeerAttrs::TxBytes(_) => "WGPEER_A_TX_BYTES",
+            WgPeerAttrs::AllowedIps(_) => "WGPEER_A_ALLOWEDIPS",
+            WgPeerAttrs::ProtocolVersion(_) => "WGPEER_A_PROTOCOL_VERSION",
+            WgPeerAttrs::Flags(_) => "WGPEER_A_FLAGS",
+        };
+        match nla {
+            WgPeerAttrs::AllowedIps(ips) => {
+                dump_nested(out, depth + 1, name, nla);
+                for ip in ips {
+                    dump_allowed_ip(out, depth + 2, ip);
+                }
+            }
+            WgPeerAttrs::PresharedKey(_) => {
+                dump_redacted(out, depth + 1, name, nla)
+            }
+            _ => dump_value(out, depth + 1, name, nla),
+        }
+    }
+}
+
+fn dump_allowed_ip(out: &mut String, depth: usize, ip: &WgAllowedIp) {
+    dump_nested(out, depth, "allowedip", ip);
+    for nla in ip.iter() {
+        let name = match nla {
+            WgAllowedIpAttrs::Unspec(_) => "WGALLOWEDIP_A_UNSPEC",
+            WgAllowedIpAttrs::Family(_) => "WGALLOWEDIP_A_FAMILY",
+            WgAllowedIpAttrs::IpAddr(_) => "WGALLOWEDIP_A_IPADDR",
+            WgAllowedIpAttrs::Cidr(_) => "WGALLOWEDIP_A_CIDR_MASK",
+        };
+        dump_value(out, depth + 1, name, nla);
+    }
+}
+
+fn dump_header(out: &mut String, depth: usize, name: &str, nla: &dyn Nla) {
+    write!(
+        out,
+        "{:indent$}{} (kind {}, len {})",
+        "",
+        name,
+        nla.kind(),
+        nla.value_len(),
+        indent = depth * 2
+    )
+    .unwrap();
+}
+
+fn dump_nested(out: &mut String, depth: usize, name: &str, nla: &dyn Nla) {
+    dump_header(out, depth, name, nla);
+    out.push('\n');
+}
+
+fn dump_redacted(out: &mut String, depth: usize, name: &str, nla: &dyn Nla) {
+    dump_header(out, depth, name, nla);
+    out.push_str(": <redacted>\n");
+}
+
+fn dump_value(out: &mut String, depth: usize, name: &str, nla: &dyn Nla) {
+    dump_header(out, depth, name, nla);
+    let mut value = vec![0; nla.value_len()];
+    nla.emit_value(&mut value);
+    out.push(':');
+    for byte in value {
+        write!(out, " {:02x}", byte).unwrap();
+    }
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::constants::*;
+
+    #[test]
+    fn test_debug_dump() {
+        let wg = Wireguard {
+            cmd: WireguardCmd::SetDevice,
+            nlas: vec![
+                WgDeviceAttrs::IfName("wg0".to_string()),
+                WgDeviceAttrs::PrivateKey([0xaa; WG_KEY_LEN]),
+                WgDeviceAttrs::ListenPort(51820),
+                WgDeviceAttrs::Peers(vec![WgPeer(vec![
+                    WgPeerAttrs::PresharedKey([0xbb; WG_KEY_LEN]),
+                    WgPeerAttrs::AllowedIps(vec![WgAllowedIp(vec![
+                        WgAllowedIpAttrs::Cidr(24),
+                    ])]),
+                ])]),
+            ],
+        };
+        let dump = wg.debug_dump();
+        assert!(dump.starts_with("WG_CMD_SET_DEVICE\n"));
+        assert!(dump.contains("WGDEVICE_A_IFNAME (kind 2, len 4): 77 67 30 00"));
+        assert!(dump.contains(&format!(
+            "WGDEVICE_A_LISTEN_PORT (kind 6, len 2): {}",
+            51820u16
+                .to_ne_bytes()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(" ")
+        )));
+        assert!(dump
+            .contains("WGDEVICE_A_PRIVATE_KEY (kind 3, len 32): <redacted>"));
+        assert!(dump.contains(
+            "    WGPEER_A_PRESHARED_KEY (kind 2, len 32): <redacted>"
+        ));
+        assert!(dump
+            .contains("        WGALLOWEDIP_A_CIDR_MASK (kind 3, len 1): 18"));
+        assert!(!dump.contains("aa aa"));
+        assert!(!dump.contains("bb bb"));
+    }
+}
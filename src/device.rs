@@ -0,0 +1,713 @@
+// SPDX-License-Identifier: MIT
+
+use std::{
+    collections::BTreeMap,
+    convert::TryFrom,
+    net::{IpAddr, SocketAddr},
+    time::SystemTime,
+};
+
+use netlink_packet_core::{NetlinkMessage, NetlinkPayload};
+use netlink_packet_generic::GenlMessage;
+
+use crate::{
+    constants::*,
+    nlas::{
+        peer::mask, WgAllowedIp, WgAllowedIpAttrs, WgDeviceAttrs, WgPeer,
+        WgPeerAttrs,
+    },
+    SecretKey, WgError, WgKey, Wireguard, WireguardCmd,
+};
+
+// The size of the interface names of Linux, terminating NUL included.
+const IFNAMSIZ: usize = 16;
+
+/// A WireGuard interface with named fields, flattened from the
+/// attributes of a [`Wireguard`] message.
+///
+/// With the `serde` feature, the fields that are `None` are left out of
+/// the serialized form. The serialized fields are named after the netlink
+/// attributes, lowercased and without their prefix (`listen_port` for
+/// `WGDEVICE_A_LISTEN_PORT`, `persistent_keepalive_interval` for
+/// `WGPEER_A_PERSISTENT_KEEPALIVE_INTERVAL`, `allowedips`, `ipaddr`,
+/// `cidr_mask`...). The names of the Rust fields are accepted too when
+/// deserializing.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Device {
+    #[cfg_attr(
+        feature = "serde",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub ifindex: Option<u32>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub ifname: Option<String>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub private_key: Option<SecretKey>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub public_key: Option<WgKey>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub listen_port: Option<u16>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub fwmark: Option<u32>,
+    pub peers: Vec<Peer>,
+}
+
+/// A peer of a [`Device`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Peer {
+    pub public_key: WgKey,
+    #[cfg_attr(
+        feature = "serde",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub preshared_key: Option<SecretKey>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub endpoint: Option<SocketAddr>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            rename = "persistent_keepalive_interval",
+            alias = "persistent_keepalive",
+            skip_serializing_if = "Option::is_none"
+        )
+    )]
+    pub persistent_keepalive: Option<u16>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            rename = "last_handshake_time",
+            alias = "last_handshake",
+            skip_serializing_if = "Option::is_none"
+        )
+    )]
+    pub last_handshake: Option<SystemTime>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub rx_bytes: Option<u64>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub tx_bytes: Option<u64>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub protocol_version: Option<u32>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(rename = "allowedips", alias = "allowed_ips")
+    )]
+    pub allowed_ips: Vec<AllowedIp>,
+}
+
+/// An allowed IP of a [`Peer`]: `addr/cidr`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct AllowedIp {
+    #[cfg_attr(feature = "serde", serde(rename = "ipaddr", alias = "addr"))]
+    pub addr: IpAddr,
+    #[cfg_attr(feature = "serde", serde(rename = "cidr_mask", alias = "cidr"))]
+    pub cidr: u8,
+}
+
+/// The figures of a [`Device`] most dashboards show, see
+/// [`Device::summary`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct DeviceSummary {
+    #[cfg_attr(
+        feature = "serde",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub ifname: Option<String>,
+    /// The public key of the device in base64.
+    #[cfg_attr(
+        feature = "serde",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub public_key: Option<String>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub listen_port: Option<u16>,
+    pub peer_count: usize,
+    /// The bytes received from all the peers, saturating at `u64::MAX`.
+    pub total_rx: u64,
+    /// The bytes sent to all the peers, saturating at `u64::MAX`.
+    pub total_tx: u64,
+}
+
+impl Device {
+    pub fn summary(&self) -> DeviceSummary {
+        DeviceSummary {
+            ifname: self.ifname.clone(),
+            public_key: self.public_key.map(|key| key.to_base64()),
+            listen_port: self.listen_port,
+            peer_count: self.peers.len(),
+            total_rx: self
+                .peers
+                .iter()
+                .filter_map(|peer| peer.rx_bytes)
+                .fold(0, u64::saturating_add),
+            total_tx: self
+                .peers
+                .iter()
+                .filter_map(|peer| peer.tx_bytes)
+                .fold(0, u64::saturating_add),
+        }
+    }
+
+    /// Check the configuration before turning it into a message, e.g. with
+    /// [`Wireguard::reset_to`], and report every problem found:
+    ///
+    /// - the interface name must be one Linux accepts
+    ///   ([`WgError::InvalidIfName`]),
+    /// - the prefix length of the allowed IPs must fit their address
+    ///   ([`WgError::InvalidCidr`]),
+    /// - a network, compared with its host bits cleared, must not be an
+    ///   allowed IP of two peers ([`WgError::AllowedIpConflict`]).
+    ///
+    /// Keys are always 32 bytes long in a [`WgKey`], so they need no check.
+    pub fn validate(&self) -> Result<(), Vec<WgError>> {
+        let mut issues = Vec::new();
+        if let Some(name) = &self.ifname {
+            if !is_valid_ifname(name) {
+                issues.push(WgError::InvalidIfName(name.clone()));
+            }
+        }
+        let mut owners = BTreeMap::new();
+        for peer in &self.peers {
+            for ip in &peer.allowed_ips {
+                let max = if ip.addr.is_ipv4() { 32 } else { 128 };
+                if ip.cidr > max {
+                    issues.push(WgError::InvalidCidr {
+                        addr: ip.addr,
+                        cidr: ip.cidr,
+                    });
+                    continue;
+                }
+                let addr = mask(ip.addr, ip.cidr);
+                let owner =
+                    *owners.entry((addr, ip.cidr)).or_insert(peer.public_key);
+                if owner != peer.public_key {
+                    issues.push(WgError::AllowedIpConflict {
+                        addr,
+                        cidr: ip.cidr,
+                        first_peer: owner.into(),
+                        second_peer: peer.public_key.into(),
+                    });
+                }
+            }
+        }
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(issues)
+        }
+    }
+}
+
+// The checks of `dev_valid_name` in the kernel.
+fn is_valid_ifname(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() < IFNAMSIZ
+        && name != "."
+        && name != ".."
+        && !name
+            .chars()
+            .any(|c| c == '/' || c == ':' || c.is_whitespace())
+}
+
+/// Flatten the attributes of a message. When an attribute is repeated, the
+/// last one wins, like in the kernel. Peers without a public key and
+/// allowed IPs without an address or prefix length are skipped.
+impl From<&Wireguard> for Device {
+    fn from(wg: &Wireguard) -> Self {
+        let mut device = Device::default();
+        for nla in &wg.nlas {
+            match nla {
+                WgDeviceAttrs::IfIndex(index) => device.ifindex = Some(*index),
+                WgDeviceAttrs::IfName(name) => {
+                    device.ifname = Some(name.clone())
+                }
+                WgDeviceAttrs::PrivateKey(key) => {
+                    device.private_key = Some(key.into())
+                }
+                WgDeviceAttrs::PublicKey(key) => {
+                    device.public_key = Some(key.into())
+                }
+                WgDeviceAttrs::ListenPort(port) => {
+                    device.listen_port = Some(*port)
+                }
+                WgDeviceAttrs::Fwmark(fwmark) => device.fwmark = Some(*fwmark),
+                WgDeviceAttrs::Peers(peers) => {
+                    device.peers.extend(peers.iter().filter_map(peer))
+                }
+                WgDeviceAttrs::Unspec(_) | WgDeviceAttrs::Flags(_) => (),
+            }
+        }
+        device
+    }
+}
+
+/// Flatten the `Wireguard` payload of a received message, failing if the
+/// message is an error, an acknowledgment or the end of a dump.
+impl TryFrom<&NetlinkMessage<GenlMessage<Wireguard>>> for Device {
+    type Error = WgError;
+
+    fn try_from(
+        msg: &NetlinkMessage<GenlMessage<Wireguard>>,
+    ) -> Result<Self, Self::Error> {
+        match &msg.payload {
+            NetlinkPayload::InnerMessage(genlmsg) => {
+                Ok(Device::from(&genlmsg.payload))
+            }
+            _ => Err(WgError::UnexpectedPayload {
+                message_type: msg.header.message_type,
+            }),
+        }
+    }
+}
+
+fn peer(peer: &WgPeer) -> Option<Peer> {
+    let mut out = Peer {
+        public_key: peer.public_key()?.into(),
+        ..Default::default()
+    };
+    for nla in peer.iter() {
+        match nla {
+            WgPeerAttrs::PresharedKey(key) => {
+                out.preshared_key = Some(key.into())
+            }
+            WgPeerAttrs::Endpoint(endpoint) => out.endpoint = Some(*endpoint),
+            WgPeerAttrs::PersistentKeepalive(interval) => {
+                out.persistent_keepalive = Some(*interval)
+            }
+            WgPeerAttrs::LastHandshake(time) => {
+                out.last_handshake = Some(*time)
+            }
+            WgPeerAttrs::RxBytes(bytes) => out.rx_bytes = Some(*bytes),
+            WgPeerAttrs::TxBytes(bytes) => out.tx_bytes = Some(*bytes),
+            WgPeerAttrs::ProtocolVersion(version) => {
+                out.protocol_version = Some(*version)
+            }
+            WgPeerAttrs::AllowedIps(ips) => {
+                out.allowed_ips.extend(ips.iter().filter_map(allowed_ip))
+            }
+            WgPeerAttrs::Unspec(_)
+            | WgPeerAttrs::PublicKey(_)
+            | WgPeerAttrs::Flags(_) => (),
+        }
+    }
+    Some(out)
+}
+
+fn allowed_ip(allowed_ip: &WgAllowedIp) -> Option<AllowedIp> {
+    let addr = allowed_ip.iter().find_map(|nla| match nla {
+        WgAllowedIpAttrs::IpAddr(addr) => Some(*addr),
+        _ => None,
+    })?;
+    let cidr = allowed_ip.iter().find_map(|nla| match nla {
+        WgAllowedIpAttrs::Cidr(cidr) => Some(*cidr),
+        _ => None,
+    })?;
+    Some(AllowedIp { addr, cidr })
+}
+
+impl Wireguard {
+    /// The `SetDevice` message replacing the whole configuration of an
+    /// interface with `config`, like `wg setconf` does.
+    ///
+    /// The message carries `WGDEVICE_F_REPLACE_PEERS`: every existing
+    /// peer missing from `config` is removed, and the peers of `config`
+    /// carry `WGPEER_F_REPLACE_ALLOWEDIPS`. The interface is identified by
+    /// `config.ifname`, or by `config.ifindex` when it has no name. The
+    /// public key and the peer statistics are read-only and not sent.
+    ///
+    /// Like `wg setconf`, a missing private key, listen port or fwmark
+    /// leaves the current value of the interface untouched.
+    pub fn reset_to(config: &Device) -> Wireguard {
+        let mut nlas = Vec::new();
+        match (&config.ifname, config.ifindex) {
+            (Some(name), _) => nlas.push(WgDeviceAttrs::IfName(name.clone())),
+            (None, Some(index)) => nlas.push(WgDeviceAttrs::IfIndex(index)),
+            (None, None) => (),
+        }
+        if let Some(key) = &config.private_key {
+            nlas.push(WgDeviceAttrs::PrivateKey((*key.expose_secret()).into()));
+        }
+        if let Some(port) = config.listen_port {
+            nlas.push(WgDeviceAttrs::ListenPort(port));
+        }
+        if let Some(fwmark) = config.fwmark {
+            nlas.push(WgDeviceAttrs::Fwmark(fwmark));
+        }
+        nlas.push(WgDeviceAttrs::Flags(WGDEVICE_F_REPLACE_PEERS));
+        let peers = config
+            .peers
+            .iter()
+            .map(|peer| {
+                let mut nlas = vec![
+                    WgPeerAttrs::PublicKey(peer.public_key.into()),
+                    WgPeerAttrs::Flags(WGPEER_F_REPLACE_ALLOWEDIPS),
+                ];
+                if let Some(key) = &peer.preshared_key {
+                    nlas.push(WgPeerAttrs::PresharedKey(
+                        (*key.expose_secret()).into(),
+                    ));
+                }
+                if let Some(endpoint) = peer.endpoint {
+                    nlas.push(WgPeerAttrs::Endpoint(endpoint));
+                }
+                if let Some(interval) = peer.persistent_keepalive {
+                    nlas.push(WgPeerAttrs::PersistentKeepalive(interval));
+                }
+                if let Some(version) = peer.protocol_version {
+                    nlas.push(WgPeerAttrs::ProtocolVersion(version));
+                }
+                nlas.push(WgPeerAttrs::AllowedIps(
+                    peer.allowed_ips
+                        .iter()
+                        .map(|ip| WgAllowedIp::new_unchecked(ip.addr, ip.cidr))
+                        .collect(),
+                ));
+                WgPeer(nlas)
+            })
+            .collect();
+        nlas.push(WgDeviceAttrs::Peers(peers));
+        Wireguard {
+            cmd: WireguardCmd::SetDevice,
+            nlas,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use netlink_packet_core::{NetlinkHeader, NLMSG_DONE};
+
+    use super::*;
+
+    fn config() -> Device {
+        Device {
+            ifname: Some("wg0".to_string()),
+            private_key: Some([0xaa; WG_KEY_LEN].into()),
+            listen_port: Some(51820),
+            peers: vec![
+                Peer {
+                    public_key: WgKey::new([0x01; WG_KEY_LEN]),
+                    preshared_key: Some([0x02; WG_KEY_LEN].into()),
+                    endpoint: Some("192.168.1.1:51820".parse().unwrap()),
+                    persistent_keepalive: Some(25),
+                    allowed_ips: vec![
+                        AllowedIp {
+                            addr: [10, 0, 0, 0].into(),
+                            cidr: 24,
+                        },
+                        AllowedIp {
+                            addr: "fd00::".parse().unwrap(),
+                            cidr: 64,
+                        },
+                    ],
+                    ..Default::default()
+                },
+                Peer {
+                    public_key: WgKey::new([0x03; WG_KEY_LEN]),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_device_from_wireguard() {
+        let wg = Wireguard {
+            cmd: WireguardCmd::GetDevice,
+            nlas: vec![
+                WgDeviceAttrs::IfIndex(3),
+                WgDeviceAttrs::IfName("wg0".to_string()),
+                WgDeviceAttrs::PublicKey([0xbb; WG_KEY_LEN]),
+                WgDeviceAttrs::Fwmark(0),
+                WgDeviceAttrs::Peers(vec![
+                    WgPeer(vec![WgPeerAttrs::RxBytes(1)]),
+                    WgPeer(vec![
+                        WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN]),
+                        WgPeerAttrs::RxBytes(1),
+                        WgPeerAttrs::TxBytes(2),
+                        WgPeerAttrs::LastHandshake(SystemTime::UNIX_EPOCH),
+                        WgPeerAttrs::AllowedIps(vec![
+                            WgAllowedIp(vec![WgAllowedIpAttrs::Cidr(8)]),
+                            WgAllowedIp::new_unchecked([10, 0, 0, 0].into(), 8),
+                        ]),
+                    ]),
+                ]),
+                WgDeviceAttrs::Fwmark(0x1234),
+            ],
+        };
+        assert_eq!(
+            Device::from(&wg),
+            Device {
+                ifindex: Some(3),
+                ifname: Some("wg0".to_string()),
+                public_key: Some(WgKey::new([0xbb; WG_KEY_LEN])),
+                fwmark: Some(0x1234),
+                peers: vec![Peer {
+                    public_key: WgKey::new([0x01; WG_KEY_LEN]),
+                    rx_bytes: Some(1),
+                    tx_bytes: Some(2),
+                    last_handshake: Some(SystemTime::UNIX_EPOCH),
+                    allowed_ips: vec![AllowedIp {
+                        addr: [10, 0, 0, 0].into(),
+                        cidr: 8
+                    }],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_device_from_netlink_message() {
+        let genlmsg = GenlMessage::from_payload(Wireguard::reset_to(&config()));
+        let mut msg = NetlinkMessage::from(genlmsg);
+        msg.finalize();
+        let mut buf = vec![0; msg.buffer_len()];
+        msg.serialize(&mut buf);
+        let msg = NetlinkMessage::<GenlMessage<Wireguard>>::deserialize(&buf)
+            .unwrap();
+        assert_eq!(Device::try_from(&msg), Ok(config()));
+
+        let mut done = NetlinkMessage::<GenlMessage<Wireguard>>::new(
+            NetlinkHeader::default(),
+            NetlinkPayload::Done(Default::default()),
+        );
+        done.finalize();
+        assert_eq!(
+            Device::try_from(&done),
+            Err(WgError::UnexpectedPayload {
+                message_type: NLMSG_DONE
+            })
+        );
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut device = config();
+        device.public_key = Some(WgKey::new([0x01; WG_KEY_LEN]));
+        device.peers[0].rx_bytes = Some(1024);
+        device.peers[0].tx_bytes = Some(u64::MAX);
+        device.peers[1].rx_bytes = Some(2048);
+        device.peers[1].tx_bytes = Some(1);
+        assert_eq!(
+            device.summary(),
+            DeviceSummary {
+                ifname: Some("wg0".to_string()),
+                public_key: Some(
+                    "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=".to_string()
+                ),
+                listen_port: Some(51820),
+                peer_count: 2,
+                total_rx: 3072,
+                total_tx: u64::MAX,
+            }
+        );
+        assert_eq!(Device::default().summary(), DeviceSummary::default());
+    }
+
+    #[test]
+    fn test_reset_to() {
+        let config = config();
+        let wg = Wireguard::reset_to(&config);
+        assert_eq!(wg.cmd, WireguardCmd::SetDevice);
+        assert_eq!(
+            wg.nlas
+                .iter()
+                .filter(|nla| matches!(nla, WgDeviceAttrs::Flags(_)))
+                .collect::<Vec<_>>(),
+            vec![&WgDeviceAttrs::Flags(WGDEVICE_F_REPLACE_PEERS)]
+        );
+        for peer in wg.peers() {
+            assert_eq!(peer.raw_flags(), Some(WGPEER_F_REPLACE_ALLOWEDIPS));
+        }
+        assert_eq!(Device::from(&wg), config);
+        assert_eq!(wg.validate_set(), Ok(()));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serialize_skips_none() {
+        let value = serde_json::to_value(config()).unwrap();
+        let device = value.as_object().unwrap();
+        assert!(device.contains_key("listen_port"));
+        for absent in ["ifindex", "public_key", "fwmark"] {
+            assert!(!device.contains_key(absent), "{}", absent);
+        }
+        let peers = device["peers"].as_array().unwrap();
+        let peer = peers[1].as_object().unwrap();
+        assert_eq!(
+            peer.keys().collect::<Vec<_>>(),
+            vec!["allowedips", "public_key"]
+        );
+        assert!(peers[0].as_object().unwrap().contains_key("preshared_key"));
+        assert!(!value.to_string().contains("null"));
+
+        let parsed: Device = serde_json::from_value(value).unwrap();
+        assert_eq!(parsed, config());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_kernel_names() {
+        let mut device = config();
+        device.peers[0].last_handshake = Some(SystemTime::UNIX_EPOCH);
+        device.peers[0].rx_bytes = Some(1);
+        device.peers[0].tx_bytes = Some(2);
+        device.peers[0].protocol_version = Some(1);
+        let value = serde_json::to_value(&device).unwrap();
+        let keys = |value: &serde_json::Value| {
+            value
+                .as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            keys(&value),
+            ["ifname", "listen_port", "peers", "private_key"]
+        );
+        let peer = &value["peers"][0];
+        assert_eq!(
+            keys(peer),
+            [
+                "allowedips",
+                "endpoint",
+                "last_handshake_time",
+                "persistent_keepalive_interval",
+                "preshared_key",
+                "protocol_version",
+                "public_key",
+                "rx_bytes",
+                "tx_bytes",
+            ]
+        );
+        assert_eq!(keys(&peer["allowedips"][0]), ["cidr_mask", "ipaddr"]);
+
+        // The field names are still accepted.
+        let json = value
+            .to_string()
+            .replace("persistent_keepalive_interval", "persistent_keepalive")
+            .replace("allowedips", "allowed_ips")
+            .replace("ipaddr", "addr")
+            .replace("cidr_mask", "cidr");
+        let parsed: Device = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, device);
+    }
+
+    #[test]
+    fn test_debug_hides_secrets() {
+        let device = config();
+        let private_key = format!("{:?}", [0xaa_u8; WG_KEY_LEN]);
+        let preshared_key = format!("{:?}", [0x02_u8; WG_KEY_LEN]);
+        for debug in [
+            format!("{:?}", device),
+            format!("{:#?}", device.peers[0]),
+            format!("{:?}", Wireguard::reset_to(&device)),
+        ] {
+            assert!(!debug.contains(&private_key[1..20]), "{}", debug);
+            assert!(!debug.contains(&preshared_key[1..20]), "{}", debug);
+            assert!(debug.contains("(hidden)"), "{}", debug);
+        }
+        let debug = format!("{:?}", Wireguard::reset_to(&device));
+        assert!(debug.contains("PrivateKey((hidden))"));
+        assert!(debug.contains("PresharedKey((hidden))"));
+        // Public keys are still shown.
+        assert!(debug.contains(&format!("{:?}", [0x01_u8; WG_KEY_LEN])));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(config().validate(), Ok(()));
+
+        let mut device = config();
+        device.ifname = Some("wg/0".to_string());
+        device.peers[1].allowed_ips = vec![
+            // Same network as the first peer, written with host bits.
+            AllowedIp {
+                addr: [10, 0, 0, 7].into(),
+                cidr: 24,
+            },
+            AllowedIp {
+                addr: [10, 0, 0, 0].into(),
+                cidr: 33,
+            },
+            // Only overlapping, not conflicting.
+            AllowedIp {
+                addr: "fd00::".parse().unwrap(),
+                cidr: 48,
+            },
+        ];
+        let issues = device.validate().unwrap_err();
+        assert_eq!(
+            issues,
+            vec![
+                WgError::InvalidIfName("wg/0".to_string()),
+                WgError::AllowedIpConflict {
+                    addr: [10, 0, 0, 0].into(),
+                    cidr: 24,
+                    first_peer: [0x01; WG_KEY_LEN],
+                    second_peer: [0x03; WG_KEY_LEN],
+                },
+                WgError::InvalidCidr {
+                    addr: [10, 0, 0, 0].into(),
+                    cidr: 33,
+                },
+            ]
+        );
+        assert_eq!(
+            issues[1].to_string(),
+            "the allowed IP 10.0.0.0/24 is shared by peers 01010101.. and \
+             03030303.."
+        );
+    }
+
+    #[test]
+    fn test_is_valid_ifname() {
+        for name in ["wg0", "wg-home.1", "012345678901234"] {
+            assert!(is_valid_ifname(name), "{}", name);
+        }
+        for name in ["", ".", "..", "wg 0", "wg:0", "a/b", "0123456789012345"] {
+            assert!(!is_valid_ifname(name), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,189 @@
+// SPDX-License-Identifier: MIT
+
+//! The base64 encoding of the keys, as written by `wg(8)`: the standard
+//! alphabet with padding.
+//!
+//! With the `base64` feature, the `base64` crate does the work. Without
+//! it, a small implementation handling the same inputs is used instead,
+//! so that this crate doesn't depend on a version of `base64` that
+//! conflicts with the rest of the dependency tree.
+
+#[cfg(feature = "base64")]
+use crate::WgError;
+
+#[cfg(feature = "base64")]
+pub(crate) fn encode(bytes: &[u8]) -> String {
+    base64::encode(bytes)
+}
+
+#[cfg(feature = "base64")]
+pub(crate) fn decode(input: &str) -> Result<Vec<u8>, WgError> {
+    base64::decode(input).map_err(|e| WgError::InvalidBase64(e.to_string()))
+}
+
+#[cfg(not(feature = "base64"))]
+pub(crate) use internal::{decode, encode};
+
+#[cfg_attr(feature = "base64", allow(dead_code))]
+mod internal {
+    use crate::WgError;
+
+    const ALPHABET: &[u8; 64] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    const PAD: u8 = b'=';
+
+    pub(crate) fn encode(bytes: &[u8]) -> String {
+        let mut out = String::with_capacity((bytes.len() + 2) / 3 * 4);
+        for chunk in bytes.chunks(3) {
+            let b = [
+                chunk[0],
+                chunk.get(1).copied().unwrap_or(0),
+                chunk.get(2).copied().unwrap_or(0),
+            ];
+            let n =
+                u32::from(b[0]) << 16 | u32::from(b[1]) << 8 | u32::from(b[2]);
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    let index = (n >> (18 - 6 * i)) & 0x3f;
+                    out.push(ALPHABET[index as usize] as char);
+                } else {
+                    out.push(PAD as char);
+                }
+            }
+        }
+        out
+    }
+
+    // Like the `base64` crate, the padding may be left out but must not
+    // be longer than needed.
+    pub(crate) fn decode(input: &str) -> Result<Vec<u8>, WgError> {
+        let invalid = |message: String| WgError::InvalidBase64(message);
+        let symbols = input.trim_end_matches(PAD as char).as_bytes();
+        let padding = input.len() - symbols.len();
+        let missing = (4 - symbols.len() % 4) % 4;
+        if symbols.len() % 4 == 1 || padding > missing {
+            return Err(invalid(format!("invalid length: {}", input.len())));
+        }
+        let mut out = Vec::with_capacity(symbols.len() / 4 * 3 + 2);
+        for (i, chunk) in symbols.chunks(4).enumerate() {
+            let mut n = 0u32;
+            for (j, &c) in chunk.iter().enumerate() {
+                let value = symbol_value(c).ok_or_else(|| {
+                    invalid(format!(
+                        "invalid byte {:#04x} at offset {}",
+                        c,
+                        i * 4 + j
+                    ))
+                })?;
+                n |= u32::from(value) << (18 - 6 * j);
+            }
+            let len = chunk.len() * 6 / 8;
+            // The bits of the last symbol that don't make a whole byte
+            // must be zero, otherwise several inputs decode to the same
+            // bytes.
+            if n & (0x00ff_ffff >> (8 * len)) != 0 {
+                return Err(invalid(format!(
+                    "invalid last symbol at offset {}",
+                    i * 4 + chunk.len() - 1
+                )));
+            }
+            out.extend_from_slice(&n.to_be_bytes()[1..1 + len]);
+        }
+        Ok(out)
+    }
+
+    fn symbol_value(c: u8) -> Option<u8> {
+        match c {
+            b'A'..=b'Z' => Some(c - b'A'),
+            b'a'..=b'z' => Some(c - b'a' + 26),
+            b'0'..=b'9' => Some(c - b'0' + 52),
+            b'+' => Some(62),
+            b'/' => Some(63),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{constants::WG_KEY_LEN, WgError};
+
+    // RFC 4648 section 10.
+    const VECTORS: &[(&str, &str)] = &[
+        ("", ""),
+        ("f", "Zg=="),
+        ("fo", "Zm8="),
+        ("foo", "Zm9v"),
+        ("foob", "Zm9vYg=="),
+        ("fooba", "Zm9vYmE="),
+        ("foobar", "Zm9vYmFy"),
+    ];
+
+    const KEY: &str = "8bdQrVLqiw3ZoHCucNh1YfH0iCWuyStniRr8t7H24Fk=";
+
+    // The padding can be left out.
+    const UNPADDED: &[(&str, &str)] =
+        &[("f", "Zg="), ("f", "Zg"), ("fo", "Zm8"), ("foob", "Zm9vYg")];
+
+    const INVALID: &[&str] = &[
+        "Z",
+        "Zg===",
+        "Zm9v=",
+        "====",
+        "Z===",
+        "Zm9v Yg==",
+        "Zm9vYg-_",
+        "Zg==Zm9v",
+        "Zh==",
+        "Zm9=",
+    ];
+
+    fn check(
+        encode: fn(&[u8]) -> String,
+        decode: fn(&str) -> Result<Vec<u8>, WgError>,
+    ) {
+        for (bytes, encoded) in VECTORS {
+            assert_eq!(encode(bytes.as_bytes()), *encoded);
+            assert_eq!(decode(encoded).unwrap(), bytes.as_bytes());
+        }
+        for (bytes, encoded) in UNPADDED {
+            assert_eq!(decode(encoded).unwrap(), bytes.as_bytes());
+        }
+        let key = decode(KEY).unwrap();
+        assert_eq!(key.len(), WG_KEY_LEN);
+        assert_eq!(&key[..4], &[0xf1, 0xb7, 0x50, 0xad]);
+        assert_eq!(encode(&key), KEY);
+        let all: Vec<u8> = (0..=255).collect();
+        assert_eq!(decode(&encode(&all)).unwrap(), all);
+        for input in INVALID {
+            assert!(
+                matches!(decode(input), Err(WgError::InvalidBase64(_))),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_internal() {
+        check(internal::encode, internal::decode);
+    }
+
+    #[test]
+    fn test_selected() {
+        check(encode, decode);
+    }
+
+    #[cfg(feature = "base64")]
+    #[test]
+    fn test_internal_matches_crate() {
+        let all: Vec<u8> = (0..=255).collect();
+        for len in 0..all.len() {
+            let bytes = &all[len..];
+            let encoded = base64::encode(bytes);
+            assert_eq!(internal::encode(bytes), encoded);
+            assert_eq!(internal::decode(&encoded).unwrap(), bytes);
+        }
+    }
+}
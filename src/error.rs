@@ -0,0 +1,257 @@
+// SPDX-License-Identifier: MIT
+
+use std::{fmt, net::IpAddr, panic::Location};
+
+use netlink_packet_utils::DecodeError;
+
+use crate::{
+    constants::{
+        WGALLOWEDIP_A_CIDR_MASK, WGALLOWEDIP_A_FAMILY, WGALLOWEDIP_A_IPADDR,
+        WG_KEY_LEN,
+    },
+    WireguardCmd,
+};
+
+/// Errors reported when checking a [`Wireguard`](crate::Wireguard) message
+/// before it is sent to the kernel.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WgError {
+    /// The message has neither `WGDEVICE_A_IFINDEX` nor
+    /// `WGDEVICE_A_IFNAME`.
+    MissingIdentity,
+    /// The message has both `WGDEVICE_A_IFINDEX` and `WGDEVICE_A_IFNAME`,
+    /// which the kernel rejects.
+    AmbiguousIdentity,
+    /// A `SetDevice` message has no attributes at all: there is nothing to
+    /// set, and no device to set it on.
+    EmptySetDevice,
+    /// The device carries `WGDEVICE_F_REPLACE_PEERS` and a peer carries
+    /// `WGPEER_F_REMOVE_ME`. Replacing the peers already drops every peer
+    /// that isn't listed, so removing one is redundant.
+    RedundantPeerRemoval {
+        public_key: Option<[u8; WG_KEY_LEN]>,
+    },
+    /// A peer has no `WGPEER_A_PUBLIC_KEY`, which the kernel needs to
+    /// know which peer to change.
+    MissingPeerPublicKey,
+    /// The message carries more peers than the limit given to
+    /// [`Wireguard::check_peer_limit`](crate::Wireguard::check_peer_limit).
+    TooManyPeers { count: usize, max: usize },
+    /// A string isn't valid base64.
+    InvalidBase64(String),
+    /// A key doesn't have the 32 bytes of a Curve25519 key.
+    BadKeyLength { got: usize },
+    /// A string isn't an allowed IP of the form `addr/cidr` or `addr`.
+    InvalidAllowedIp(String),
+    /// The prefix length is longer than the address (32 bits for IPv4, 128
+    /// bits for IPv6).
+    InvalidCidr { addr: IpAddr, cidr: u8 },
+    /// The `WGALLOWEDIP_A_FAMILY` of an allowed IP is not the family of
+    /// its address, which the kernel rejects.
+    AllowedIpFamilyMismatch { addr: IpAddr, family: u16 },
+    /// The same network is an allowed IP of two peers. The kernel keeps
+    /// it for the last one only.
+    AllowedIpConflict {
+        addr: IpAddr,
+        cidr: u8,
+        first_peer: [u8; WG_KEY_LEN],
+        second_peer: [u8; WG_KEY_LEN],
+    },
+    /// The interface name isn't one Linux accepts: it must be 1 to 15
+    /// bytes long, must not be `.` or `..`, and must not contain `/`, `:`
+    /// or whitespace.
+    InvalidIfName(String),
+    /// An allowed IP lacks one of the `WGALLOWEDIP_A_FAMILY`,
+    /// `WGALLOWEDIP_A_IPADDR` and `WGALLOWEDIP_A_CIDR_MASK` attributes the
+    /// kernel requires. `kind` is the missing `WGALLOWEDIP_A_*`.
+    MissingAllowedIpAttr { kind: u16 },
+    /// A peer asks for a `WGPEER_A_PROTOCOL_VERSION` the kernel doesn't
+    /// implement.
+    UnsupportedProtocolVersion { version: u32 },
+    /// The device private key isn't clamped, which suggests a public key
+    /// was put in its place.
+    UnclampedPrivateKey,
+    /// The device public key is not the one derived from its private key.
+    KeyMismatch,
+    /// The bytes are not a `sockaddr_in` or a `sockaddr_in6`.
+    InvalidSocketAddr(String),
+    /// A buffer is shorter than the frame it should hold.
+    TruncatedFrame { expected: usize, got: usize },
+    /// A netlink message doesn't carry a WireGuard payload, e.g. it is an
+    /// error or the end of a dump.
+    UnexpectedPayload { message_type: u16 },
+    /// The message doesn't carry the command the caller expected, see
+    /// [`Wireguard::expect_command`](crate::Wireguard::expect_command).
+    UnexpectedCommand {
+        expected: WireguardCmd,
+        got: WireguardCmd,
+    },
+    /// The bytes could not be parsed as a WireGuard message.
+    Decode(String),
+    /// Same as [`WgError::Decode`], with the location of the code that
+    /// asked for the parsing.
+    DecodeAt {
+        message: String,
+        location: &'static Location<'static>,
+    },
+    /// A configuration in the format of `wg setconf` could not be parsed.
+    InvalidConf { line: usize, message: String },
+    /// An input of [`Wireguard::from_kv`](crate::Wireguard::from_kv) could
+    /// not be parsed.
+    InvalidKv(String),
+    /// Every problem found by [`Wireguard::validate`](crate::Wireguard).
+    Multiple(Vec<WgError>),
+}
+
+impl fmt::Display for WgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WgError::MissingIdentity => write!(
+                f,
+                "neither WGDEVICE_A_IFINDEX nor WGDEVICE_A_IFNAME is set"
+            ),
+            WgError::AmbiguousIdentity => write!(
+                f,
+                "both WGDEVICE_A_IFINDEX and WGDEVICE_A_IFNAME are set"
+            ),
+            WgError::EmptySetDevice => {
+                write!(f, "the SetDevice message has no attributes")
+            }
+            WgError::RedundantPeerRemoval { public_key } => {
+                write!(f, "peer ")?;
+                fmt_key(f, public_key.as_ref())?;
+                write!(
+                    f,
+                    " is flagged WGPEER_F_REMOVE_ME while the device is \
+                     flagged WGDEVICE_F_REPLACE_PEERS"
+                )
+            }
+            WgError::MissingPeerPublicKey => {
+                write!(f, "a peer has no WGPEER_A_PUBLIC_KEY")
+            }
+            WgError::TooManyPeers { count, max } => write!(
+                f,
+                "too many peers: {} peers for a limit of {} ({} over)",
+                count,
+                max,
+                count.saturating_sub(*max)
+            ),
+            WgError::InvalidBase64(message) => {
+                write!(f, "invalid base64: {}", message)
+            }
+            WgError::BadKeyLength { got } => write!(
+                f,
+                "invalid key length: {} bytes (expected {})",
+                got, WG_KEY_LEN
+            ),
+            WgError::InvalidAllowedIp(s) => {
+                write!(f, "invalid allowed IP: {}", s)
+            }
+            WgError::InvalidCidr { addr, cidr } => {
+                write!(f, "invalid prefix length for {}: /{}", addr, cidr)
+            }
+            WgError::AllowedIpFamilyMismatch { addr, family } => write!(
+                f,
+                "the allowed IP {} has the address family {}",
+                addr, family
+            ),
+            WgError::AllowedIpConflict {
+                addr,
+                cidr,
+                first_peer,
+                second_peer,
+            } => {
+                write!(
+                    f,
+                    "the allowed IP {}/{} is shared by peers ",
+                    addr, cidr
+                )?;
+                fmt_key(f, Some(first_peer))?;
+                write!(f, " and ")?;
+                fmt_key(f, Some(second_peer))
+            }
+            WgError::InvalidIfName(name) => {
+                write!(f, "invalid interface name: {:?}", name)
+            }
+            WgError::MissingAllowedIpAttr { kind } => {
+                let name = match *kind {
+                    WGALLOWEDIP_A_FAMILY => "WGALLOWEDIP_A_FAMILY",
+                    WGALLOWEDIP_A_IPADDR => "WGALLOWEDIP_A_IPADDR",
+                    WGALLOWEDIP_A_CIDR_MASK => "WGALLOWEDIP_A_CIDR_MASK",
+                    _ => "attribute",
+                };
+                write!(f, "an allowed IP has no {}", name)
+            }
+            WgError::UnsupportedProtocolVersion { version } => {
+                write!(f, "unsupported peer protocol version: {}", version)
+            }
+            WgError::UnclampedPrivateKey => write!(
+                f,
+                "the private key is not clamped, it may be a public key"
+            ),
+            WgError::KeyMismatch => {
+                write!(f, "the public key doesn't match the private key")
+            }
+            WgError::InvalidSocketAddr(message) => {
+                write!(f, "invalid socket address: {}", message)
+            }
+            WgError::TruncatedFrame { expected, got } => write!(
+                f,
+                "truncated frame: expected {} bytes, got {}",
+                expected, got
+            ),
+            WgError::UnexpectedPayload { message_type } => write!(
+                f,
+                "the netlink message of type {} has no WireGuard payload",
+                message_type
+            ),
+            WgError::UnexpectedCommand { expected, got } => write!(
+                f,
+                "unexpected command {:?}, expected {:?}",
+                got, expected
+            ),
+            WgError::Decode(msg) => write!(f, "{}", msg),
+            WgError::DecodeAt { message, location } => {
+                write!(f, "{}: {}", location, message)
+            }
+            WgError::InvalidConf { line, message } => {
+                write!(f, "invalid configuration, line {}: {}", line, message)
+            }
+            WgError::InvalidKv(message) => {
+                write!(f, "invalid key=value input: {}", message)
+            }
+            WgError::Multiple(errors) => {
+                for (i, e) in errors.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{}", e)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for WgError {}
+
+impl From<DecodeError> for WgError {
+    fn from(e: DecodeError) -> Self {
+        WgError::Decode(e.to_string())
+    }
+}
+
+fn fmt_key(
+    f: &mut fmt::Formatter<'_>,
+    key: Option<&[u8; WG_KEY_LEN]>,
+) -> fmt::Result {
+    match key {
+        Some(key) => {
+            for byte in &key[..4] {
+                write!(f, "{:02x}", byte)?;
+            }
+            write!(f, "..")
+        }
+        None => write!(f, "<no public key>"),
+    }
+}
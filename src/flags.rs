@@ -0,0 +1,104 @@
+// SPDX-License-Identifier: MIT
+
+use std::ops::{BitOr, BitOrAssign};
+
+use crate::constants::{
+    WGDEVICE_F_REPLACE_PEERS, WGPEER_F_REMOVE_ME, WGPEER_F_REPLACE_ALLOWEDIPS,
+    WGPEER_F_UPDATE_ONLY,
+};
+
+/// The `WGDEVICE_F_*` flags of a `SetDevice` message.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct WgDeviceFlags(u32);
+
+impl WgDeviceFlags {
+    /// Remove the peers missing from the message.
+    pub const REPLACE_PEERS: Self = Self(WGDEVICE_F_REPLACE_PEERS);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Flags from raw bits, keeping the ones this crate doesn't know.
+    pub const fn from_bits_retain(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for WgDeviceFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for WgDeviceFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl From<WgDeviceFlags> for u32 {
+    fn from(flags: WgDeviceFlags) -> Self {
+        flags.0
+    }
+}
+
+/// The `WGPEER_F_*` flags of a peer in a `SetDevice` message.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct WgPeerFlags(u32);
+
+impl WgPeerFlags {
+    /// Remove the peer from the device.
+    pub const REMOVE_ME: Self = Self(WGPEER_F_REMOVE_ME);
+    /// Replace the allowed IPs of the peer instead of adding to them.
+    pub const REPLACE_ALLOWEDIPS: Self = Self(WGPEER_F_REPLACE_ALLOWEDIPS);
+    /// Only change the peer if the device already has it.
+    pub const UPDATE_ONLY: Self = Self(WGPEER_F_UPDATE_ONLY);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Flags from raw bits, keeping the ones this crate doesn't know.
+    pub const fn from_bits_retain(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for WgPeerFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for WgPeerFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl From<WgPeerFlags> for u32 {
+    fn from(flags: WgPeerFlags) -> Self {
+        flags.0
+    }
+}
@@ -0,0 +1,126 @@
+// SPDX-License-Identifier: MIT
+
+use byteorder::{ByteOrder, NativeEndian};
+use netlink_packet_generic::{GenlBuffer, GenlHeader};
+use netlink_packet_utils::{Emitable, Parseable, ParseableParametrized};
+
+use crate::{WgError, Wireguard};
+
+const FRAME_HEADER_LEN: usize = 4;
+
+impl Wireguard {
+    /// Serialize the generic netlink header and the attributes of the
+    /// message: the payload of the netlink message, for callers framing
+    /// netlink messages themselves. See [`Wireguard::from_bytes`] for the
+    /// reverse.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let header = GenlHeader {
+            cmd: self.cmd.into(),
+            version: netlink_packet_generic::GenlFamily::version(self),
+        };
+        let mut buf = vec![0; header.buffer_len() + self.buffer_len()];
+        header.emit(&mut buf);
+        self.emit(&mut buf[header.buffer_len()..]);
+        buf
+    }
+
+    /// Parse a generic netlink header followed by the attributes of a
+    /// message, as written by [`Wireguard::to_bytes`] or found in the
+    /// payload of a netlink message from the kernel.
+    pub fn from_bytes(buf: &[u8]) -> Result<Wireguard, WgError> {
+        let genl = GenlBuffer::new_checked(buf)?;
+        let header = GenlHeader::parse(&genl)?;
+        Ok(Wireguard::parse_with_param(genl.payload(), header)?)
+    }
+
+    /// Serialize the message in a simple framed format meant for logging
+    /// captures: a `u32` length in native endianness followed by that
+    /// many bytes holding the generic netlink header and the attributes.
+    ///
+    /// Frames can be concatenated and read back one after the other with
+    /// [`Wireguard::read_framed`]. Like netlink itself, the format uses the
+    /// host endianness and is not portable across architectures.
+    pub fn to_framed(&self) -> Vec<u8> {
+        let payload = self.to_bytes();
+        let mut buf = vec![0; FRAME_HEADER_LEN];
+        NativeEndian::write_u32(&mut buf, payload.len() as u32);
+        buf.extend(payload);
+        buf
+    }
+
+    /// Read the first frame written by [`Wireguard::to_framed`] from `buf`,
+    /// returning the message and the number of bytes consumed.
+    pub fn read_framed(buf: &[u8]) -> Result<(Wireguard, usize), WgError> {
+        if buf.len() < FRAME_HEADER_LEN {
+            return Err(WgError::TruncatedFrame {
+                expected: FRAME_HEADER_LEN,
+                got: buf.len(),
+            });
+        }
+        let len = NativeEndian::read_u32(&buf[..FRAME_HEADER_LEN]) as usize;
+        let end = FRAME_HEADER_LEN + len;
+        if buf.len() < end {
+            return Err(WgError::TruncatedFrame {
+                expected: end,
+                got: buf.len(),
+            });
+        }
+        let wg = Wireguard::from_bytes(&buf[FRAME_HEADER_LEN..end])?;
+        Ok((wg, end))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        constants::*, nlas::WgDeviceAttrs, WgDeviceBuilder, WireguardCmd,
+    };
+
+    #[test]
+    fn test_framed_round_trip() {
+        let get = Wireguard {
+            cmd: WireguardCmd::GetDevice,
+            nlas: vec![WgDeviceAttrs::IfIndex(3)],
+        };
+        let set = WgDeviceBuilder::new()
+            .ifname("wg0")
+            .private_key([0xaa; WG_KEY_LEN])
+            .listen_port(51820)
+            .build();
+
+        let mut capture = get.to_framed();
+        capture.extend(set.to_framed());
+
+        let (first, len) = Wireguard::read_framed(&capture).unwrap();
+        assert_eq!(first, get);
+        let (second, len2) = Wireguard::read_framed(&capture[len..]).unwrap();
+        assert_eq!(second, set);
+        assert_eq!(len + len2, capture.len());
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let wg = WgDeviceBuilder::new()
+            .ifindex(3)
+            .listen_port(51820)
+            .peer(crate::WgPeerBuilder::new([0x01; WG_KEY_LEN]).build())
+            .build();
+        let bytes = wg.to_bytes();
+        assert_eq!(bytes[..4], [WG_CMD_SET_DEVICE, WG_GENL_VERSION, 0, 0]);
+        assert_eq!(Wireguard::from_bytes(&bytes).unwrap(), wg);
+        assert!(Wireguard::from_bytes(&bytes[..2]).is_err());
+    }
+
+    #[test]
+    fn test_read_truncated_frame() {
+        let frame = Wireguard::dump_all().to_framed();
+        assert_eq!(
+            Wireguard::read_framed(&frame[..frame.len() - 1]),
+            Err(WgError::TruncatedFrame {
+                expected: frame.len(),
+                got: frame.len() - 1,
+            })
+        );
+    }
+}
@@ -0,0 +1,108 @@
+// SPDX-License-Identifier: MIT
+
+use std::time::{Duration, SystemTime};
+
+use crate::{nlas::WgPeerAttrs, Wireguard};
+
+/// The number of peers of a device by how recent their last handshake is,
+/// see [`Wireguard::handshake_buckets`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HandshakeBuckets {
+    /// Peers whose last handshake is less than
+    /// [`HandshakeBuckets::ACTIVE_WINDOW`] old.
+    pub active: usize,
+    /// Peers whose last handshake is older.
+    pub idle: usize,
+    /// Peers that never completed a handshake, or whose
+    /// `WGPEER_A_LAST_HANDSHAKE_TIME` is missing.
+    pub never: usize,
+}
+
+impl HandshakeBuckets {
+    /// How recent a handshake must be for the peer to count as active:
+    /// 3 minutes, the time after which WireGuard rejects the session
+    /// keys (`Reject-After-Time`) if no new handshake happened.
+    pub const ACTIVE_WINDOW: Duration = Duration::from_secs(180);
+
+    /// The number of peers counted.
+    pub fn total(&self) -> usize {
+        self.active + self.idle + self.never
+    }
+}
+
+impl Wireguard {
+    /// Count the peers of a `GetDevice` reply by the age of their last
+    /// handshake at `now`. The kernel reports the epoch for a peer that
+    /// never completed a handshake. A handshake after `now`, e.g. after
+    /// the clock was moved back, counts as active.
+    pub fn handshake_buckets(&self, now: SystemTime) -> HandshakeBuckets {
+        let mut buckets = HandshakeBuckets::default();
+        for peer in self.peers() {
+            let handshake = peer.iter().find_map(|nla| match nla {
+                WgPeerAttrs::LastHandshake(time) => Some(*time),
+                _ => None,
+            });
+            match handshake {
+                None | Some(SystemTime::UNIX_EPOCH) => buckets.never += 1,
+                Some(time) => match now.duration_since(time) {
+                    Ok(age) if age >= HandshakeBuckets::ACTIVE_WINDOW => {
+                        buckets.idle += 1
+                    }
+                    _ => buckets.active += 1,
+                },
+            }
+        }
+        buckets
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        constants::WG_KEY_LEN,
+        nlas::{WgDeviceAttrs, WgPeer},
+    };
+
+    #[test]
+    fn test_handshake_buckets() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let peer = |key: u8, handshake: Option<SystemTime>| {
+            let mut nlas = vec![WgPeerAttrs::PublicKey([key; WG_KEY_LEN])];
+            nlas.extend(handshake.map(WgPeerAttrs::LastHandshake));
+            WgPeer(nlas)
+        };
+        let secs = Duration::from_secs;
+        let wg = Wireguard {
+            cmd: crate::WireguardCmd::GetDevice,
+            nlas: vec![
+                WgDeviceAttrs::IfName("wg0".to_string()),
+                WgDeviceAttrs::Peers(vec![
+                    peer(1, Some(now - secs(5))),
+                    peer(2, Some(now - secs(179))),
+                    peer(3, Some(now + secs(10))),
+                    peer(4, Some(now - secs(180))),
+                    peer(5, Some(now - secs(86400))),
+                ]),
+                WgDeviceAttrs::Peers(vec![
+                    peer(6, Some(SystemTime::UNIX_EPOCH)),
+                    peer(7, None),
+                ]),
+            ],
+        };
+        let buckets = wg.handshake_buckets(now);
+        assert_eq!(
+            buckets,
+            HandshakeBuckets {
+                active: 3,
+                idle: 2,
+                never: 2,
+            }
+        );
+        assert_eq!(buckets.total(), 7);
+        assert_eq!(
+            Wireguard::dump_all().handshake_buckets(now),
+            HandshakeBuckets::default()
+        );
+    }
+}
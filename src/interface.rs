@@ -0,0 +1,48 @@
+// SPDX-License-Identifier: MIT
+
+use crate::nlas::WgDeviceAttrs;
+
+/// The identity of a WireGuard device: its interface index
+/// (`WGDEVICE_A_IFINDEX`) or its name (`WGDEVICE_A_IFNAME`).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum InterfaceId {
+    Index(u32),
+    Name(String),
+}
+
+impl InterfaceId {
+    pub(crate) fn from_attr(nla: &WgDeviceAttrs) -> Option<Self> {
+        match nla {
+            WgDeviceAttrs::IfIndex(index) => Some(InterfaceId::Index(*index)),
+            WgDeviceAttrs::IfName(name) => {
+                Some(InterfaceId::Name(name.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn to_attr(&self) -> WgDeviceAttrs {
+        match self {
+            InterfaceId::Index(index) => WgDeviceAttrs::IfIndex(*index),
+            InterfaceId::Name(name) => WgDeviceAttrs::IfName(name.clone()),
+        }
+    }
+}
+
+impl From<u32> for InterfaceId {
+    fn from(index: u32) -> Self {
+        InterfaceId::Index(index)
+    }
+}
+
+impl From<String> for InterfaceId {
+    fn from(name: String) -> Self {
+        InterfaceId::Name(name)
+    }
+}
+
+impl From<&str> for InterfaceId {
+    fn from(name: &str) -> Self {
+        InterfaceId::Name(name.to_string())
+    }
+}
@@ -0,0 +1,494 @@
+// SPDX-License-Identifier: MIT
+
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt,
+    str::FromStr,
+};
+
+use crate::{constants::WG_KEY_LEN, encoding, WgError};
+
+/// A WireGuard key: private, public or preshared.
+///
+/// Keys are ordered by comparing their bytes lexicographically, e.g. to
+/// sort peers in a canonical order. Neither the ordering nor the equality
+/// are constant-time, so they should not be used on secrets an attacker
+/// can probe.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct WgKey(
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::serde_helpers::base64_key")
+    )]
+    #[cfg_attr(feature = "schemars", schemars(with = "String"))]
+    [u8; WG_KEY_LEN],
+);
+
+impl WgKey {
+    pub const fn new(bytes: [u8; WG_KEY_LEN]) -> Self {
+        Self(bytes)
+    }
+
+    /// Copy a borrowed array, like the `From<&[u8; 32]>` implementation,
+    /// but usable in `const` contexts.
+    pub const fn from_ref(bytes: &[u8; WG_KEY_LEN]) -> Self {
+        Self(*bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; WG_KEY_LEN] {
+        &self.0
+    }
+
+    /// Parse a key in the base64 form used by `wg(8)`, e.g. the output of
+    /// `wg genkey`.
+    pub fn from_base64(s: &str) -> Result<Self, WgError> {
+        Self::try_from(encoding::decode(s)?.as_slice())
+    }
+
+    /// Same as [`WgKey::from_base64`], after trimming from both ends the
+    /// characters keys copied from other files often come with: ASCII
+    /// whitespace, double quotes (`"`), single quotes (`'`) and commas
+    /// (`,`). Other characters are left as is, so `"key";` is still
+    /// rejected.
+    pub fn from_base64_lenient(s: &str) -> Result<Self, WgError> {
+        Self::from_base64(s.trim_matches(|c: char| {
+            c.is_ascii_whitespace() || matches!(c, '"' | '\'' | ',')
+        }))
+    }
+
+    /// The key in the base64 form used by `wg(8)`.
+    pub fn to_base64(&self) -> String {
+        encoding::encode(&self.0)
+    }
+
+    /// Whether `s` is the base64 form of this key. A string that isn't the
+    /// base64 form of a key is never equal.
+    ///
+    /// The bytes are compared in constant time, so this can be used on
+    /// private and preshared keys: the time taken doesn't tell how many
+    /// bytes match.
+    pub fn eq_base64(&self, s: &str) -> bool {
+        let other = match Self::from_base64(s) {
+            Ok(other) => other,
+            Err(_) => return false,
+        };
+        constant_time_eq(&self.0, &other.0)
+    }
+
+    /// Apply the Curve25519 clamping described at
+    /// <https://cr.yp.to/ecdh.html> to a private key: clear the three
+    /// lowest bits and the highest bit, and set the second highest bit.
+    ///
+    /// Call this after filling a private key with random bytes or after
+    /// modifying its bytes. It must not be used on public or preshared
+    /// keys.
+    pub fn clamp_private(&mut self) {
+        self.0[0] &= 248;
+        self.0[31] &= 127;
+        self.0[31] |= 64;
+    }
+
+    /// 32 random bytes from the operating system, e.g. for a preshared
+    /// key. Use [`WgKey::generate_private`] for a private key.
+    ///
+    /// Panics if the operating system fails to provide random bytes.
+    #[cfg(feature = "rand")]
+    pub fn random() -> Self {
+        let mut bytes = [0; WG_KEY_LEN];
+        getrandom::getrandom(&mut bytes)
+            .expect("failed to get random bytes from the operating system");
+        Self(bytes)
+    }
+
+    /// A new private key: [`WgKey::random`] with the clamping applied,
+    /// like `wg genkey`.
+    ///
+    /// Panics if the operating system fails to provide random bytes.
+    #[cfg(feature = "rand")]
+    pub fn generate_private() -> Self {
+        let mut key = Self::random();
+        key.clamp_private();
+        key
+    }
+}
+
+impl From<[u8; WG_KEY_LEN]> for WgKey {
+    fn from(bytes: [u8; WG_KEY_LEN]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<&[u8; WG_KEY_LEN]> for WgKey {
+    fn from(bytes: &[u8; WG_KEY_LEN]) -> Self {
+        Self(*bytes)
+    }
+}
+
+/// Every key read from a slice goes through this conversion, so a wrong
+/// length is always reported as [`WgError::BadKeyLength`].
+impl TryFrom<&[u8]> for WgKey {
+    type Error = WgError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        bytes
+            .try_into()
+            .map(Self)
+            .map_err(|_| WgError::BadKeyLength { got: bytes.len() })
+    }
+}
+
+/// Parse a key written either as 64 hexadecimal digits, like in the
+/// `wg(8)` cross-platform userspace API, or in base64, like in `wg`
+/// configurations. The two forms can't be confused: the base64 form of a
+/// key is 44 characters long.
+impl FromStr for WgKey {
+    type Err = WgError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 2 * WG_KEY_LEN
+            || !s.bytes().all(|b| b.is_ascii_hexdigit())
+        {
+            return Self::from_base64(s);
+        }
+        let mut bytes = [0; WG_KEY_LEN];
+        for (byte, digits) in bytes.iter_mut().zip(s.as_bytes().chunks(2)) {
+            // Only ASCII hexadecimal digits are left.
+            let digits = std::str::from_utf8(digits).unwrap();
+            *byte = u8::from_str_radix(digits, 16).unwrap();
+        }
+        Ok(Self(bytes))
+    }
+}
+
+/// Same as the [`FromStr`] implementation, for generic code bounded on
+/// `TryFrom<&str>`.
+impl TryFrom<&str> for WgKey {
+    type Error = WgError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<WgKey> for [u8; WG_KEY_LEN] {
+    fn from(key: WgKey) -> Self {
+        key.0
+    }
+}
+
+impl AsRef<[u8]> for WgKey {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// A public key, displayed in base64 like `wg show` does.
+///
+/// [`WgKey`] doesn't implement [`Display`](fmt::Display), since it may
+/// hold a secret: wrap public keys in this type to print them, and
+/// private and preshared keys in a [`SecretKey`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PublicKey(pub WgKey);
+
+impl fmt::Display for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0.to_base64())
+    }
+}
+
+impl From<WgKey> for PublicKey {
+    fn from(key: WgKey) -> Self {
+        Self(key)
+    }
+}
+
+impl From<[u8; WG_KEY_LEN]> for PublicKey {
+    fn from(bytes: [u8; WG_KEY_LEN]) -> Self {
+        Self(WgKey(bytes))
+    }
+}
+
+/// A private or preshared key. Both [`Display`](fmt::Display) and
+/// [`Debug`] print `(hidden)`, so the key can be part of a logged value
+/// without leaking: its bytes are only reachable through
+/// [`SecretKey::expose_secret`].
+///
+/// The private and preshared keys of [`Device`](crate::Device),
+/// [`Peer`](crate::Peer) and [`PeerConfig`](crate::PeerConfig) are held in
+/// this type, and the `Debug` output of the attributes of a
+/// [`Wireguard`](crate::Wireguard) message hides them the same way.
+///
+/// Unlike for [`WgKey`], the equality is constant-time.
+#[derive(Clone, Default, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct SecretKey(WgKey);
+
+impl SecretKey {
+    pub fn new(key: WgKey) -> Self {
+        Self(key)
+    }
+
+    /// The key itself, e.g. to put it in a `WGDEVICE_A_PRIVATE_KEY`.
+    pub fn expose_secret(&self) -> &WgKey {
+        &self.0
+    }
+}
+
+impl PartialEq for SecretKey {
+    fn eq(&self, other: &Self) -> bool {
+        constant_time_eq(&self.0 .0, &other.0 .0)
+    }
+}
+
+impl fmt::Display for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("(hidden)")
+    }
+}
+
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretKey((hidden))")
+    }
+}
+
+impl From<WgKey> for SecretKey {
+    fn from(key: WgKey) -> Self {
+        Self(key)
+    }
+}
+
+impl From<[u8; WG_KEY_LEN]> for SecretKey {
+    fn from(bytes: [u8; WG_KEY_LEN]) -> Self {
+        Self(WgKey(bytes))
+    }
+}
+
+impl From<&[u8; WG_KEY_LEN]> for SecretKey {
+    fn from(bytes: &[u8; WG_KEY_LEN]) -> Self {
+        Self(WgKey(*bytes))
+    }
+}
+
+// Compare the keys without stopping at the first differing byte, so that
+// the time taken doesn't tell how many bytes match.
+fn constant_time_eq(a: &[u8; WG_KEY_LEN], b: &[u8; WG_KEY_LEN]) -> bool {
+    let diff = a
+        .iter()
+        .zip(b.iter())
+        .fold(0, |diff, (a, b)| diff | (a ^ b));
+    std::hint::black_box(diff) == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_ref() {
+        const BYTES: [u8; WG_KEY_LEN] = [0x01; WG_KEY_LEN];
+        const KEY: WgKey = WgKey::from_ref(&BYTES);
+        let bytes = [0x02; WG_KEY_LEN];
+        assert_eq!(KEY, WgKey::new(BYTES));
+        assert_eq!(WgKey::from_ref(&bytes), WgKey::from(&bytes));
+        // The array is still usable.
+        assert_eq!(bytes, [0x02; WG_KEY_LEN]);
+    }
+
+    #[test]
+    fn test_key_len() {
+        assert_eq!(std::mem::size_of::<WgKey>(), WG_KEY_LEN);
+        assert_eq!(WgKey::default().as_bytes().len(), WG_KEY_LEN);
+        let encoded = WgKey::new([0x01; WG_KEY_LEN]).to_base64();
+        assert_eq!(encoded.len(), (WG_KEY_LEN + 2) / 3 * 4);
+    }
+
+    #[test]
+    fn test_from_str() {
+        let key =
+            WgKey::from_base64("8bdQrVLqiw3ZoHCucNh1YfH0iCWuyStniRr8t7H24Fk=")
+                .unwrap();
+        let hex: String = key
+            .as_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        assert_eq!(key.to_base64().parse(), Ok(key));
+        assert_eq!(hex.parse(), Ok(key));
+        assert_eq!(hex.to_uppercase().parse(), Ok(key));
+
+        // 64 characters that aren't all hexadecimal digits are base64.
+        let not_hex = format!("g{}", &hex[1..]);
+        assert_eq!(
+            not_hex.parse::<WgKey>(),
+            Err(WgError::BadKeyLength { got: 48 })
+        );
+        assert_eq!(
+            "AQEB".parse::<WgKey>(),
+            Err(WgError::BadKeyLength { got: 3 })
+        );
+    }
+
+    #[test]
+    fn test_try_from_str() {
+        fn convert<T: for<'a> TryFrom<&'a str>>(s: &str) -> Option<T> {
+            T::try_from(s).ok()
+        }
+        let key = WgKey::new([0x01; WG_KEY_LEN]);
+        assert_eq!(convert::<WgKey>(&key.to_base64()), Some(key));
+        assert_eq!(WgKey::try_from(key.to_base64().as_str()), Ok(key));
+        assert_eq!(convert::<WgKey>("not a key"), None);
+    }
+
+    #[test]
+    fn test_key_wrappers_display() {
+        let key =
+            WgKey::from_base64("8bdQrVLqiw3ZoHCucNh1YfH0iCWuyStniRr8t7H24Fk=")
+                .unwrap();
+        assert_eq!(
+            PublicKey(key).to_string(),
+            "8bdQrVLqiw3ZoHCucNh1YfH0iCWuyStniRr8t7H24Fk="
+        );
+
+        let secret = SecretKey::from(key);
+        assert_eq!(secret.to_string(), "(hidden)");
+        assert_eq!(format!("{:?}", secret), "SecretKey((hidden))");
+        assert_eq!(
+            format!("{:#?}", Some(&secret)),
+            "Some(\n    SecretKey((hidden)),\n)"
+        );
+        assert_eq!(secret.expose_secret(), &key);
+        assert_eq!(secret, SecretKey::from(key));
+        assert_ne!(secret, SecretKey::default());
+    }
+
+    #[test]
+    fn test_base64() {
+        let key =
+            WgKey::from_base64("8bdQrVLqiw3ZoHCucNh1YfH0iCWuyStniRr8t7H24Fk=")
+                .unwrap();
+        assert_eq!(&key.as_bytes()[..4], &[0xf1, 0xb7, 0x50, 0xad]);
+        assert_eq!(
+            key.to_base64(),
+            "8bdQrVLqiw3ZoHCucNh1YfH0iCWuyStniRr8t7H24Fk="
+        );
+        assert!(matches!(
+            WgKey::from_base64("8bdQrVLqiw3ZoHCucNh1YfH0iCWuyStniRr8t7H24F!="),
+            Err(WgError::InvalidBase64(_))
+        ));
+        assert_eq!(
+            WgKey::from_base64("AQEB"),
+            Err(WgError::BadKeyLength { got: 3 })
+        );
+    }
+
+    #[test]
+    fn test_eq_base64() {
+        const KEY: &str = "8bdQrVLqiw3ZoHCucNh1YfH0iCWuyStniRr8t7H24Fk=";
+        let key = WgKey::from_base64(KEY).unwrap();
+        assert!(key.eq_base64(KEY));
+        assert!(!key.eq_base64("9bdQrVLqiw3ZoHCucNh1YfH0iCWuyStniRr8t7H24Fk="));
+        assert!(!key.eq_base64("8bdQrVLqiw3ZoHCucNh1YfH0iCWuyStniRr8t7H24Fg="));
+        assert!(!key.eq_base64(""));
+        assert!(!key.eq_base64("not base64"));
+        assert!(!key.eq_base64(&KEY[..40]));
+    }
+
+    #[test]
+    fn test_from_base64_lenient() {
+        const KEY: &str = "8bdQrVLqiw3ZoHCucNh1YfH0iCWuyStniRr8t7H24Fk=";
+        let key = WgKey::from_base64(KEY).unwrap();
+        for input in [
+            KEY.to_string(),
+            format!("\"{}\"", KEY),
+            format!("'{}'", KEY),
+            format!("{},", KEY),
+            format!("  \"{}\",\n", KEY),
+        ] {
+            assert_eq!(
+                WgKey::from_base64_lenient(&input),
+                Ok(key),
+                "{}",
+                input
+            );
+            if input != KEY {
+                assert!(WgKey::from_base64(&input).is_err());
+            }
+        }
+        assert!(WgKey::from_base64_lenient(&format!("\"{}\";", KEY)).is_err());
+        assert!(WgKey::from_base64_lenient(&format!("[{}]", KEY)).is_err());
+    }
+
+    #[test]
+    fn test_try_from_slice() {
+        let bytes = [0x01; 2 * WG_KEY_LEN];
+        assert_eq!(
+            WgKey::try_from(&bytes[..WG_KEY_LEN]),
+            Ok(WgKey::new([0x01; WG_KEY_LEN]))
+        );
+        for len in [0, 1, WG_KEY_LEN - 1, WG_KEY_LEN + 1, 2 * WG_KEY_LEN] {
+            assert_eq!(
+                WgKey::try_from(&bytes[..len]),
+                Err(WgError::BadKeyLength { got: len })
+            );
+        }
+    }
+
+    #[test]
+    fn test_sort() {
+        let mut a = [0x00; WG_KEY_LEN];
+        a[31] = 0x01;
+        let mut keys = vec![
+            WgKey::new([0xff; WG_KEY_LEN]),
+            WgKey::new(a),
+            WgKey::new([0x00; WG_KEY_LEN]),
+            WgKey::new([0x10; WG_KEY_LEN]),
+        ];
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec![
+                WgKey::new([0x00; WG_KEY_LEN]),
+                WgKey::new(a),
+                WgKey::new([0x10; WG_KEY_LEN]),
+                WgKey::new([0xff; WG_KEY_LEN]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_clamp_private() {
+        let mut key = WgKey::new([0xff; WG_KEY_LEN]);
+        key.clamp_private();
+        assert_eq!(key.as_bytes()[0], 0xf8);
+        assert_eq!(key.as_bytes()[1..31], [0xff; 30]);
+        assert_eq!(key.as_bytes()[31], 0x7f);
+
+        let mut key = WgKey::new([0x00; WG_KEY_LEN]);
+        key.clamp_private();
+        assert_eq!(key.as_bytes()[0], 0x00);
+        assert_eq!(key.as_bytes()[31], 0x40);
+        assert!(is_clamped(&key));
+    }
+
+    fn is_clamped(key: &WgKey) -> bool {
+        key.as_bytes()[0] & 0x07 == 0 && key.as_bytes()[31] & 0xc0 == 0x40
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_random() {
+        // A random key looks clamped with a probability of 1/16.
+        let keys: Vec<_> = (0..32).map(|_| WgKey::random()).collect();
+        assert!(keys.iter().any(|key| !is_clamped(key)));
+        assert!(keys.windows(2).any(|w| w[0] != w[1]));
+        for _ in 0..32 {
+            assert!(is_clamped(&WgKey::generate_private()));
+        }
+    }
+}
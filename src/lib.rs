@@ -6,15 +6,59 @@ extern crate log;
 use crate::constants::*;
 use anyhow::Context;
 use netlink_packet_generic::{GenlFamily, GenlHeader};
-use netlink_packet_utils::{nla::NlasIterator, traits::*, DecodeError};
-use nlas::WgDeviceAttrs;
-use std::convert::{TryFrom, TryInto};
+use netlink_packet_utils::{
+    nla::{Nla, NlasIterator},
+    traits::*,
+    DecodeError,
+};
+use nlas::{WgAllowedIpAttrs, WgDeviceAttrs, WgPeerAttrs};
+use std::{
+    convert::{TryFrom, TryInto},
+    iter::FromIterator,
+    net::IpAddr,
+};
 
+mod builder;
+mod byte_order;
+mod canonical;
+mod chunk;
+mod conf;
+mod config;
 pub mod constants;
+mod debug;
+mod device;
+mod encoding;
+mod error;
+mod flags;
+mod framing;
+mod handshake;
+mod interface;
+mod key;
+mod lint;
+mod merge;
 pub mod nlas;
+mod plan;
 mod raw;
+#[cfg(feature = "serde")]
+pub mod serde_helpers;
+mod validate;
+
+pub use builder::{DeviceBuilder, WgDeviceBuilder, WgPeerBuilder};
+pub use config::PeerConfig;
+pub use device::{AllowedIp, Device, DeviceSummary, Peer};
+pub use error::WgError;
+pub use flags::{WgDeviceFlags, WgPeerFlags};
+pub use handshake::HandshakeBuckets;
+pub use interface::InterfaceId;
+pub use key::{PublicKey, SecretKey, WgKey};
+pub use lint::Lint;
+pub use merge::MergeStrategy;
+pub use plan::ChangePlan;
+pub use raw::parse_sockaddr;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub enum WireguardCmd {
     GetDevice,
     SetDevice,
@@ -49,18 +93,374 @@ impl TryFrom<u8> for WireguardCmd {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Wireguard {
     pub cmd: WireguardCmd,
     pub nlas: Vec<nlas::WgDeviceAttrs>,
 }
 
+impl Wireguard {
+    /// A `GetDevice` payload without any `WGDEVICE_A_IFINDEX` or
+    /// `WGDEVICE_A_IFNAME` attribute, meant to be sent with
+    /// `NLM_F_REQUEST | NLM_F_DUMP`.
+    ///
+    /// Note that the Linux implementation requires exactly one of the
+    /// identity attributes and answers `EBADR` to this request: to dump
+    /// every interface there, list the wireguard links over rtnetlink and
+    /// send one request per interface.
+    pub fn dump_all() -> Self {
+        Self {
+            cmd: WireguardCmd::GetDevice,
+            nlas: Vec::new(),
+        }
+    }
+
+    /// Whether the message has no attribute. Only a `GetDevice` dumping
+    /// every device, see [`Wireguard::dump_all`], makes sense empty.
+    pub fn is_empty(&self) -> bool {
+        self.nlas.is_empty()
+    }
+
+    /// The command of the message. [`GenlFamily::command`] returns the
+    /// raw `WG_CMD_*` value instead.
+    pub fn wg_command(&self) -> WireguardCmd {
+        self.cmd
+    }
+
+    pub fn set_command(&mut self, cmd: WireguardCmd) {
+        self.cmd = cmd;
+    }
+
+    /// Check that the message carries `cmd`, e.g. that a message handed
+    /// to code sending it to the kernel is a `SetDevice` and not a dump
+    /// reply. Returns [`WgError::UnexpectedCommand`] otherwise.
+    pub fn expect_command(&self, cmd: WireguardCmd) -> Result<(), WgError> {
+        if self.cmd == cmd {
+            Ok(())
+        } else {
+            Err(WgError::UnexpectedCommand {
+                expected: cmd,
+                got: self.cmd,
+            })
+        }
+    }
+
+    /// Identify the device by name, replacing any `WGDEVICE_A_IFINDEX`
+    /// or `WGDEVICE_A_IFNAME` attribute since the kernel only accepts
+    /// one of them.
+    pub fn set_ifname<T: Into<String>>(&mut self, name: T) {
+        self.set_identity(WgDeviceAttrs::IfName(name.into()));
+    }
+
+    /// Identify the device by index, replacing any `WGDEVICE_A_IFINDEX`
+    /// or `WGDEVICE_A_IFNAME` attribute since the kernel only accepts
+    /// one of them.
+    pub fn set_ifindex(&mut self, index: u32) {
+        self.set_identity(WgDeviceAttrs::IfIndex(index));
+    }
+
+    fn set_identity(&mut self, identity: WgDeviceAttrs) {
+        self.nlas.retain(|nla| {
+            !matches!(nla, WgDeviceAttrs::IfIndex(_) | WgDeviceAttrs::IfName(_))
+        });
+        self.nlas.insert(0, identity);
+    }
+
+    /// The device private key, borrowed from its attribute rather than
+    /// copied so that the secret isn't duplicated in memory.
+    pub fn private_key_bytes(&self) -> Option<&[u8; WG_KEY_LEN]> {
+        self.nlas.iter().find_map(|nla| match nla {
+            WgDeviceAttrs::PrivateKey(key) => Some(key),
+            _ => None,
+        })
+    }
+
+    /// Whether the device has a private key configured, without exposing
+    /// it. The kernel reports an all-zero key when none is set.
+    pub fn has_private_key(&self) -> bool {
+        self.nlas.iter().any(|nla| {
+            matches!(nla, WgDeviceAttrs::PrivateKey(key)
+                if key.iter().any(|b| *b != 0))
+        })
+    }
+
+    /// A copy of the message without the secrets it may carry: the
+    /// `WGDEVICE_A_PRIVATE_KEY` of the device and the
+    /// `WGPEER_A_PRESHARED_KEY` of the peers. Public keys are kept.
+    pub fn clone_without_secrets(&self) -> Self {
+        let mut wg = self.clone();
+        wg.nlas
+            .retain(|nla| !matches!(nla, WgDeviceAttrs::PrivateKey(_)));
+        for peer in wg.peers_iter_mut() {
+            peer.0
+                .retain(|nla| !matches!(nla, WgPeerAttrs::PresharedKey(_)));
+        }
+        wg
+    }
+
+    /// Drop every `WGDEVICE_A_PEERS` attribute, so that a `SetDevice`
+    /// message only changes the device settings and leaves the peers
+    /// untouched.
+    pub fn without_peers(mut self) -> Self {
+        self.nlas
+            .retain(|nla| !matches!(nla, WgDeviceAttrs::Peers(_)));
+        self
+    }
+
+    /// Split the message into two `SetDevice` messages, for callers
+    /// applying the device settings and the peer changes separately: the
+    /// first one with the device settings, the second one with the
+    /// `WGDEVICE_A_PEERS` attributes and the `WGDEVICE_A_FLAGS`, since
+    /// `WGDEVICE_F_REPLACE_PEERS` applies to the peers. Both carry the
+    /// `WGDEVICE_A_IFINDEX` or `WGDEVICE_A_IFNAME` of the message.
+    pub fn split_device_and_peers(self) -> (Wireguard, Wireguard) {
+        let mut device = Vec::new();
+        let mut peers = Vec::new();
+        for nla in self.nlas {
+            match nla {
+                WgDeviceAttrs::IfIndex(_) | WgDeviceAttrs::IfName(_) => {
+                    device.push(nla.clone());
+                    peers.push(nla);
+                }
+                WgDeviceAttrs::Peers(_) | WgDeviceAttrs::Flags(_) => {
+                    peers.push(nla)
+                }
+                _ => device.push(nla),
+            }
+        }
+        let message = |nlas| Wireguard {
+            cmd: WireguardCmd::SetDevice,
+            nlas,
+        };
+        (message(device), message(peers))
+    }
+
+    /// Take the peers of every `WGDEVICE_A_PEERS` attribute out of the
+    /// message, in order, dropping the device attributes.
+    pub fn into_peers(self) -> Vec<nlas::WgPeer> {
+        self.nlas
+            .into_iter()
+            .filter_map(|nla| match nla {
+                WgDeviceAttrs::Peers(peers) => Some(peers),
+                _ => None,
+            })
+            .flatten()
+            .collect()
+    }
+
+    /// The peers of every `WGDEVICE_A_PEERS` attribute.
+    pub(crate) fn peers(&self) -> impl Iterator<Item = &nlas::WgPeer> {
+        self.nlas
+            .iter()
+            .filter_map(|nla| match nla {
+                WgDeviceAttrs::Peers(peers) => Some(peers),
+                _ => None,
+            })
+            .flatten()
+    }
+
+    /// The peers of every `WGDEVICE_A_PEERS` attribute, to change them in
+    /// place.
+    pub fn peers_iter_mut(
+        &mut self,
+    ) -> impl Iterator<Item = &mut nlas::WgPeer> {
+        self.nlas
+            .iter_mut()
+            .filter_map(|nla| match nla {
+                WgDeviceAttrs::Peers(peers) => Some(peers),
+                _ => None,
+            })
+            .flatten()
+    }
+
+    /// The first peer with the public key `key`.
+    pub fn peer_by_key(&self, key: &[u8; WG_KEY_LEN]) -> Option<&nlas::WgPeer> {
+        self.peers().find(|peer| peer.public_key() == Some(key))
+    }
+
+    /// Same as [`Wireguard::peer_by_key`], to change the peer in place.
+    pub fn peer_by_key_mut(
+        &mut self,
+        key: &[u8; WG_KEY_LEN],
+    ) -> Option<&mut nlas::WgPeer> {
+        self.peers_iter_mut()
+            .find(|peer| peer.public_key() == Some(key))
+    }
+
+    /// The peer with the public key `key`, appended with only its key to
+    /// the first `WGDEVICE_A_PEERS` attribute if there is none yet.
+    pub fn peer_entry(&mut self, key: [u8; WG_KEY_LEN]) -> &mut nlas::WgPeer {
+        if self.peer_by_key(&key).is_none() {
+            self.extend(vec![nlas::WgPeer(vec![WgPeerAttrs::PublicKey(key)])]);
+        }
+        self.peer_by_key_mut(&key)
+            .expect("the peer is in the message")
+    }
+
+    /// Remove the runtime statistics (`LastHandshake`, `RxBytes` and
+    /// `TxBytes`) of every peer, e.g. before comparing two dumps. The
+    /// command is left as is, unlike [`Wireguard::into_set_template`].
+    pub fn clear_peer_stats(&mut self) {
+        for peer in self.peers_iter_mut() {
+            *peer = peer.to_config();
+        }
+    }
+
+    /// Keep only the peers for which `f` returns `true`, in every
+    /// `WGDEVICE_A_PEERS` attribute.
+    pub fn retain_peers<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&nlas::WgPeer) -> bool,
+    {
+        for nla in self.nlas.iter_mut() {
+            if let WgDeviceAttrs::Peers(peers) = nla {
+                peers.retain(&mut f);
+            }
+        }
+    }
+
+    /// The number of allowed IPs of all the peers together, repeated
+    /// entries included.
+    pub fn allowed_ip_count(&self) -> usize {
+        self.peers().map(nlas::WgPeer::allowed_ips_len).sum()
+    }
+
+    /// The number of peers, across all the `WGDEVICE_A_PEERS` attributes.
+    pub fn peers_len(&self) -> usize {
+        self.nlas
+            .iter()
+            .map(|nla| match nla {
+                WgDeviceAttrs::Peers(peers) => peers.len(),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// The public keys of the peers in base64, in the order of the
+    /// message. Peers without a public key are skipped.
+    pub fn peer_public_keys_base64(&self) -> Vec<String> {
+        self.peers()
+            .filter_map(|peer| peer.public_key())
+            .map(|key| WgKey::from(key).to_base64())
+            .collect()
+    }
+
+    /// Every allowed IP of the device paired with the public key of the
+    /// peer it routes to, across all the peers. Peers without a public
+    /// key and allowed IPs without an address or prefix length are
+    /// skipped.
+    pub fn peer_allowed_ip_pairs(
+        &self,
+    ) -> impl Iterator<Item = ([u8; WG_KEY_LEN], IpAddr, u8)> + '_ {
+        self.peers().flat_map(|peer| {
+            let public_key = peer.public_key().copied();
+            peer.iter()
+                .filter_map(|nla| match nla {
+                    WgPeerAttrs::AllowedIps(ips) => Some(ips),
+                    _ => None,
+                })
+                .flatten()
+                .filter_map(move |ip| {
+                    let addr = ip.iter().find_map(|nla| match nla {
+                        WgAllowedIpAttrs::IpAddr(addr) => Some(*addr),
+                        _ => None,
+                    })?;
+                    let cidr = ip.iter().find_map(|nla| match nla {
+                        WgAllowedIpAttrs::Cidr(cidr) => Some(*cidr),
+                        _ => None,
+                    })?;
+                    Some((public_key?, addr, cidr))
+                })
+        })
+    }
+
+    /// The first peer with the allowed IP `net`.
+    ///
+    /// Only the exact prefix matches: `10.0.0.0/16` doesn't find the peer
+    /// allowed `10.0.0.0/24`, nor the other way around. Like in the kernel,
+    /// the host bits are ignored, so `10.0.0.1/24` finds it.
+    pub fn peer_for_allowed_ip(
+        &self,
+        net: (IpAddr, u8),
+    ) -> Option<&nlas::WgPeer> {
+        let (addr, cidr) = net;
+        let net = (nlas::peer::mask(addr, cidr), cidr);
+        self.peers().find(|peer| {
+            peer.iter()
+                .filter_map(|nla| match nla {
+                    WgPeerAttrs::AllowedIps(ips) => Some(ips),
+                    _ => None,
+                })
+                .flatten()
+                .filter_map(|ip| ip.addr_and_cidr())
+                .any(|(addr, cidr)| (nlas::peer::mask(addr, cidr), cidr) == net)
+        })
+    }
+
+    /// Whether this `GetDevice` reply is a continuation of a dump split
+    /// across several messages.
+    ///
+    /// The kernel only puts the device attributes, identity included, in
+    /// the first message of a dump. The following ones only carry peers,
+    /// which should be merged into that first message.
+    pub fn is_continuation_fragment(&self) -> bool {
+        let has_identity = self.nlas.iter().any(|nla| {
+            matches!(nla, WgDeviceAttrs::IfIndex(_) | WgDeviceAttrs::IfName(_))
+        });
+        !has_identity
+            && self
+                .nlas
+                .iter()
+                .any(|nla| matches!(nla, WgDeviceAttrs::Peers(_)))
+    }
+
+    /// The number of bytes the `WGDEVICE_A_PEERS` attributes take in the
+    /// emitted message, headers and padding included, e.g. to decide how
+    /// to split a large `SetDevice` message.
+    pub fn peers_bytes(&self) -> usize {
+        self.nlas
+            .iter()
+            .filter(|nla| matches!(nla, WgDeviceAttrs::Peers(_)))
+            .map(|nla| nla.buffer_len())
+            .sum()
+    }
+
+    /// The emitted value (without the NLA header and padding) of the
+    /// first device attribute of the given `WGDEVICE_A_*` kind.
+    pub fn attr_value(&self, kind: u16) -> Option<Vec<u8>> {
+        let nla = self.nlas.iter().find(|nla| nla.kind() == kind)?;
+        let mut value = vec![0; nla.value_len()];
+        nla.emit_value(&mut value);
+        Some(value)
+    }
+
+    /// The `WGDEVICE_A_*` kinds of the device attributes, in the order of
+    /// the message, repeated kinds included.
+    pub fn attr_kinds(&self) -> Vec<u16> {
+        self.nlas.iter().map(|nla| nla.kind()).collect()
+    }
+
+    /// Parse the device attributes of a message payload, reporting the
+    /// location of the caller in the error to tell call sites apart when
+    /// debugging decode failures.
+    #[track_caller]
+    pub fn parse_nlas_at(buf: &[u8]) -> Result<Vec<WgDeviceAttrs>, WgError> {
+        let location = std::panic::Location::caller();
+        parse_nlas(buf).map_err(|e| WgError::DecodeAt {
+            message: e.to_string(),
+            location,
+        })
+    }
+}
+
 impl GenlFamily for Wireguard {
     fn family_name() -> &'static str {
-        "wireguard"
+        WG_GENL_NAME
     }
 
     fn version(&self) -> u8 {
-        1
+        WG_GENL_VERSION
     }
 
     fn command(&self) -> u8 {
@@ -68,6 +468,35 @@ impl GenlFamily for Wireguard {
     }
 }
 
+/// Append peers to the first `WGDEVICE_A_PEERS` attribute, adding one at
+/// the end of the message if there is none.
+impl Extend<nlas::WgPeer> for Wireguard {
+    fn extend<I: IntoIterator<Item = nlas::WgPeer>>(&mut self, iter: I) {
+        for nla in self.nlas.iter_mut() {
+            if let WgDeviceAttrs::Peers(peers) = nla {
+                peers.extend(iter);
+                return;
+            }
+        }
+        self.nlas
+            .push(WgDeviceAttrs::Peers(iter.into_iter().collect()));
+    }
+}
+
+/// Collect peers into a `SetDevice` message with a single
+/// `WGDEVICE_A_PEERS` attribute. The message identifies no device and
+/// carries no flags: `WGDEVICE_A_IFINDEX` or `WGDEVICE_A_IFNAME`, and
+/// `WGDEVICE_A_FLAGS` if needed, must be added before sending it, e.g.
+/// with [`Wireguard::set_ifname`].
+impl FromIterator<nlas::WgPeer> for Wireguard {
+    fn from_iter<I: IntoIterator<Item = nlas::WgPeer>>(iter: I) -> Self {
+        Wireguard {
+            cmd: WireguardCmd::SetDevice,
+            nlas: vec![WgDeviceAttrs::Peers(iter.into_iter().collect())],
+        }
+    }
+}
+
 impl Emitable for Wireguard {
     fn emit(&self, buffer: &mut [u8]) {
         self.nlas.as_slice().emit(buffer)
@@ -102,12 +531,11 @@ fn parse_nlas(buf: &[u8]) -> Result<Vec<WgDeviceAttrs>, DecodeError> {
 }
 
 #[cfg(test)]
-
 mod test {
     use netlink_packet_core::{NetlinkMessage, NLM_F_ACK, NLM_F_REQUEST};
     use netlink_packet_generic::GenlMessage;
 
-    use crate::nlas::{WgAllowedIp, WgAllowedIpAttrs, WgPeer, WgPeerAttrs};
+    use crate::nlas::{EmitToVec, WgAllowedIp, WgPeer};
 
     use super::*;
 
@@ -139,11 +567,11 @@ mod test {
                 cmd: WireguardCmd::SetDevice,
                 nlas: vec![
                     WgDeviceAttrs::IfName("wg0".to_string()),
-                    WgDeviceAttrs::PrivateKey([0xaa; 32]),
+                    WgDeviceAttrs::PrivateKey([0xaa; WG_KEY_LEN]),
                     WgDeviceAttrs::Peers(vec![
                         WgPeer(vec![
-                            WgPeerAttrs::PublicKey([0x01; 32]),
-                            WgPeerAttrs::PresharedKey([0x01; 32]),
+                            WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN]),
+                            WgPeerAttrs::PresharedKey([0x01; WG_KEY_LEN]),
                             WgPeerAttrs::AllowedIps(vec![WgAllowedIp(vec![
                                 WgAllowedIpAttrs::IpAddr([10, 0, 0, 0].into()),
                                 WgAllowedIpAttrs::Cidr(24),
@@ -151,8 +579,8 @@ mod test {
                             ])]),
                         ]),
                         WgPeer(vec![
-                            WgPeerAttrs::PublicKey([0x02; 32]),
-                            WgPeerAttrs::PresharedKey([0x01; 32]),
+                            WgPeerAttrs::PublicKey([0x02; WG_KEY_LEN]),
+                            WgPeerAttrs::PresharedKey([0x01; WG_KEY_LEN]),
                             WgPeerAttrs::AllowedIps(vec![WgAllowedIp(vec![
                                 WgAllowedIpAttrs::IpAddr([10, 0, 1, 0].into()),
                                 WgAllowedIpAttrs::Cidr(24),
@@ -172,4 +600,629 @@ mod test {
         NetlinkMessage::<GenlMessage<Wireguard>>::deserialize(&buf[..len])
             .unwrap();
     }
+
+    #[test]
+    fn test_emitted_genl_header_version() {
+        let genlmsg = GenlMessage::from_payload(Wireguard {
+            cmd: WireguardCmd::GetDevice,
+            nlas: vec![WgDeviceAttrs::IfName("wg0".to_string())],
+        });
+        let mut nlmsg = NetlinkMessage::from(genlmsg);
+        nlmsg.finalize();
+        let mut buf = vec![0; nlmsg.buffer_len()];
+        nlmsg.serialize(&mut buf);
+        // The generic netlink header follows the 16 bytes netlink header:
+        // command, version, then two reserved bytes.
+        assert_eq!(buf[16], WG_CMD_GET_DEVICE);
+        assert_eq!(buf[17], WG_GENL_VERSION);
+        assert_eq!(WG_GENL_VERSION, 1);
+    }
+
+    #[test]
+    fn test_attr_value() {
+        let wg = Wireguard {
+            cmd: WireguardCmd::SetDevice,
+            nlas: vec![
+                WgDeviceAttrs::IfName("wg0".to_string()),
+                WgDeviceAttrs::ListenPort(51820),
+            ],
+        };
+        assert_eq!(
+            wg.attr_value(WGDEVICE_A_LISTEN_PORT),
+            Some(51820u16.to_ne_bytes().to_vec())
+        );
+        assert_eq!(wg.attr_value(WGDEVICE_A_FWMARK), None);
+    }
+
+    #[test]
+    fn test_attr_kinds() {
+        let wg = crate::WgDeviceBuilder::new()
+            .ifname("wg0")
+            .private_key([0xaa; WG_KEY_LEN])
+            .listen_port(51820)
+            .peer(crate::WgPeerBuilder::new([0x01; WG_KEY_LEN]).build())
+            .build();
+        assert_eq!(
+            wg.attr_kinds(),
+            vec![
+                WGDEVICE_A_IFNAME,
+                WGDEVICE_A_PRIVATE_KEY,
+                WGDEVICE_A_LISTEN_PORT,
+                WGDEVICE_A_PEERS,
+            ]
+        );
+        assert!(Wireguard::dump_all().attr_kinds().is_empty());
+    }
+
+    #[test]
+    fn test_peers_iter_mut() {
+        let peer = |key| {
+            WgPeer(vec![
+                WgPeerAttrs::PublicKey(key),
+                WgPeerAttrs::RxBytes(1024),
+                WgPeerAttrs::PersistentKeepalive(25),
+                WgPeerAttrs::TxBytes(2048),
+            ])
+        };
+        let mut wg = Wireguard {
+            cmd: WireguardCmd::GetDevice,
+            nlas: vec![
+                WgDeviceAttrs::IfName("wg0".to_string()),
+                WgDeviceAttrs::Peers(vec![
+                    peer([0x01; WG_KEY_LEN]),
+                    peer([0x02; WG_KEY_LEN]),
+                ]),
+                WgDeviceAttrs::Peers(vec![peer([0x03; WG_KEY_LEN])]),
+            ],
+        };
+        for peer in wg.peers_iter_mut() {
+            *peer = peer.to_config();
+        }
+        let keys: Vec<_> =
+            wg.peers().map(|p| *p.public_key().unwrap()).collect();
+        assert_eq!(
+            keys,
+            vec![[0x01; WG_KEY_LEN], [0x02; WG_KEY_LEN], [0x03; WG_KEY_LEN]]
+        );
+        for peer in wg.peers() {
+            assert_eq!(peer.len(), 2);
+            assert_eq!(peer[1], WgPeerAttrs::PersistentKeepalive(25));
+        }
+    }
+
+    #[test]
+    fn test_peer_for_allowed_ip() {
+        let wg = WgDeviceBuilder::new()
+            .ifname("wg0")
+            .peer(
+                WgPeerBuilder::new([0x01; WG_KEY_LEN])
+                    .allowed_ip_unchecked([10, 0, 0, 0].into(), 24)
+                    .build(),
+            )
+            .peer(
+                WgPeerBuilder::new([0x02; WG_KEY_LEN])
+                    .allowed_ip_unchecked([10, 0, 0, 0].into(), 16)
+                    .allowed_ip_unchecked("fd00::".parse().unwrap(), 64)
+                    .build(),
+            )
+            .build();
+        let owner = |net: &str| {
+            let (addr, cidr) = net.split_once('/').unwrap();
+            wg.peer_for_allowed_ip((
+                addr.parse().unwrap(),
+                cidr.parse().unwrap(),
+            ))
+            .map(|peer| peer.public_key().unwrap()[0])
+        };
+        assert_eq!(owner("10.0.0.0/24"), Some(0x01));
+        assert_eq!(owner("10.0.0.1/24"), Some(0x01));
+        assert_eq!(owner("10.0.0.0/16"), Some(0x02));
+        assert_eq!(owner("fd00::/64"), Some(0x02));
+        assert_eq!(owner("10.0.0.1/32"), None);
+        assert_eq!(owner("10.0.0.0/8"), None);
+    }
+
+    #[test]
+    fn test_retain_peers() {
+        let peer = |key, handshake| {
+            WgPeer(vec![
+                WgPeerAttrs::PublicKey(key),
+                WgPeerAttrs::LastHandshake(
+                    std::time::UNIX_EPOCH
+                        + std::time::Duration::from_secs(handshake),
+                ),
+            ])
+        };
+        let mut wg = Wireguard {
+            cmd: WireguardCmd::GetDevice,
+            nlas: vec![
+                WgDeviceAttrs::IfName("wg0".to_string()),
+                WgDeviceAttrs::Peers(vec![
+                    peer([0x01; WG_KEY_LEN], 1_700_000_000),
+                    peer([0x02; WG_KEY_LEN], 1_600_000_000),
+                ]),
+                WgDeviceAttrs::Peers(vec![peer(
+                    [0x03; WG_KEY_LEN],
+                    1_700_000_100,
+                )]),
+            ],
+        };
+        let since = std::time::UNIX_EPOCH
+            + std::time::Duration::from_secs(1_650_000_000);
+        wg.retain_peers(|peer| {
+            peer.iter().any(|nla| {
+                matches!(nla, WgPeerAttrs::LastHandshake(t) if *t > since)
+            })
+        });
+        let keys: Vec<_> =
+            wg.peers().map(|p| p.public_key().unwrap()[0]).collect();
+        assert_eq!(keys, vec![0x01, 0x03]);
+        wg.retain_peers(|_| false);
+        assert_eq!(wg.peers().count(), 0);
+        assert_eq!(wg.nlas.len(), 3);
+    }
+
+    #[test]
+    fn test_allowed_ip_count() {
+        let mut wg = WgDeviceBuilder::new()
+            .ifname("wg0")
+            .peer(WgPeerBuilder::new([0x01; WG_KEY_LEN]).build())
+            .peer(
+                WgPeerBuilder::new([0x02; WG_KEY_LEN])
+                    .allowed_ip_unchecked([10, 0, 0, 2].into(), 32)
+                    .build(),
+            )
+            .peer(
+                WgPeerBuilder::new([0x03; WG_KEY_LEN])
+                    .allowed_ip_unchecked([10, 0, 1, 0].into(), 24)
+                    .allowed_ip_unchecked([10, 0, 2, 0].into(), 24)
+                    .allowed_ip_unchecked("fd00::".parse().unwrap(), 64)
+                    .build(),
+            )
+            .build();
+        assert_eq!(wg.allowed_ip_count(), 4);
+        wg.nlas.push(WgDeviceAttrs::Peers(vec![
+            crate::nlas::WgPeer::add_allowed_ips(
+                [0x01; WG_KEY_LEN],
+                WgAllowedIp::hosts(vec![[10, 0, 0, 1].into()]),
+            ),
+        ]));
+        assert_eq!(wg.allowed_ip_count(), 5);
+        assert_eq!(Wireguard::dump_all().allowed_ip_count(), 0);
+    }
+
+    #[test]
+    fn test_peers_len() {
+        let mut wg = WgDeviceBuilder::new()
+            .ifname("wg0")
+            .peer(WgPeerBuilder::new([0x01; WG_KEY_LEN]).build())
+            .peer(WgPeerBuilder::new([0x02; WG_KEY_LEN]).build())
+            .build();
+        assert_eq!(wg.peers_len(), 2);
+        wg.nlas.push(WgDeviceAttrs::Peers(vec![WgPeerBuilder::new(
+            [0x03; WG_KEY_LEN],
+        )
+        .build()]));
+        assert_eq!(wg.peers_len(), 3);
+        assert_eq!(wg.peers_len(), wg.peers().count());
+        assert_eq!(Wireguard::dump_all().peers_len(), 0);
+    }
+
+    #[test]
+    fn test_peer_public_keys_base64() {
+        let wg = WgDeviceBuilder::new()
+            .ifname("wg0")
+            .peer(WgPeerBuilder::new([0x01; WG_KEY_LEN]).build())
+            .peer(WgPeer(vec![WgPeerAttrs::PersistentKeepalive(25)]))
+            .peer(WgPeerBuilder::new([0xff; WG_KEY_LEN]).build())
+            .build();
+        assert_eq!(
+            wg.peer_public_keys_base64(),
+            [
+                "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=",
+                "//////////////////////////////////////////8=",
+            ]
+        );
+        assert!(Wireguard::dump_all().peer_public_keys_base64().is_empty());
+    }
+
+    #[test]
+    fn test_clear_peer_stats() {
+        let mut wg = Wireguard {
+            cmd: WireguardCmd::GetDevice,
+            nlas: vec![
+                WgDeviceAttrs::IfName("wg0".to_string()),
+                WgDeviceAttrs::PublicKey([0x0a; WG_KEY_LEN]),
+                WgDeviceAttrs::Peers(vec![WgPeer(vec![
+                    WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN]),
+                    WgPeerAttrs::LastHandshake(std::time::UNIX_EPOCH),
+                    WgPeerAttrs::RxBytes(1024),
+                    WgPeerAttrs::TxBytes(2048),
+                    WgPeerAttrs::PersistentKeepalive(25),
+                ])]),
+            ],
+        };
+        wg.clear_peer_stats();
+        assert_eq!(wg.cmd, WireguardCmd::GetDevice);
+        assert_eq!(
+            wg.nlas[2],
+            WgDeviceAttrs::Peers(vec![WgPeer(vec![
+                WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN]),
+                WgPeerAttrs::PersistentKeepalive(25),
+            ])])
+        );
+        assert_eq!(wg.nlas[1], WgDeviceAttrs::PublicKey([0x0a; WG_KEY_LEN]));
+    }
+
+    #[test]
+    fn test_peer_by_key_mut() {
+        let mut wg = WgDeviceBuilder::new()
+            .ifname("wg0")
+            .peer(WgPeerBuilder::new([0x01; WG_KEY_LEN]).build())
+            .peer(WgPeerBuilder::new([0x02; WG_KEY_LEN]).build())
+            .build();
+        let peer = wg.peer_by_key_mut(&[0x02; WG_KEY_LEN]).unwrap();
+        peer.0.push(WgPeerAttrs::PersistentKeepalive(25));
+        assert!(wg.peer_by_key_mut(&[0x03; WG_KEY_LEN]).is_none());
+        assert_eq!(
+            wg.peer_by_key(&[0x02; WG_KEY_LEN]).unwrap().0,
+            vec![
+                WgPeerAttrs::PublicKey([0x02; WG_KEY_LEN]),
+                WgPeerAttrs::PersistentKeepalive(25),
+            ]
+        );
+        assert_eq!(wg.peer_by_key(&[0x01; WG_KEY_LEN]).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_is_empty() {
+        assert!(Wireguard::dump_all().is_empty());
+        let mut wg = Wireguard::dump_all();
+        wg.set_ifname("wg0");
+        assert!(!wg.is_empty());
+        // An empty Peers attribute is still an attribute.
+        let wg = Wireguard {
+            cmd: WireguardCmd::SetDevice,
+            nlas: vec![WgDeviceAttrs::Peers(vec![])],
+        };
+        assert!(!wg.is_empty());
+    }
+
+    #[test]
+    fn test_peer_entry() {
+        let mut wg = WgDeviceBuilder::new()
+            .ifname("wg0")
+            .peer(
+                WgPeerBuilder::new([0x01; WG_KEY_LEN])
+                    .persistent_keepalive(25)
+                    .build(),
+            )
+            .build();
+        // Hit: the existing peer is returned.
+        let peer = wg.peer_entry([0x01; WG_KEY_LEN]);
+        assert_eq!(peer[1], WgPeerAttrs::PersistentKeepalive(25));
+        peer.0.push(WgPeerAttrs::Flags(WGPEER_F_UPDATE_ONLY));
+        assert_eq!(wg.peers().count(), 1);
+
+        // Miss: a peer with only the key is appended.
+        let peer = wg.peer_entry([0x02; WG_KEY_LEN]);
+        assert_eq!(peer.0, vec![WgPeerAttrs::PublicKey([0x02; WG_KEY_LEN])]);
+        peer.0.push(WgPeerAttrs::PersistentKeepalive(10));
+        let peers: Vec<_> = wg.peers().cloned().collect();
+        assert_eq!(peers.len(), 2);
+        assert_eq!(peers[0].raw_flags(), Some(WGPEER_F_UPDATE_ONLY));
+        assert_eq!(peers[1][1], WgPeerAttrs::PersistentKeepalive(10));
+
+        let mut wg = Wireguard::dump_all();
+        wg.peer_entry([0x03; WG_KEY_LEN]);
+        assert_eq!(
+            wg.nlas,
+            vec![WgDeviceAttrs::Peers(vec![WgPeer(vec![
+                WgPeerAttrs::PublicKey([0x03; WG_KEY_LEN])
+            ])])]
+        );
+    }
+
+    #[test]
+    fn test_extend_peers() {
+        let mut wg = crate::WgDeviceBuilder::new().ifname("wg0").build();
+        wg.extend(vec![WgPeer(vec![WgPeerAttrs::PublicKey(
+            [0x01; WG_KEY_LEN],
+        )])]);
+        wg.extend(vec![WgPeer(vec![WgPeerAttrs::PublicKey(
+            [0x02; WG_KEY_LEN],
+        )])]);
+        assert_eq!(
+            wg.nlas,
+            vec![
+                WgDeviceAttrs::IfName("wg0".to_string()),
+                WgDeviceAttrs::Peers(vec![
+                    WgPeer(vec![WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN])]),
+                    WgPeer(vec![WgPeerAttrs::PublicKey([0x02; WG_KEY_LEN])]),
+                ]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_collect_peers() {
+        let peers = vec![
+            WgPeer(vec![WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN])]),
+            WgPeer(vec![WgPeerAttrs::PublicKey([0x02; WG_KEY_LEN])]),
+        ];
+        let mut wg: Wireguard = peers.clone().into_iter().collect();
+        assert_eq!(wg.cmd, WireguardCmd::SetDevice);
+        assert_eq!(wg.nlas, vec![WgDeviceAttrs::Peers(peers.clone())]);
+        assert_eq!(wg.validate_identity(), Err(WgError::MissingIdentity));
+        wg.set_ifname("wg0");
+        assert_eq!(wg.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_private_key_bytes() {
+        let wg = WgDeviceBuilder::new()
+            .ifname("wg0")
+            .private_key([0xaa; WG_KEY_LEN])
+            .build();
+        let key = wg.private_key_bytes().unwrap();
+        assert_eq!(key, &[0xaa; WG_KEY_LEN]);
+        match &wg.nlas[1] {
+            WgDeviceAttrs::PrivateKey(stored) => {
+                assert!(std::ptr::eq(key, stored))
+            }
+            nla => panic!("unexpected attribute {:?}", nla),
+        }
+        assert_eq!(Wireguard::dump_all().private_key_bytes(), None);
+    }
+
+    #[test]
+    fn test_has_private_key() {
+        let mut wg = Wireguard {
+            cmd: WireguardCmd::GetDevice,
+            nlas: vec![WgDeviceAttrs::IfName("wg0".to_string())],
+        };
+        assert!(!wg.has_private_key());
+        wg.nlas.push(WgDeviceAttrs::PrivateKey([0; WG_KEY_LEN]));
+        assert!(!wg.has_private_key());
+        wg.nlas[1] = WgDeviceAttrs::PrivateKey([0xaa; WG_KEY_LEN]);
+        assert!(wg.has_private_key());
+    }
+
+    #[test]
+    fn test_parse_nlas_at() {
+        let wg = Wireguard {
+            cmd: WireguardCmd::SetDevice,
+            nlas: vec![WgDeviceAttrs::IfName("wg0".to_string())],
+        };
+        let mut buf = vec![0; wg.buffer_len()];
+        wg.emit(&mut buf);
+        assert_eq!(Wireguard::parse_nlas_at(&buf).unwrap(), wg.nlas);
+
+        // A WGDEVICE_A_IFINDEX attribute with a 2 bytes value.
+        let buf = [6, 0, 1, 0, 0, 0, 0, 0];
+        let (err, line) = (Wireguard::parse_nlas_at(&buf), line!());
+        let err = err.unwrap_err();
+        match &err {
+            WgError::DecodeAt { location, .. } => {
+                assert_eq!(location.file(), file!());
+                assert_eq!(location.line(), line);
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+        assert!(err
+            .to_string()
+            .starts_with(&format!("{}:{}:", file!(), line)));
+    }
+
+    #[test]
+    fn test_peer_allowed_ip_pairs() {
+        let v6: IpAddr = "fd00::2".parse().unwrap();
+        let wg = WgDeviceBuilder::new()
+            .ifname("wg0")
+            .peer(
+                WgPeerBuilder::new([0x01; WG_KEY_LEN])
+                    .allowed_ip_unchecked([10, 0, 0, 1].into(), 32)
+                    .allowed_ip_unchecked(v6, 128)
+                    .build(),
+            )
+            .peer(
+                WgPeerBuilder::new([0x02; WG_KEY_LEN])
+                    .allowed_ip(WgAllowedIp(vec![WgAllowedIpAttrs::Cidr(8)]))
+                    .allowed_ip_unchecked([10, 0, 1, 0].into(), 24)
+                    .build(),
+            )
+            .peer(WgPeer(vec![WgPeerAttrs::AllowedIps(vec![
+                WgAllowedIp::new_unchecked([10, 0, 2, 0].into(), 24),
+            ])]))
+            .build();
+        assert_eq!(
+            wg.peer_allowed_ip_pairs().collect::<Vec<_>>(),
+            vec![
+                ([0x01; WG_KEY_LEN], IpAddr::from([10, 0, 0, 1]), 32),
+                ([0x01; WG_KEY_LEN], v6, 128),
+                ([0x02; WG_KEY_LEN], IpAddr::from([10, 0, 1, 0]), 24),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_set_identity() {
+        let mut wg =
+            WgDeviceBuilder::new().ifindex(3).listen_port(51820).build();
+        wg.set_ifname("wg0");
+        assert_eq!(
+            wg.nlas,
+            vec![
+                WgDeviceAttrs::IfName("wg0".to_string()),
+                WgDeviceAttrs::ListenPort(51820),
+            ]
+        );
+        wg.set_ifindex(4);
+        assert_eq!(
+            wg.nlas,
+            vec![WgDeviceAttrs::IfIndex(4), WgDeviceAttrs::ListenPort(51820)]
+        );
+
+        let mut wg = Wireguard::dump_all();
+        wg.nlas.push(WgDeviceAttrs::IfName("wg0".to_string()));
+        wg.nlas.push(WgDeviceAttrs::IfIndex(3));
+        wg.set_ifindex(3);
+        assert_eq!(wg.nlas, vec![WgDeviceAttrs::IfIndex(3)]);
+        assert_eq!(wg.validate_get(), Ok(()));
+    }
+
+    #[test]
+    fn test_is_continuation_fragment() {
+        let peer = WgPeerBuilder::new([0x01; WG_KEY_LEN]).build();
+        let head = Wireguard {
+            cmd: WireguardCmd::GetDevice,
+            nlas: vec![
+                WgDeviceAttrs::ListenPort(51820),
+                WgDeviceAttrs::Fwmark(0),
+                WgDeviceAttrs::IfIndex(3),
+                WgDeviceAttrs::IfName("wg0".to_string()),
+                WgDeviceAttrs::Peers(vec![peer.clone()]),
+            ],
+        };
+        assert!(!head.is_continuation_fragment());
+        let continuation = Wireguard {
+            cmd: WireguardCmd::GetDevice,
+            nlas: vec![WgDeviceAttrs::Peers(vec![peer])],
+        };
+        assert!(continuation.is_continuation_fragment());
+        assert!(!Wireguard::dump_all().is_continuation_fragment());
+    }
+
+    #[test]
+    fn test_peers_bytes() {
+        let mut wg =
+            WgDeviceBuilder::new().ifname("wg0").listen_port(1).build();
+        assert_eq!(wg.peers_bytes(), 0);
+        let device_len = wg.buffer_len();
+        wg.extend(vec![
+            WgPeerBuilder::new([0x01; WG_KEY_LEN])
+                .allowed_ip_unchecked([10, 0, 0, 1].into(), 32)
+                .build(),
+            WgPeerBuilder::new([0x02; WG_KEY_LEN]).build(),
+        ]);
+        let peers = wg.nlas.last().unwrap().emit_to_vec();
+        assert_eq!(wg.peers_bytes(), peers.len());
+        assert_eq!(wg.buffer_len(), device_len + peers.len());
+    }
+
+    #[test]
+    fn test_command() {
+        let mut wg = Wireguard::dump_all();
+        assert_eq!(wg.wg_command(), WireguardCmd::GetDevice);
+        wg.set_command(WireguardCmd::SetDevice);
+        assert_eq!(wg.wg_command(), WireguardCmd::SetDevice);
+        assert_eq!(wg.command(), WG_CMD_SET_DEVICE);
+    }
+
+    #[test]
+    fn test_expect_command() {
+        let wg = Wireguard::dump_all();
+        assert_eq!(wg.expect_command(WireguardCmd::GetDevice), Ok(()));
+        let err = wg.expect_command(WireguardCmd::SetDevice).unwrap_err();
+        assert_eq!(
+            err,
+            WgError::UnexpectedCommand {
+                expected: WireguardCmd::SetDevice,
+                got: WireguardCmd::GetDevice,
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "unexpected command GetDevice, expected SetDevice"
+        );
+    }
+
+    #[test]
+    fn test_into_peers() {
+        let first = WgPeerBuilder::new([0x01; WG_KEY_LEN]).build();
+        let second = WgPeerBuilder::new([0x02; WG_KEY_LEN])
+            .allowed_ip("10.0.0.2".parse().unwrap())
+            .build();
+        let wg = WgDeviceBuilder::new()
+            .ifname("wg0")
+            .listen_port(51820)
+            .peer(first.clone())
+            .peer(second.clone())
+            .build();
+        assert_eq!(wg.into_peers(), vec![first, second]);
+        assert!(Wireguard::dump_all().into_peers().is_empty());
+    }
+
+    #[test]
+    fn test_split_device_and_peers() {
+        let peer = WgPeerBuilder::new([0x01; WG_KEY_LEN])
+            .allowed_ip("10.0.0.1".parse().unwrap())
+            .build();
+        let wg = WgDeviceBuilder::new()
+            .ifname("wg0")
+            .private_key([0xaa; WG_KEY_LEN])
+            .listen_port(51820)
+            .fwmark(0x1234)
+            .replace_peers()
+            .peer(peer.clone())
+            .build();
+        let (device, peers) = wg.split_device_and_peers();
+        assert_eq!(
+            device,
+            Wireguard {
+                cmd: WireguardCmd::SetDevice,
+                nlas: vec![
+                    WgDeviceAttrs::IfName("wg0".to_string()),
+                    WgDeviceAttrs::PrivateKey([0xaa; WG_KEY_LEN]),
+                    WgDeviceAttrs::ListenPort(51820),
+                    WgDeviceAttrs::Fwmark(0x1234),
+                ],
+            }
+        );
+        assert_eq!(
+            peers,
+            Wireguard {
+                cmd: WireguardCmd::SetDevice,
+                nlas: vec![
+                    WgDeviceAttrs::IfName("wg0".to_string()),
+                    WgDeviceAttrs::Flags(WGDEVICE_F_REPLACE_PEERS),
+                    WgDeviceAttrs::Peers(vec![peer]),
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn test_without_peers() {
+        let wg = crate::WgDeviceBuilder::new()
+            .ifname("wg0")
+            .listen_port(51820)
+            .peer(crate::WgPeerBuilder::new([0x01; WG_KEY_LEN]).build())
+            .build()
+            .without_peers();
+        assert_eq!(
+            wg.nlas,
+            vec![
+                WgDeviceAttrs::IfName("wg0".to_string()),
+                WgDeviceAttrs::ListenPort(51820),
+            ]
+        );
+
+        // The builder doesn't emit an empty peer list.
+        let wg = crate::WgDeviceBuilder::new().ifname("wg0").build();
+        assert!(!wg
+            .nlas
+            .iter()
+            .any(|nla| matches!(nla, WgDeviceAttrs::Peers(_))));
+    }
+
+    #[test]
+    fn test_dump_all_has_no_identity() {
+        let wg = Wireguard::dump_all();
+        assert_eq!(wg.cmd, WireguardCmd::GetDevice);
+        assert!(!wg.nlas.iter().any(|nla| matches!(
+            nla,
+            WgDeviceAttrs::IfIndex(_) | WgDeviceAttrs::IfName(_)
+        )));
+    }
 }
@@ -0,0 +1,107 @@
+// SPDX-License-Identifier: MIT
+
+use std::{fmt, net::SocketAddr};
+
+use crate::nlas::{WgAllowedIpAttrs, WgPeer, WgPeerAttrs};
+
+/// An informational note about a configuration the kernel accepts but
+/// that is often a mistake. Unlike a [`WgError`](crate::WgError), a lint
+/// never means the message is invalid.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Lint {
+    /// The endpoint of the peer is of a family none of its allowed IPs
+    /// is, e.g. an IPv4 endpoint for a peer only allowed IPv6 addresses.
+    /// The tunnel works, but this often comes from a copy-paste mistake.
+    EndpointFamilyMismatch { endpoint: SocketAddr },
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Lint::EndpointFamilyMismatch { endpoint } => write!(
+                f,
+                "the endpoint {} is {} but no allowed IP is",
+                endpoint,
+                if endpoint.is_ipv4() { "IPv4" } else { "IPv6" }
+            ),
+        }
+    }
+}
+
+impl WgPeer {
+    /// A [`Lint::EndpointFamilyMismatch`] when the peer has an endpoint
+    /// and allowed IPs, and none of the allowed IPs is of the family of
+    /// the endpoint. The protocol doesn't tie the two together, so this is
+    /// advisory only.
+    pub fn endpoint_family_hint(&self) -> Option<Lint> {
+        let endpoint = *self.endpoint()?;
+        let mut ips = self
+            .iter()
+            .filter_map(|nla| match nla {
+                WgPeerAttrs::AllowedIps(ips) => Some(ips),
+                _ => None,
+            })
+            .flatten()
+            .filter_map(|ip| {
+                ip.iter().find_map(|nla| match nla {
+                    WgAllowedIpAttrs::IpAddr(addr) => Some(*addr),
+                    _ => None,
+                })
+            })
+            .peekable();
+        ips.peek()?;
+        if ips.any(|addr| addr.is_ipv4() == endpoint.is_ipv4()) {
+            None
+        } else {
+            Some(Lint::EndpointFamilyMismatch { endpoint })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{constants::WG_KEY_LEN, WgPeerBuilder};
+
+    #[test]
+    fn test_endpoint_family_hint() {
+        let peer = |endpoint: &str, ips: &[&str]| {
+            let mut peer = WgPeerBuilder::new([0x01; WG_KEY_LEN])
+                .endpoint(endpoint.parse().unwrap());
+            for ip in ips {
+                peer = peer.allowed_ip(ip.parse().unwrap());
+            }
+            peer.build()
+        };
+        let endpoint = "192.0.2.1:51820".parse().unwrap();
+        let lint = peer("192.0.2.1:51820", &["fd00::/64"])
+            .endpoint_family_hint()
+            .unwrap();
+        assert_eq!(lint, Lint::EndpointFamilyMismatch { endpoint });
+        assert_eq!(
+            lint.to_string(),
+            "the endpoint 192.0.2.1:51820 is IPv4 but no allowed IP is"
+        );
+        assert_eq!(
+            peer("[2001:db8::1]:51820", &["10.0.0.0/24"])
+                .endpoint_family_hint()
+                .map(|lint| lint.to_string()),
+            Some(
+                "the endpoint [2001:db8::1]:51820 is IPv6 but no allowed IP \
+                 is"
+                .to_string()
+            )
+        );
+
+        for ips in [&["fd00::/64", "10.0.0.0/24"][..], &[]] {
+            assert_eq!(
+                peer("192.0.2.1:51820", ips).endpoint_family_hint(),
+                None
+            );
+        }
+        let no_endpoint = WgPeerBuilder::new([0x01; WG_KEY_LEN])
+            .allowed_ip("fd00::/64".parse().unwrap())
+            .build();
+        assert_eq!(no_endpoint.endpoint_family_hint(), None);
+    }
+}
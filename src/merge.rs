@@ -0,0 +1,541 @@
+// SPDX-License-Identifier: MIT
+
+use std::collections::{HashMap, HashSet};
+
+use netlink_packet_utils::nla::Nla;
+
+use crate::{
+    nlas::{WgAllowedIp, WgDeviceAttrs, WgPeer, WgPeerAttrs},
+    Wireguard, WireguardCmd,
+};
+
+/// What [`Wireguard::merge_peers`] keeps of a peer found in both
+/// messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// Keep the peer of `self`.
+    PreferSelf,
+    /// Replace the peer of `self` with the one of `other`.
+    PreferOther,
+    /// Keep the attributes of the peer of `self`, with the allowed IPs of
+    /// both peers: those of `self` first, then the new ones of `other`.
+    MergeAllowedIps,
+}
+
+impl Wireguard {
+    /// Add the peers of `other` to `self`, matching them by public key.
+    ///
+    /// Peers of `other` missing from `self` are appended, and `strategy`
+    /// decides what happens to the peers found in both. The device
+    /// attributes of `other` are ignored, and so are its peers without a
+    /// public key. See [`Wireguard::overlay_config`] to apply a
+    /// configuration onto a dump while keeping its statistics.
+    pub fn merge_peers(&mut self, other: &Wireguard, strategy: MergeStrategy) {
+        let mut added = Vec::new();
+        for other_peer in other.peers() {
+            let key = match other_peer.public_key() {
+                Some(key) => key,
+                None => continue,
+            };
+            match self.peer_by_key_mut(key) {
+                Some(peer) => match strategy {
+                    MergeStrategy::PreferSelf => (),
+                    MergeStrategy::PreferOther => *peer = other_peer.clone(),
+                    MergeStrategy::MergeAllowedIps => {
+                        let allowed_ips = union_allowed_ips(peer, other_peer);
+                        set_allowed_ips(peer, allowed_ips);
+                    }
+                },
+                None => added.push(other_peer.clone()),
+            }
+        }
+        if !added.is_empty() {
+            self.extend(added);
+        }
+    }
+
+    /// Overlay a desired configuration onto a dumped device.
+    ///
+    /// The `PrivateKey`, `ListenPort` and `Fwmark` attributes of `config`
+    /// replace the ones of `self`. If the private key changes, the dumped
+    /// `PublicKey` no longer matches it and is removed.
+    ///
+    /// Peers are matched by public key: the settings of a config peer
+    /// (preshared key, endpoint, keepalive, allowed IPs and protocol
+    /// version) replace the dumped ones while the runtime statistics
+    /// (handshake time and transfer counters) of the dump are kept. Config
+    /// peers missing from the dump are appended, dump peers missing from
+    /// the config are left untouched. Flags are `SetDevice` only and are
+    /// not copied.
+    pub fn overlay_config(&mut self, config: &Wireguard) {
+        for nla in &config.nlas {
+            match nla {
+                WgDeviceAttrs::PrivateKey(key) => {
+                    let changed = self.nlas.iter().any(|nla| {
+                        matches!(nla, WgDeviceAttrs::PrivateKey(k) if k != key)
+                    });
+                    if changed {
+                        self.nlas.retain(|nla| {
+                            !matches!(nla, WgDeviceAttrs::PublicKey(_))
+                        });
+                    }
+                    replace_device_attr(&mut self.nlas, nla);
+                }
+                WgDeviceAttrs::ListenPort(_) | WgDeviceAttrs::Fwmark(_) => {
+                    replace_device_attr(&mut self.nlas, nla);
+                }
+                _ => (),
+            }
+        }
+
+        let mut added = Vec::new();
+        for config_peer in config.peers() {
+            let key = match config_peer.public_key() {
+                Some(key) => key,
+                None => continue,
+            };
+            match self.peer_by_key_mut(key) {
+                Some(peer) => overlay_peer(peer, config_peer),
+                None => {
+                    let mut peer = WgPeer(vec![WgPeerAttrs::PublicKey(*key)]);
+                    overlay_peer(&mut peer, config_peer);
+                    added.push(peer);
+                }
+            }
+        }
+        if !added.is_empty() {
+            self.extend(added);
+        }
+    }
+
+    /// Turn a dumped device into a `SetDevice` message re-applying its
+    /// configuration.
+    ///
+    /// The runtime statistics of the peers (handshake time and transfer
+    /// counters) and the read-only device public key are removed. Since
+    /// the kernel rejects messages carrying both, `WGDEVICE_A_IFINDEX` is
+    /// dropped when `WGDEVICE_A_IFNAME` is present. No flag is added.
+    pub fn into_set_template(mut self) -> Wireguard {
+        self.cmd = WireguardCmd::SetDevice;
+        let has_name = self
+            .nlas
+            .iter()
+            .any(|nla| matches!(nla, WgDeviceAttrs::IfName(_)));
+        self.nlas.retain(|nla| match nla {
+            WgDeviceAttrs::PublicKey(_) => false,
+            WgDeviceAttrs::IfIndex(_) => !has_name,
+            _ => true,
+        });
+        for peer in self.peers_iter_mut() {
+            *peer = peer.to_config();
+        }
+        self
+    }
+
+    /// Collapse the peers listed several times with the same public key
+    /// into one, at the position of the first occurrence.
+    ///
+    /// The attributes of the last occurrence win. With
+    /// `merge_allowed_ips`, the peer gets the allowed IPs of every
+    /// occurrence, in order and without repetitions, instead of those of
+    /// the last one. Peers without a public key are left untouched.
+    pub fn dedup_peers_by_key(&mut self, merge_allowed_ips: bool) {
+        let mut first = HashMap::new();
+        let mut duplicates = HashSet::new();
+        let mut replacements: Vec<((usize, usize), WgPeer)> = Vec::new();
+        for (i, nla) in self.nlas.iter().enumerate() {
+            let peers = match nla {
+                WgDeviceAttrs::Peers(peers) => peers,
+                _ => continue,
+            };
+            for (j, peer) in peers.iter().enumerate() {
+                let key = match peer.public_key() {
+                    Some(key) => *key,
+                    None => continue,
+                };
+                let slot = *first.entry(key).or_insert((i, j));
+                if slot == (i, j) {
+                    continue;
+                }
+                duplicates.insert((i, j));
+                let mut peer = peer.clone();
+                if let Some(pos) = replacements.iter().position(|r| r.0 == slot)
+                {
+                    let (_, previous) = replacements.remove(pos);
+                    if merge_allowed_ips {
+                        merge_allowed_ips_into(&mut peer, &previous);
+                    }
+                } else if merge_allowed_ips {
+                    if let WgDeviceAttrs::Peers(peers) = &self.nlas[slot.0] {
+                        merge_allowed_ips_into(&mut peer, &peers[slot.1]);
+                    }
+                }
+                replacements.push((slot, peer));
+            }
+        }
+        for ((i, j), peer) in replacements {
+            if let WgDeviceAttrs::Peers(peers) = &mut self.nlas[i] {
+                peers[j] = peer;
+            }
+        }
+        for (i, nla) in self.nlas.iter_mut().enumerate() {
+            if let WgDeviceAttrs::Peers(peers) = nla {
+                let mut j = 0;
+                peers.retain(|_| {
+                    j += 1;
+                    !duplicates.contains(&(i, j - 1))
+                });
+            }
+        }
+    }
+}
+
+fn replace_device_attr(nlas: &mut Vec<WgDeviceAttrs>, new: &WgDeviceAttrs) {
+    match nlas.iter_mut().find(|nla| nla.kind() == new.kind()) {
+        Some(nla) => *nla = new.clone(),
+        None => nlas.push(new.clone()),
+    }
+}
+
+// Put the allowed IPs of `previous` before the ones of `peer`.
+fn merge_allowed_ips_into(peer: &mut WgPeer, previous: &WgPeer) {
+    let allowed_ips = union_allowed_ips(previous, peer);
+    set_allowed_ips(peer, allowed_ips);
+}
+
+// The allowed IPs of `first` then those of `second`, without repetitions.
+fn union_allowed_ips(first: &WgPeer, second: &WgPeer) -> Vec<WgAllowedIp> {
+    let mut allowed_ips: Vec<WgAllowedIp> = Vec::new();
+    for p in [first, second] {
+        for nla in p.iter() {
+            if let WgPeerAttrs::AllowedIps(ips) = nla {
+                for ip in ips {
+                    if !allowed_ips.contains(ip) {
+                        allowed_ips.push(ip.clone());
+                    }
+                }
+            }
+        }
+    }
+    allowed_ips
+}
+
+fn set_allowed_ips(peer: &mut WgPeer, allowed_ips: Vec<WgAllowedIp>) {
+    peer.0
+        .retain(|nla| !matches!(nla, WgPeerAttrs::AllowedIps(_)));
+    peer.0.push(WgPeerAttrs::AllowedIps(allowed_ips));
+}
+
+fn overlay_peer(peer: &mut WgPeer, config: &WgPeer) {
+    for nla in config.iter() {
+        match nla {
+            WgPeerAttrs::PresharedKey(_)
+            | WgPeerAttrs::Endpoint(_)
+            | WgPeerAttrs::PersistentKeepalive(_)
+            | WgPeerAttrs::AllowedIps(_)
+            | WgPeerAttrs::ProtocolVersion(_) => {
+                match peer.0.iter_mut().find(|p| p.kind() == nla.kind()) {
+                    Some(p) => *p = nla.clone(),
+                    None => peer.0.push(nla.clone()),
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+    use crate::{constants::*, WgDeviceBuilder, WgPeerBuilder, WgPeerFlags};
+
+    fn merge_sources() -> (Wireguard, Wireguard) {
+        let peer = |key, keepalive, ip: &str| {
+            WgPeerBuilder::new([key; WG_KEY_LEN])
+                .persistent_keepalive(keepalive)
+                .allowed_ip(ip.parse().unwrap())
+                .build()
+        };
+        let this = WgDeviceBuilder::new()
+            .ifname("wg0")
+            .peer(peer(0x01, 25, "10.0.0.1"))
+            .peer(peer(0x02, 25, "10.0.0.2"))
+            .build();
+        let other = WgDeviceBuilder::new()
+            .ifname("wg1")
+            .peer(peer(0x02, 10, "10.0.1.2"))
+            .peer(peer(0x03, 10, "10.0.0.3"))
+            .build();
+        (this, other)
+    }
+
+    fn keepalives_and_ips(wg: &Wireguard) -> Vec<(u8, u16, Vec<String>)> {
+        wg.peers()
+            .map(|peer| {
+                let keepalive = peer.iter().find_map(|nla| match nla {
+                    WgPeerAttrs::PersistentKeepalive(secs) => Some(*secs),
+                    _ => None,
+                });
+                (
+                    peer.public_key().unwrap()[0],
+                    keepalive.unwrap(),
+                    peer.allowed_ip_strings(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_peers_prefer_self() {
+        let (mut this, other) = merge_sources();
+        this.merge_peers(&other, MergeStrategy::PreferSelf);
+        assert_eq!(this.nlas[0], WgDeviceAttrs::IfName("wg0".to_string()));
+        assert_eq!(
+            keepalives_and_ips(&this),
+            vec![
+                (0x01, 25, vec!["10.0.0.1/32".to_string()]),
+                (0x02, 25, vec!["10.0.0.2/32".to_string()]),
+                (0x03, 10, vec!["10.0.0.3/32".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_peers_prefer_other() {
+        let (mut this, other) = merge_sources();
+        this.merge_peers(&other, MergeStrategy::PreferOther);
+        assert_eq!(
+            keepalives_and_ips(&this),
+            vec![
+                (0x01, 25, vec!["10.0.0.1/32".to_string()]),
+                (0x02, 10, vec!["10.0.1.2/32".to_string()]),
+                (0x03, 10, vec!["10.0.0.3/32".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_peers_merge_allowed_ips() {
+        let (mut this, mut other) = merge_sources();
+        // Allowed IPs already present are not repeated.
+        other
+            .peer_by_key_mut(&[0x02; WG_KEY_LEN])
+            .unwrap()
+            .0
+            .push(WgPeerAttrs::AllowedIps(vec!["10.0.0.2".parse().unwrap()]));
+        this.merge_peers(&other, MergeStrategy::MergeAllowedIps);
+        assert_eq!(
+            keepalives_and_ips(&this),
+            vec![
+                (0x01, 25, vec!["10.0.0.1/32".to_string()]),
+                (
+                    0x02,
+                    25,
+                    vec!["10.0.0.2/32".to_string(), "10.0.1.2/32".to_string()]
+                ),
+                (0x03, 10, vec!["10.0.0.3/32".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_overlay_config() {
+        let handshake = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        let mut dump = Wireguard {
+            cmd: WireguardCmd::GetDevice,
+            nlas: vec![
+                WgDeviceAttrs::IfIndex(3),
+                WgDeviceAttrs::IfName("wg0".to_string()),
+                WgDeviceAttrs::PrivateKey([0xaa; WG_KEY_LEN]),
+                WgDeviceAttrs::PublicKey([0xab; WG_KEY_LEN]),
+                WgDeviceAttrs::ListenPort(51820),
+                WgDeviceAttrs::Fwmark(0),
+                WgDeviceAttrs::Peers(vec![
+                    WgPeer(vec![
+                        WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN]),
+                        WgPeerAttrs::Endpoint(
+                            "10.0.0.1:51820".parse().unwrap(),
+                        ),
+                        WgPeerAttrs::PersistentKeepalive(0),
+                        WgPeerAttrs::LastHandshake(handshake),
+                        WgPeerAttrs::RxBytes(10),
+                        WgPeerAttrs::TxBytes(20),
+                        WgPeerAttrs::AllowedIps(vec![]),
+                    ]),
+                    WgPeer(vec![WgPeerAttrs::PublicKey([0x02; WG_KEY_LEN])]),
+                ]),
+            ],
+        };
+        let allowed_ip = WgAllowedIp::new([10, 0, 0, 0].into(), 24).unwrap();
+        let config = WgDeviceBuilder::new()
+            .ifname("wg0")
+            .listen_port(51821)
+            .peer(
+                WgPeerBuilder::new([0x01; WG_KEY_LEN])
+                    .flags(WgPeerFlags::REPLACE_ALLOWEDIPS)
+                    .endpoint("10.0.0.2:51820".parse().unwrap())
+                    .allowed_ip(allowed_ip.clone())
+                    .build(),
+            )
+            .peer(WgPeerBuilder::new([0x03; WG_KEY_LEN]).build())
+            .build();
+
+        dump.overlay_config(&config);
+        assert_eq!(
+            dump.nlas,
+            vec![
+                WgDeviceAttrs::IfIndex(3),
+                WgDeviceAttrs::IfName("wg0".to_string()),
+                WgDeviceAttrs::PrivateKey([0xaa; WG_KEY_LEN]),
+                WgDeviceAttrs::PublicKey([0xab; WG_KEY_LEN]),
+                WgDeviceAttrs::ListenPort(51821),
+                WgDeviceAttrs::Fwmark(0),
+                WgDeviceAttrs::Peers(vec![
+                    WgPeer(vec![
+                        WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN]),
+                        WgPeerAttrs::Endpoint(
+                            "10.0.0.2:51820".parse().unwrap()
+                        ),
+                        WgPeerAttrs::PersistentKeepalive(0),
+                        WgPeerAttrs::LastHandshake(handshake),
+                        WgPeerAttrs::RxBytes(10),
+                        WgPeerAttrs::TxBytes(20),
+                        WgPeerAttrs::AllowedIps(vec![allowed_ip]),
+                    ]),
+                    WgPeer(vec![WgPeerAttrs::PublicKey([0x02; WG_KEY_LEN])]),
+                    WgPeer(vec![WgPeerAttrs::PublicKey([0x03; WG_KEY_LEN])]),
+                ]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_overlay_config_new_private_key() {
+        let mut dump = Wireguard {
+            cmd: WireguardCmd::GetDevice,
+            nlas: vec![
+                WgDeviceAttrs::PrivateKey([0xaa; WG_KEY_LEN]),
+                WgDeviceAttrs::PublicKey([0xab; WG_KEY_LEN]),
+            ],
+        };
+        let config = WgDeviceBuilder::new()
+            .private_key([0xcc; WG_KEY_LEN])
+            .build();
+        dump.overlay_config(&config);
+        assert_eq!(
+            dump.nlas,
+            vec![WgDeviceAttrs::PrivateKey([0xcc; WG_KEY_LEN])]
+        );
+    }
+
+    #[test]
+    fn test_into_set_template() {
+        let dump = Wireguard {
+            cmd: WireguardCmd::GetDevice,
+            nlas: vec![
+                WgDeviceAttrs::IfIndex(3),
+                WgDeviceAttrs::IfName("wg0".to_string()),
+                WgDeviceAttrs::PrivateKey([0xaa; WG_KEY_LEN]),
+                WgDeviceAttrs::PublicKey([0xab; WG_KEY_LEN]),
+                WgDeviceAttrs::ListenPort(51820),
+                WgDeviceAttrs::Fwmark(0),
+                WgDeviceAttrs::Peers(vec![WgPeer(vec![
+                    WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN]),
+                    WgPeerAttrs::PresharedKey([0; WG_KEY_LEN]),
+                    WgPeerAttrs::LastHandshake(SystemTime::UNIX_EPOCH),
+                    WgPeerAttrs::RxBytes(1024),
+                    WgPeerAttrs::TxBytes(512),
+                    WgPeerAttrs::PersistentKeepalive(25),
+                    WgPeerAttrs::AllowedIps(vec![WgAllowedIp::new_unchecked(
+                        [10, 0, 0, 1].into(),
+                        32,
+                    )]),
+                    WgPeerAttrs::ProtocolVersion(1),
+                ])]),
+            ],
+        };
+        let template = dump.into_set_template();
+        assert_eq!(
+            template,
+            Wireguard {
+                cmd: WireguardCmd::SetDevice,
+                nlas: vec![
+                    WgDeviceAttrs::IfName("wg0".to_string()),
+                    WgDeviceAttrs::PrivateKey([0xaa; WG_KEY_LEN]),
+                    WgDeviceAttrs::ListenPort(51820),
+                    WgDeviceAttrs::Fwmark(0),
+                    WgDeviceAttrs::Peers(vec![WgPeer(vec![
+                        WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN]),
+                        WgPeerAttrs::PresharedKey([0; WG_KEY_LEN]),
+                        WgPeerAttrs::PersistentKeepalive(25),
+                        WgPeerAttrs::AllowedIps(vec![
+                            WgAllowedIp::new_unchecked(
+                                [10, 0, 0, 1].into(),
+                                32
+                            )
+                        ]),
+                        WgPeerAttrs::ProtocolVersion(1),
+                    ])]),
+                ],
+            }
+        );
+        assert_eq!(template.validate_set(), Ok(()));
+    }
+
+    #[test]
+    fn test_dedup_peers_by_key() {
+        let ip =
+            |last: u8| WgAllowedIp::new_unchecked([10, 0, 0, last].into(), 32);
+        let peer = |key: u8, keepalive: u16, ips: Vec<WgAllowedIp>| {
+            let mut peer = WgPeerBuilder::new([key; WG_KEY_LEN])
+                .persistent_keepalive(keepalive);
+            for ip in ips {
+                peer = peer.allowed_ip(ip);
+            }
+            peer.build()
+        };
+        let wg = Wireguard {
+            cmd: WireguardCmd::SetDevice,
+            nlas: vec![
+                WgDeviceAttrs::IfName("wg0".to_string()),
+                WgDeviceAttrs::Peers(vec![
+                    peer(0x01, 10, vec![ip(1)]),
+                    peer(0x02, 10, vec![ip(2)]),
+                    peer(0x01, 20, vec![ip(3), ip(1)]),
+                ]),
+                WgDeviceAttrs::Peers(vec![
+                    WgPeer(vec![]),
+                    peer(0x01, 30, vec![ip(4)]),
+                ]),
+            ],
+        };
+
+        let mut last_wins = wg.clone();
+        last_wins.dedup_peers_by_key(false);
+        assert_eq!(
+            last_wins.nlas[1..],
+            [
+                WgDeviceAttrs::Peers(vec![
+                    peer(0x01, 30, vec![ip(4)]),
+                    peer(0x02, 10, vec![ip(2)]),
+                ]),
+                WgDeviceAttrs::Peers(vec![WgPeer(vec![])]),
+            ]
+        );
+
+        let mut merged = wg;
+        merged.dedup_peers_by_key(true);
+        assert_eq!(
+            merged.nlas[1..],
+            [
+                WgDeviceAttrs::Peers(vec![
+                    peer(0x01, 30, vec![ip(1), ip(3), ip(4)]),
+                    peer(0x02, 10, vec![ip(2)]),
+                ]),
+                WgDeviceAttrs::Peers(vec![WgPeer(vec![])]),
+            ]
+        );
+    }
+}
@@ -15,6 +15,8 @@ use netlink_packet_utils::{
 };
 use std::{mem::size_of_val, net::IpAddr};
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub enum WgAllowedIpAttrs {
     Unspec(Vec<u8>),
     Family(u16),
@@ -46,7 +48,9 @@ impl Nla for WgAllowedIpAttrs {
 
     fn emit_value(&self, buffer: &mut [u8]) {
         match self {
-            WgAllowedIpAttrs::Unspec(bytes) => buffer.copy_from_slice(bytes),
+            WgAllowedIpAttrs::Unspec(bytes) => {
+                buffer[..bytes.len()].copy_from_slice(bytes)
+            }
             WgAllowedIpAttrs::Family(v) => NativeEndian::write_u16(buffer, *v),
             WgAllowedIpAttrs::IpAddr(v) => emit_ip(v, buffer),
             WgAllowedIpAttrs::Cidr(v) => buffer[0] = *v,
@@ -69,7 +73,10 @@ impl<'a, T: AsRef<[u8]> + ?Sized> Parseable<NlaBuffer<&'a T>>
                 parse_ip(payload)
                     .context("invalid WGALLOWEDIP_A_IPADDR value")?,
             ),
-            WGALLOWEDIP_A_CIDR_MASK => Self::Cidr(payload[0]),
+            WGALLOWEDIP_A_CIDR_MASK => Self::Cidr(
+                parse_u8(payload)
+                    .context("invalid WGALLOWEDIP_A_CIDR_MASK value")?,
+            ),
             kind => {
                 return Err(DecodeError::from(format!(
                     "invalid NLA kind: {}",
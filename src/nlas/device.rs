@@ -3,6 +3,7 @@
 use crate::{
     constants::*,
     nlas::{WgPeer, WgPeerAttrs},
+    WgKey,
 };
 use anyhow::Context;
 use byteorder::{ByteOrder, NativeEndian};
@@ -12,27 +13,76 @@ use netlink_packet_utils::{
     traits::*,
     DecodeError,
 };
-use std::{convert::TryInto, mem::size_of_val};
+use std::{convert::TryFrom, fmt, mem::size_of_val};
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub enum WgDeviceAttrs {
     Unspec(Vec<u8>),
     IfIndex(u32),
     IfName(String),
-    PrivateKey([u8; WG_KEY_LEN]),
-    PublicKey([u8; WG_KEY_LEN]),
+    PrivateKey(
+        #[cfg_attr(
+            feature = "serde",
+            serde(with = "crate::serde_helpers::base64_key")
+        )]
+        #[cfg_attr(feature = "schemars", schemars(with = "String"))]
+        [u8; WG_KEY_LEN],
+    ),
+    PublicKey(
+        #[cfg_attr(
+            feature = "serde",
+            serde(with = "crate::serde_helpers::base64_key")
+        )]
+        #[cfg_attr(feature = "schemars", schemars(with = "String"))]
+        [u8; WG_KEY_LEN],
+    ),
     ListenPort(u16),
     Fwmark(u32),
     Peers(Vec<WgPeer>),
     Flags(u32),
 }
 
+/// Like a derived `Debug`, except that the private key is shown as
+/// `PrivateKey((hidden))` so that logging a message doesn't leak it.
+impl fmt::Debug for WgDeviceAttrs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WgDeviceAttrs::Unspec(v) => {
+                f.debug_tuple("Unspec").field(v).finish()
+            }
+            WgDeviceAttrs::IfIndex(v) => {
+                f.debug_tuple("IfIndex").field(v).finish()
+            }
+            WgDeviceAttrs::IfName(v) => {
+                f.debug_tuple("IfName").field(v).finish()
+            }
+            WgDeviceAttrs::PrivateKey(_) => f
+                .debug_tuple("PrivateKey")
+                .field(&format_args!("(hidden)"))
+                .finish(),
+            WgDeviceAttrs::PublicKey(v) => {
+                f.debug_tuple("PublicKey").field(v).finish()
+            }
+            WgDeviceAttrs::ListenPort(v) => {
+                f.debug_tuple("ListenPort").field(v).finish()
+            }
+            WgDeviceAttrs::Fwmark(v) => {
+                f.debug_tuple("Fwmark").field(v).finish()
+            }
+            WgDeviceAttrs::Peers(v) => f.debug_tuple("Peers").field(v).finish(),
+            WgDeviceAttrs::Flags(v) => f.debug_tuple("Flags").field(v).finish(),
+        }
+    }
+}
+
 impl Nla for WgDeviceAttrs {
     fn value_len(&self) -> usize {
         match self {
             WgDeviceAttrs::Unspec(bytes) => bytes.len(),
             WgDeviceAttrs::IfIndex(v) => size_of_val(v),
-            WgDeviceAttrs::IfName(v) => v.as_bytes().len() + 1,
+            WgDeviceAttrs::IfName(v) => v.len() + 1,
             WgDeviceAttrs::PrivateKey(v) => size_of_val(v),
             WgDeviceAttrs::PublicKey(v) => size_of_val(v),
             WgDeviceAttrs::ListenPort(v) => size_of_val(v),
@@ -60,7 +110,9 @@ impl Nla for WgDeviceAttrs {
 
     fn emit_value(&self, buffer: &mut [u8]) {
         match self {
-            WgDeviceAttrs::Unspec(bytes) => buffer.copy_from_slice(bytes),
+            WgDeviceAttrs::Unspec(bytes) => {
+                buffer[..bytes.len()].copy_from_slice(bytes)
+            }
             WgDeviceAttrs::IfIndex(v) => NativeEndian::write_u32(buffer, *v),
             WgDeviceAttrs::IfName(s) => {
                 buffer[..s.len()].copy_from_slice(s.as_bytes());
@@ -102,14 +154,14 @@ impl<'a, T: AsRef<[u8]> + ?Sized> Parseable<NlaBuffer<&'a T>>
                     .context("invalid WGDEVICE_A_IFNAME value")?,
             ),
             WGDEVICE_A_PRIVATE_KEY => Self::PrivateKey(
-                payload
-                    .try_into()
-                    .context("invalid WGDEVICE_A_PRIVATE_KEY value")?,
+                WgKey::try_from(payload)
+                    .context("invalid WGDEVICE_A_PRIVATE_KEY value")?
+                    .into(),
             ),
             WGDEVICE_A_PUBLIC_KEY => Self::PublicKey(
-                payload
-                    .try_into()
-                    .context("invalid WGDEVICE_A_PUBLIC_KEY value")?,
+                WgKey::try_from(payload)
+                    .context("invalid WGDEVICE_A_PUBLIC_KEY value")?
+                    .into(),
             ),
             WGDEVICE_A_LISTEN_PORT => Self::ListenPort(
                 parse_u16(payload)
@@ -127,6 +179,12 @@ impl<'a, T: AsRef<[u8]> + ?Sized> Parseable<NlaBuffer<&'a T>>
                     let mut group = Vec::new();
                     for nla in NlasIterator::new(nlas.value()) {
                         let nla = &nla.context(error_msg)?;
+                        // An empty endpoint means the peer has none.
+                        if nla.kind() == WGPEER_A_ENDPOINT
+                            && nla.value().is_empty()
+                        {
+                            continue;
+                        }
                         let parsed =
                             WgPeerAttrs::parse(nla).context(error_msg)?;
                         group.push(parsed);
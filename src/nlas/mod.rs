@@ -2,8 +2,391 @@
 
 mod allowedip;
 mod device;
-mod peer;
+pub(crate) mod peer;
 
 pub use allowedip::WgAllowedIpAttrs;
 pub use device::WgDeviceAttrs;
 pub use peer::{WgAllowedIp, WgPeer, WgPeerAttrs};
+
+use netlink_packet_utils::Emitable;
+
+/// Emit into a freshly allocated buffer.
+///
+/// For an attribute this is the whole NLA, with its header and padding.
+pub trait EmitToVec {
+    fn emit_to_vec(&self) -> Vec<u8>;
+}
+
+impl<T: Emitable + ?Sized> EmitToVec for T {
+    fn emit_to_vec(&self) -> Vec<u8> {
+        let mut buf = vec![0; self.buffer_len()];
+        self.emit(&mut buf);
+        buf
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        fmt::Debug,
+        net::{SocketAddr, SocketAddrV6},
+        time::{Duration, SystemTime},
+    };
+
+    use netlink_packet_utils::{
+        nla::{Nla, NlaBuffer},
+        Emitable, Parseable,
+    };
+
+    use super::*;
+    use crate::constants::*;
+
+    const NLA_HEADER_LEN: usize = 4;
+
+    // The value of the emitted NLA, without header and padding.
+    fn emitted_value<T: Nla>(nla: &T) -> Vec<u8> {
+        let buf = nla.emit_to_vec();
+        buf[NLA_HEADER_LEN..NLA_HEADER_LEN + nla.value_len()].to_vec()
+    }
+
+    fn assert_round_trip<T>(nla: T)
+    where
+        T: Nla + Debug + PartialEq,
+        T: for<'a> Parseable<NlaBuffer<&'a [u8]>>,
+    {
+        let buf = nla.emit_to_vec();
+        let parsed =
+            T::parse(&NlaBuffer::new_checked(buf.as_slice()).unwrap()).unwrap();
+        assert_eq!(parsed, nla);
+    }
+
+    fn allowed_ips() -> Vec<WgAllowedIp> {
+        vec![
+            WgAllowedIp(vec![
+                WgAllowedIpAttrs::Family(AF_INET),
+                WgAllowedIpAttrs::IpAddr([10, 0, 0, 0].into()),
+                WgAllowedIpAttrs::Cidr(24),
+            ]),
+            WgAllowedIp(vec![
+                WgAllowedIpAttrs::Family(AF_INET6),
+                WgAllowedIpAttrs::IpAddr("fd00::".parse().unwrap()),
+                WgAllowedIpAttrs::Cidr(64),
+            ]),
+        ]
+    }
+
+    fn peer_attrs() -> Vec<WgPeerAttrs> {
+        vec![
+            WgPeerAttrs::Unspec(vec![1, 2, 3]),
+            WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN]),
+            WgPeerAttrs::PresharedKey([0x02; WG_KEY_LEN]),
+            WgPeerAttrs::Endpoint("192.168.1.1:51820".parse().unwrap()),
+            WgPeerAttrs::Endpoint(SocketAddr::V6(SocketAddrV6::new(
+                "fe80::1".parse().unwrap(),
+                51820,
+                16,
+                3,
+            ))),
+            WgPeerAttrs::PersistentKeepalive(25),
+            WgPeerAttrs::LastHandshake(
+                SystemTime::UNIX_EPOCH
+                    + Duration::new(1_600_000_000, 123_456_789),
+            ),
+            WgPeerAttrs::RxBytes(1 << 40),
+            WgPeerAttrs::TxBytes(42),
+            WgPeerAttrs::AllowedIps(allowed_ips()),
+            WgPeerAttrs::ProtocolVersion(1),
+            WgPeerAttrs::Flags(WGPEER_F_REPLACE_ALLOWEDIPS),
+        ]
+    }
+
+    #[test]
+    fn test_allowed_ip_attrs_round_trip() {
+        for nla in [
+            WgAllowedIpAttrs::Unspec(vec![]),
+            WgAllowedIpAttrs::Unspec(vec![1, 2, 3, 4, 5]),
+            WgAllowedIpAttrs::Family(AF_INET),
+            WgAllowedIpAttrs::Family(AF_INET6),
+            WgAllowedIpAttrs::IpAddr([10, 0, 0, 1].into()),
+            WgAllowedIpAttrs::IpAddr("fd00::1".parse().unwrap()),
+            WgAllowedIpAttrs::Cidr(0),
+            WgAllowedIpAttrs::Cidr(128),
+        ] {
+            assert_round_trip(nla);
+        }
+    }
+
+    #[test]
+    fn test_emit_to_vec() {
+        let nla = WgAllowedIpAttrs::Cidr(24);
+        let buf = nla.emit_to_vec();
+        assert_eq!(buf, [5, 0, 3, 0, 24, 0, 0, 0]);
+        let parsed =
+            WgAllowedIpAttrs::parse(&NlaBuffer::new_checked(&buf[..]).unwrap());
+        assert_eq!(parsed.unwrap(), nla);
+
+        for nla in peer_attrs() {
+            let buf = nla.emit_to_vec();
+            assert_eq!(buf.len(), nla.buffer_len());
+            let parsed =
+                WgPeerAttrs::parse(&NlaBuffer::new_checked(&buf[..]).unwrap());
+            assert_eq!(parsed.unwrap(), nla);
+        }
+
+        let nla = WgDeviceAttrs::Peers(vec![WgPeer(peer_attrs())]);
+        let buf = nla.emit_to_vec();
+        let parsed =
+            WgDeviceAttrs::parse(&NlaBuffer::new_checked(&buf[..]).unwrap());
+        assert_eq!(parsed.unwrap(), nla);
+    }
+
+    #[test]
+    fn test_link_local_allowed_ip() {
+        // Unlike endpoints, allowed IPs don't carry a scope: a link-local
+        // prefix is emitted as is.
+        let addr: std::net::IpAddr = "fe80::".parse().unwrap();
+        let ip = WgAllowedIp::new(addr, 64).unwrap();
+        assert_eq!(
+            emitted_value(&WgAllowedIpAttrs::IpAddr(addr)),
+            [0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+        assert_round_trip(WgPeerAttrs::AllowedIps(vec![ip.clone()]));
+        assert_eq!(
+            WgAllowedIp::from_interface_addr("fe80::1".parse().unwrap(), 64),
+            Ok(ip)
+        );
+    }
+
+    #[test]
+    fn test_cidr_length() {
+        let nla = WgAllowedIpAttrs::Cidr(24);
+        assert_eq!(nla.value_len(), 1);
+        // 4 bytes of header and 1 byte of value padded to 4.
+        assert_eq!(nla.buffer_len(), 8);
+        let buf = nla.emit_to_vec();
+        assert_eq!(buf, [5, 0, 3, 0, 24, 0, 0, 0]);
+
+        // The group is sized with the padded length of each attribute:
+        // 8 for the family, 8 for the IPv4 address and 8 for the prefix.
+        let ip = WgAllowedIp::new_unchecked([10, 0, 0, 0].into(), 24);
+        assert_eq!(ip.value_len(), 24);
+        assert_eq!(ip.emit_to_vec().len(), 28);
+    }
+
+    #[test]
+    fn test_unspec_round_trip() {
+        let bytes = vec![1, 2, 3, 4, 5];
+        let nla = WgDeviceAttrs::Unspec(bytes.clone());
+        let buf = nla.emit_to_vec();
+        // 4 bytes of header, the 5 bytes and 3 bytes of padding.
+        assert_eq!(buf, [9, 0, 0, 0, 1, 2, 3, 4, 5, 0, 0, 0]);
+        let parsed =
+            WgDeviceAttrs::parse(&NlaBuffer::new_checked(&buf[..]).unwrap())
+                .unwrap();
+        assert_eq!(parsed, WgDeviceAttrs::Unspec(bytes.clone()));
+
+        assert_round_trip(WgPeerAttrs::Unspec(bytes.clone()));
+        assert_round_trip(WgAllowedIpAttrs::Unspec(bytes.clone()));
+        assert_eq!(emitted_value(&WgPeerAttrs::Unspec(bytes.clone())), bytes);
+    }
+
+    #[test]
+    fn test_parse_empty_cidr() {
+        // An empty WGALLOWEDIP_A_CIDR_MASK used to make the parser panic.
+        let buf = [0x04, 0x00, 0x03, 0x00];
+        let nla = NlaBuffer::new_checked(&buf[..]).unwrap();
+        assert!(WgAllowedIpAttrs::parse(&nla).is_err());
+    }
+
+    #[test]
+    fn test_parse_short_key() {
+        let mut buf = vec![35, 0, 0x03, 0x00]; // WGDEVICE_A_PRIVATE_KEY
+        buf.extend([0xaa; WG_KEY_LEN - 1]);
+        buf.push(0); // padding
+        let nla = NlaBuffer::new_checked(&buf[..]).unwrap();
+        let err = WgDeviceAttrs::parse(&nla).unwrap_err();
+        assert!(format!("{:?}", err)
+            .contains("invalid key length: 31 bytes (expected 32)"));
+    }
+
+    #[test]
+    fn test_parse_short_peer_key() {
+        let mut buf = vec![
+            44, 0, 0x08, 0x80, // WGDEVICE_A_PEERS
+            40, 0, 0x00, 0x80, // peer
+            36, 0, 0x02, 0x00, // WGPEER_A_PRESHARED_KEY
+        ];
+        buf.extend([0x01; WG_KEY_LEN]);
+        // Shorten the key by one byte, keeping the padding.
+        buf[0] -= 1;
+        buf[4] -= 1;
+        buf[8] -= 1;
+        let nla = NlaBuffer::new_checked(&buf[..]).unwrap();
+        let err = WgDeviceAttrs::parse(&nla).unwrap_err();
+        assert!(format!("{:?}", err).contains("invalid WGPEER_A_PRESHARED_KEY"));
+    }
+
+    #[test]
+    fn test_parse_allowed_ip_reverse_order() {
+        let reversed = WgPeerAttrs::AllowedIps(vec![WgAllowedIp(vec![
+            WgAllowedIpAttrs::Cidr(24),
+            WgAllowedIpAttrs::IpAddr("fd00::".parse().unwrap()),
+            WgAllowedIpAttrs::Family(AF_INET6),
+        ])]);
+        let buf = reversed.emit_to_vec();
+        let parsed = WgPeerAttrs::parse(
+            &NlaBuffer::new_checked(buf.as_slice()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(parsed, reversed);
+
+        let peer = WgPeer(vec![parsed]);
+        assert_eq!(
+            peer.allowed_ip_groups()[0].addr_and_cidr(),
+            Some(("fd00::".parse().unwrap(), 24))
+        );
+        assert_eq!(peer.allowed_ip_strings(), ["fd00::/24"]);
+    }
+
+    #[test]
+    fn test_parse_empty_endpoint() {
+        let mut buf = vec![
+            48, 0, 0x08, 0x80, // WGDEVICE_A_PEERS
+            44, 0, 0x00, 0x80, // peer
+            36, 0, 0x01, 0x00, // WGPEER_A_PUBLIC_KEY
+        ];
+        buf.extend([0x01; WG_KEY_LEN]);
+        buf.extend([4, 0, 0x04, 0x00]); // empty WGPEER_A_ENDPOINT
+        let nla = NlaBuffer::new_checked(&buf[..]).unwrap();
+        assert_eq!(
+            WgDeviceAttrs::parse(&nla).unwrap(),
+            WgDeviceAttrs::Peers(vec![WgPeer(vec![WgPeerAttrs::PublicKey(
+                [0x01; WG_KEY_LEN]
+            )])])
+        );
+    }
+
+    #[test]
+    fn test_peer_attrs_round_trip() {
+        for nla in peer_attrs() {
+            assert_round_trip(nla);
+        }
+    }
+
+    #[test]
+    fn test_device_attrs_round_trip() {
+        for nla in [
+            WgDeviceAttrs::Unspec(vec![1, 2, 3]),
+            WgDeviceAttrs::IfIndex(3),
+            WgDeviceAttrs::IfName("wg0".to_string()),
+            WgDeviceAttrs::PrivateKey([0xaa; WG_KEY_LEN]),
+            WgDeviceAttrs::PublicKey([0xbb; WG_KEY_LEN]),
+            WgDeviceAttrs::ListenPort(51820),
+            WgDeviceAttrs::Fwmark(0x1234),
+            WgDeviceAttrs::Flags(WGDEVICE_F_REPLACE_PEERS),
+            WgDeviceAttrs::Peers(vec![]),
+            WgDeviceAttrs::Peers(vec![
+                WgPeer(peer_attrs()),
+                WgPeer(vec![WgPeerAttrs::PublicKey([0x03; WG_KEY_LEN])]),
+            ]),
+        ] {
+            assert_round_trip(nla);
+        }
+    }
+
+    #[test]
+    fn test_boundary_values() {
+        for nla in [
+            WgDeviceAttrs::ListenPort(0),
+            WgDeviceAttrs::ListenPort(u16::MAX),
+            WgDeviceAttrs::Fwmark(0),
+            WgDeviceAttrs::Fwmark(u32::MAX),
+            WgDeviceAttrs::PrivateKey([0xff; WG_KEY_LEN]),
+            WgDeviceAttrs::PublicKey([0xff; WG_KEY_LEN]),
+        ] {
+            assert_round_trip(nla);
+        }
+        let peer = WgPeer(vec![
+            WgPeerAttrs::PublicKey([0xff; WG_KEY_LEN]),
+            WgPeerAttrs::PresharedKey([0xff; WG_KEY_LEN]),
+            WgPeerAttrs::PersistentKeepalive(u16::MAX),
+            WgPeerAttrs::RxBytes(u64::MAX),
+            WgPeerAttrs::TxBytes(u64::MAX),
+        ]);
+        for nla in peer.iter() {
+            assert_round_trip(nla.clone());
+        }
+        assert_eq!(peer.total_bytes(), Some(u64::MAX));
+
+        let wg = crate::Wireguard {
+            cmd: crate::WireguardCmd::GetDevice,
+            nlas: vec![
+                WgDeviceAttrs::ListenPort(u16::MAX),
+                WgDeviceAttrs::Fwmark(u32::MAX),
+                WgDeviceAttrs::Peers(vec![peer]),
+            ],
+        };
+        assert!(wg.debug_dump().contains("ff ff ff ff ff ff ff ff"));
+        let device = crate::Device::from(&wg);
+        assert_eq!(device.fwmark, Some(u32::MAX));
+        assert_eq!(device.peers[0].rx_bytes, Some(u64::MAX));
+    }
+
+    #[test]
+    fn test_device_scalars_are_native_endian() {
+        let value = emitted_value(&WgDeviceAttrs::IfIndex(0x01020304));
+        assert_eq!(value, 0x01020304u32.to_ne_bytes());
+        let value = emitted_value(&WgDeviceAttrs::ListenPort(0x0102));
+        assert_eq!(value, 0x0102u16.to_ne_bytes());
+        let value = emitted_value(&WgDeviceAttrs::Fwmark(0x01020304));
+        assert_eq!(value, 0x01020304u32.to_ne_bytes());
+        let value = emitted_value(&WgDeviceAttrs::Flags(0x01020304));
+        assert_eq!(value, 0x01020304u32.to_ne_bytes());
+    }
+
+    #[test]
+    fn test_peer_scalars_are_native_endian() {
+        let value = emitted_value(&WgPeerAttrs::PersistentKeepalive(0x0102));
+        assert_eq!(value, 0x0102u16.to_ne_bytes());
+        let value = emitted_value(&WgPeerAttrs::RxBytes(0x0102030405060708));
+        assert_eq!(value, 0x0102030405060708u64.to_ne_bytes());
+        let value = emitted_value(&WgPeerAttrs::TxBytes(0x0102030405060708));
+        assert_eq!(value, 0x0102030405060708u64.to_ne_bytes());
+        let value = emitted_value(&WgPeerAttrs::ProtocolVersion(0x01020304));
+        assert_eq!(value, 0x01020304u32.to_ne_bytes());
+        let value = emitted_value(&WgPeerAttrs::Flags(0x01020304));
+        assert_eq!(value, 0x01020304u32.to_ne_bytes());
+
+        let time =
+            SystemTime::UNIX_EPOCH + Duration::new(0x01020304, 0x05060708);
+        let value = emitted_value(&WgPeerAttrs::LastHandshake(time));
+        assert_eq!(value[..8], 0x01020304i64.to_ne_bytes());
+        assert_eq!(value[8..], 0x05060708i64.to_ne_bytes());
+    }
+
+    #[test]
+    fn test_allowed_ip_scalars_are_native_endian() {
+        let value = emitted_value(&WgAllowedIpAttrs::Family(AF_INET6));
+        assert_eq!(value, AF_INET6.to_ne_bytes());
+    }
+
+    #[test]
+    fn test_endpoint_port_is_network_endian() {
+        let nla = WgPeerAttrs::Endpoint("1.2.3.4:51820".parse().unwrap());
+        let value = emitted_value(&nla);
+        assert_eq!(value[..2], AF_INET.to_ne_bytes());
+        assert_eq!(value[2..4], 51820u16.to_be_bytes());
+        assert_eq!(value[4..8], [1, 2, 3, 4]);
+
+        let nla = WgPeerAttrs::Endpoint(SocketAddr::V6(SocketAddrV6::new(
+            "fe80::1".parse().unwrap(),
+            51820,
+            0,
+            0x01020304,
+        )));
+        let value = emitted_value(&nla);
+        assert_eq!(value[..2], AF_INET6.to_ne_bytes());
+        assert_eq!(value[2..4], 51820u16.to_be_bytes());
+        assert_eq!(value[24..28], 0x01020304u32.to_ne_bytes());
+    }
+}
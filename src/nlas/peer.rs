@@ -7,6 +7,7 @@ use crate::{
         emit_socket_addr, emit_timespec, parse_socket_addr, parse_timespec,
         SOCKET_ADDR_V4_LEN, SOCKET_ADDR_V6_LEN, TIMESPEC_LEN,
     },
+    WgError, WgKey,
 };
 use anyhow::Context;
 use byteorder::{ByteOrder, NativeEndian};
@@ -17,11 +18,19 @@ use netlink_packet_utils::{
     DecodeError,
 };
 use std::{
-    convert::TryInto, mem::size_of_val, net::SocketAddr, ops::Deref,
+    collections::BTreeSet,
+    convert::TryFrom,
+    fmt,
+    mem::size_of_val,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    ops::Deref,
+    str::FromStr,
     time::SystemTime,
 };
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct WgPeer(pub Vec<WgPeerAttrs>);
 
 impl Nla for WgPeer {
@@ -42,6 +51,276 @@ impl Nla for WgPeer {
     }
 }
 
+impl WgPeer {
+    /// The number of bytes the peer takes in a `WGDEVICE_A_PEERS`
+    /// attribute, header and padding included, e.g. to split peers into
+    /// messages of a bounded size.
+    pub fn buffer_len(&self) -> usize {
+        Emitable::buffer_len(self)
+    }
+
+    /// The number of allowed IPs of the peer, across all its
+    /// `WGPEER_A_ALLOWEDIPS` attributes.
+    pub fn allowed_ips_len(&self) -> usize {
+        self.0
+            .iter()
+            .map(|nla| match nla {
+                WgPeerAttrs::AllowedIps(ips) => ips.len(),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    pub fn public_key(&self) -> Option<&[u8; WG_KEY_LEN]> {
+        self.0.iter().find_map(|nla| match nla {
+            WgPeerAttrs::PublicKey(key) => Some(key),
+            _ => None,
+        })
+    }
+
+    /// The preshared key, borrowed from its attribute rather than copied
+    /// so that the secret isn't duplicated in memory.
+    pub fn preshared_key_bytes(&self) -> Option<&[u8; WG_KEY_LEN]> {
+        self.0.iter().find_map(|nla| match nla {
+            WgPeerAttrs::PresharedKey(key) => Some(key),
+            _ => None,
+        })
+    }
+
+    pub fn endpoint(&self) -> Option<&SocketAddr> {
+        self.0.iter().find_map(|nla| match nla {
+            WgPeerAttrs::Endpoint(endpoint) => Some(endpoint),
+            _ => None,
+        })
+    }
+
+    pub fn endpoint_ip(&self) -> Option<IpAddr> {
+        self.endpoint().map(SocketAddr::ip)
+    }
+
+    pub fn endpoint_port(&self) -> Option<u16> {
+        self.endpoint().map(SocketAddr::port)
+    }
+
+    /// The `WGPEER_F_*` flags, or `None` when the peer has no
+    /// `WGPEER_A_FLAGS` attribute, which is not the same as flags
+    /// explicitly set to 0 when diffing messages.
+    pub fn raw_flags(&self) -> Option<u32> {
+        self.0.iter().find_map(|nla| match nla {
+            WgPeerAttrs::Flags(flags) => Some(*flags),
+            _ => None,
+        })
+    }
+
+    /// The bytes received and sent to the peer, or `None` unless both
+    /// `WGPEER_A_RX_BYTES` and `WGPEER_A_TX_BYTES` are present. The sum
+    /// saturates at `u64::MAX`.
+    pub fn total_bytes(&self) -> Option<u64> {
+        let rx = self.0.iter().find_map(|nla| match nla {
+            WgPeerAttrs::RxBytes(bytes) => Some(*bytes),
+            _ => None,
+        })?;
+        let tx = self.0.iter().find_map(|nla| match nla {
+            WgPeerAttrs::TxBytes(bytes) => Some(*bytes),
+            _ => None,
+        })?;
+        Some(rx.saturating_add(tx))
+    }
+
+    /// The time elapsed since the last handshake as `wg show` displays it,
+    /// e.g. `2 minutes, 3 seconds ago`, or `None` if the peer has no
+    /// `WGPEER_A_LAST_HANDSHAKE_TIME` or never completed a handshake (the
+    /// kernel then reports the epoch). A handshake after `now` is shown as
+    /// `Now`.
+    pub fn last_handshake_relative(&self, now: SystemTime) -> Option<String> {
+        const UNITS: [(&str, u64); 5] = [
+            ("year", 365 * 24 * 60 * 60),
+            ("day", 24 * 60 * 60),
+            ("hour", 60 * 60),
+            ("minute", 60),
+            ("second", 1),
+        ];
+        let handshake = self.0.iter().find_map(|nla| match nla {
+            WgPeerAttrs::LastHandshake(time) => Some(*time),
+            _ => None,
+        })?;
+        if handshake == SystemTime::UNIX_EPOCH {
+            return None;
+        }
+        let mut left = now
+            .duration_since(handshake)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+        if left == 0 {
+            return Some("Now".to_string());
+        }
+        let mut parts = Vec::new();
+        for (unit, secs) in UNITS {
+            let count = left / secs;
+            left %= secs;
+            if count > 0 {
+                let plural = if count == 1 { "" } else { "s" };
+                parts.push(format!("{} {}{}", count, unit, plural));
+            }
+        }
+        Some(format!("{} ago", parts.join(", ")))
+    }
+
+    /// The endpoint as `wg show` displays it: `ip:port`, with the IPv6
+    /// addresses in brackets (`[fd00::1]:51820`).
+    pub fn endpoint_string(&self) -> Option<String> {
+        self.endpoint().map(|endpoint| endpoint.to_string())
+    }
+
+    /// The allowed IPs of the first `WGPEER_A_ALLOWEDIPS` attribute, or an
+    /// empty slice if the peer has none. The kernel emits a single one per
+    /// peer; see [`WgPeer::allowed_ip_strings`] to read all of them.
+    pub fn allowed_ip_groups(&self) -> &[WgAllowedIp] {
+        self.0
+            .iter()
+            .find_map(|nla| match nla {
+                WgPeerAttrs::AllowedIps(ips) => Some(ips.as_slice()),
+                _ => None,
+            })
+            .unwrap_or(&[])
+    }
+
+    /// The allowed IPs as `addr/cidr` strings, in the order of the
+    /// message, like the `AllowedIPs` line of `wg showconf`. Allowed IPs
+    /// without an address or a prefix length are skipped.
+    pub fn allowed_ip_strings(&self) -> Vec<String> {
+        self.0
+            .iter()
+            .filter_map(|nla| match nla {
+                WgPeerAttrs::AllowedIps(ips) => Some(ips),
+                _ => None,
+            })
+            .flatten()
+            .filter(|ip| ip.addr_and_cidr().is_some())
+            .map(|ip| ip.to_string())
+            .collect()
+    }
+
+    /// The peer without its runtime statistics (`LastHandshake`,
+    /// `RxBytes` and `TxBytes`), which the kernel reports in dumps and
+    /// ignores in `SetDevice` messages.
+    pub fn to_config(&self) -> WgPeer {
+        WgPeer(
+            self.0
+                .iter()
+                .filter(|nla| {
+                    !matches!(
+                        nla,
+                        WgPeerAttrs::LastHandshake(_)
+                            | WgPeerAttrs::RxBytes(_)
+                            | WgPeerAttrs::TxBytes(_)
+                    )
+                })
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// Whether both peers have the same allowed IPs, regardless of their
+    /// order or of repetitions.
+    ///
+    /// Like the kernel, which stores `10.0.0.1/24` as `10.0.0.0/24`, the
+    /// addresses are compared with their host bits cleared. Allowed IPs
+    /// without an address or a prefix length are ignored.
+    pub fn allowed_ips_equal(&self, other: &WgPeer) -> bool {
+        fn networks(peer: &WgPeer) -> BTreeSet<(IpAddr, u8)> {
+            peer.0
+                .iter()
+                .filter_map(|nla| match nla {
+                    WgPeerAttrs::AllowedIps(ips) => Some(ips),
+                    _ => None,
+                })
+                .flatten()
+                .filter_map(|ip| ip.addr_and_cidr())
+                .map(|(addr, cidr)| (mask(addr, cidr), cidr))
+                .collect()
+        }
+        networks(self) == networks(other)
+    }
+
+    /// A peer that appends `allowed_ips` to the allowed IPs of the
+    /// existing peer `public_key`.
+    ///
+    /// `WGPEER_F_REPLACE_ALLOWEDIPS` is not set, so the allowed IPs the
+    /// peer already has are kept.
+    pub fn add_allowed_ips<K, I>(public_key: K, allowed_ips: I) -> Self
+    where
+        K: Into<WgKey>,
+        I: IntoIterator<Item = WgAllowedIp>,
+    {
+        Self(vec![
+            WgPeerAttrs::PublicKey(public_key.into().into()),
+            WgPeerAttrs::AllowedIps(allowed_ips.into_iter().collect()),
+        ])
+    }
+
+    /// A peer replacing the preshared key of the existing peer
+    /// `public_key` with `new_psk`.
+    ///
+    /// `WGPEER_F_UPDATE_ONLY` is set, so the kernel doesn't create the
+    /// peer if it was removed in the meantime.
+    pub fn rotate_psk<K, P>(public_key: K, new_psk: P) -> Self
+    where
+        K: Into<WgKey>,
+        P: Into<WgKey>,
+    {
+        Self(vec![
+            WgPeerAttrs::PublicKey(public_key.into().into()),
+            WgPeerAttrs::Flags(WGPEER_F_UPDATE_ONLY),
+            WgPeerAttrs::PresharedKey(new_psk.into().into()),
+        ])
+    }
+
+    /// A peer removing the preshared key of the existing peer
+    /// `public_key`, with an all-zero `WGPEER_A_PRESHARED_KEY`.
+    ///
+    /// Leaving the attribute out keeps the preshared key unchanged: only
+    /// zeros clear it. `WGPEER_F_UPDATE_ONLY` is set, like in
+    /// [`WgPeer::rotate_psk`].
+    pub fn clear_psk<K: Into<WgKey>>(public_key: K) -> Self {
+        Self::rotate_psk(public_key, [0; WG_KEY_LEN])
+    }
+
+    /// A peer only setting the persistent keepalive interval of the peer
+    /// `public_key` to `secs` seconds, `0` disabling it.
+    ///
+    /// No flag is set: if the peer doesn't exist, the kernel creates it
+    /// with no endpoint and no allowed IPs.
+    pub fn keepalive_update<K: Into<WgKey>>(public_key: K, secs: u16) -> Self {
+        Self(vec![
+            WgPeerAttrs::PublicKey(public_key.into().into()),
+            WgPeerAttrs::PersistentKeepalive(secs),
+        ])
+    }
+}
+
+/// A one-line summary of the peer for logs:
+/// `<public key> endpoint=<ip:port> allowed=<count>`, followed by
+/// `psk=(hidden)` when the peer has a preshared key. The preshared key
+/// itself is never printed.
+impl fmt::Display for WgPeer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.public_key() {
+            Some(key) => write!(f, "{}", WgKey::from(key).to_base64())?,
+            None => write!(f, "<no public key>")?,
+        }
+        match self.endpoint() {
+            Some(endpoint) => write!(f, " endpoint={}", endpoint)?,
+            None => write!(f, " endpoint=(none)")?,
+        }
+        write!(f, " allowed={}", self.allowed_ips_len())?;
+        if self.preshared_key_bytes().is_some() {
+            write!(f, " psk=(hidden)")?;
+        }
+        Ok(())
+    }
+}
+
 impl Deref for WgPeer {
     type Target = Vec<WgPeerAttrs>;
 
@@ -51,8 +330,204 @@ impl Deref for WgPeer {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct WgAllowedIp(pub Vec<WgAllowedIpAttrs>);
 
+impl WgAllowedIp {
+    /// The number of bytes the allowed IP takes in a
+    /// `WGPEER_A_ALLOWEDIPS` attribute, header and padding included: 28
+    /// bytes for an IPv4 address and 40 for an IPv6 one.
+    pub fn buffer_len(&self) -> usize {
+        Emitable::buffer_len(self)
+    }
+
+    /// Parse the value of an `AllowedIPs = ...` line of a `wg`
+    /// configuration: allowed IPs as accepted by [`FromStr`], separated by
+    /// commas. Whitespace around the entries and empty entries, e.g. after
+    /// a trailing comma, are ignored.
+    pub fn parse_conf_line(value: &str) -> Result<Vec<Self>, WgError> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|allowed_ip| !allowed_ip.is_empty())
+            .map(str::parse)
+            .collect()
+    }
+
+    /// Build the `Family`, `IpAddr` and `Cidr` attributes of an allowed
+    /// IP, checking that the prefix length fits the address.
+    pub fn new(addr: IpAddr, cidr: u8) -> Result<Self, WgError> {
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if cidr > max {
+            return Err(WgError::InvalidCidr { addr, cidr });
+        }
+        Ok(Self::new_unchecked(addr, cidr))
+    }
+
+    /// Build a list of allowed IPs with [`WgAllowedIp::new`], stopping at
+    /// the first invalid prefix length.
+    pub fn new_many<I>(allowed_ips: I) -> Result<Vec<Self>, WgError>
+    where
+        I: IntoIterator<Item = (IpAddr, u8)>,
+    {
+        allowed_ips
+            .into_iter()
+            .map(|(addr, cidr)| Self::new(addr, cidr))
+            .collect()
+    }
+
+    /// Host routes (`/32` for IPv4, `/128` for IPv6) allowing only the
+    /// given addresses.
+    pub fn hosts<I: IntoIterator<Item = IpAddr>>(addrs: I) -> Vec<Self> {
+        addrs
+            .into_iter()
+            .map(|addr| {
+                let cidr = match addr {
+                    IpAddr::V4(_) => 32,
+                    IpAddr::V6(_) => 128,
+                };
+                Self::new_unchecked(addr, cidr)
+            })
+            .collect()
+    }
+
+    /// The allowed IP covering the whole subnet of an interface address,
+    /// e.g. `10.0.0.0/24` for the interface address `10.0.0.1/24`.
+    ///
+    /// Unlike [`WgAllowedIp::new`], the host bits of `addr` are cleared:
+    /// the result routes the subnet through the peer, not just the
+    /// interface address. Use a `/32` or `/128` prefix with
+    /// [`WgAllowedIp::new`] for a host route.
+    pub fn from_interface_addr(
+        addr: IpAddr,
+        prefix: u8,
+    ) -> Result<Self, WgError> {
+        Self::new(addr, prefix)?;
+        Ok(Self::new_unchecked(mask(addr, prefix), prefix))
+    }
+
+    /// Same as [`WgAllowedIp::new`] without checking the prefix length.
+    ///
+    /// This is only meant for input that was already validated: the
+    /// kernel rejects the whole `SetDevice` message if the prefix is
+    /// longer than the address.
+    pub fn new_unchecked(addr: IpAddr, cidr: u8) -> Self {
+        let family = match addr {
+            IpAddr::V4(_) => AF_INET,
+            IpAddr::V6(_) => AF_INET6,
+        };
+        Self(vec![
+            WgAllowedIpAttrs::Family(family),
+            WgAllowedIpAttrs::IpAddr(addr),
+            WgAllowedIpAttrs::Cidr(cidr),
+        ])
+    }
+
+    /// Same as [`WgAllowedIp::new_unchecked`] with the given
+    /// `WGALLOWEDIP_A_FAMILY` instead of the family of `addr`.
+    ///
+    /// This is an escape hatch to test how the kernel handles
+    /// inconsistent attributes: it rejects a family that doesn't match the
+    /// length of the address, so regular code should never need it.
+    pub fn with_family(addr: IpAddr, cidr: u8, family: u16) -> Self {
+        Self(vec![
+            WgAllowedIpAttrs::Family(family),
+            WgAllowedIpAttrs::IpAddr(addr),
+            WgAllowedIpAttrs::Cidr(cidr),
+        ])
+    }
+
+    pub(crate) fn addr_and_cidr(&self) -> Option<(IpAddr, u8)> {
+        let addr = self.0.iter().find_map(|nla| match nla {
+            WgAllowedIpAttrs::IpAddr(addr) => Some(*addr),
+            _ => None,
+        })?;
+        let cidr = self.0.iter().find_map(|nla| match nla {
+            WgAllowedIpAttrs::Cidr(cidr) => Some(*cidr),
+            _ => None,
+        })?;
+        Some((addr, cidr))
+    }
+}
+
+/// Format the allowed IP as a CIDR, `10.0.0.0/24` or `fd00::/64`, or
+/// `<invalid>` when the address or the prefix length is missing.
+impl fmt::Display for WgAllowedIp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.addr_and_cidr() {
+            Some((addr, cidr)) => write!(f, "{}/{}", addr, cidr),
+            None => write!(f, "<invalid>"),
+        }
+    }
+}
+
+// Clear the host bits of `addr`.
+pub(crate) fn mask(addr: IpAddr, prefix: u8) -> IpAddr {
+    match addr {
+        IpAddr::V4(ip) => {
+            let mask = u32::MAX
+                .checked_shl(32u32.saturating_sub(prefix.into()))
+                .unwrap_or(0);
+            IpAddr::V4((u32::from(ip) & mask).into())
+        }
+        IpAddr::V6(ip) => {
+            let mask = u128::MAX
+                .checked_shl(128u32.saturating_sub(prefix.into()))
+                .unwrap_or(0);
+            IpAddr::V6((u128::from(ip) & mask).into())
+        }
+    }
+}
+
+/// Parse an allowed IP as written in `wg` configurations: `addr/cidr`, or
+/// a bare address for a host route. `default` and `default6` are accepted
+/// for the default routes `0.0.0.0/0` and `::/0`, like in `ip route`.
+impl FromStr for WgAllowedIp {
+    type Err = WgError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => return Self::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+            "default6" => return Self::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+            _ => (),
+        }
+        let invalid = || WgError::InvalidAllowedIp(s.to_string());
+        let (addr, cidr) = match s.split_once('/') {
+            Some((addr, cidr)) => (addr, Some(cidr)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+        let cidr = match (cidr, addr) {
+            (Some(cidr), _) => cidr.parse().map_err(|_| invalid())?,
+            (None, IpAddr::V4(_)) => 32,
+            (None, IpAddr::V6(_)) => 128,
+        };
+        Self::new(addr, cidr)
+    }
+}
+
+/// Same as [`WgAllowedIp::new`]: the prefix length must be at most 32.
+impl TryFrom<(Ipv4Addr, u8)> for WgAllowedIp {
+    type Error = WgError;
+
+    fn try_from((addr, cidr): (Ipv4Addr, u8)) -> Result<Self, Self::Error> {
+        Self::new(addr.into(), cidr)
+    }
+}
+
+/// Same as [`WgAllowedIp::new`]: the prefix length must be at most 128.
+impl TryFrom<(Ipv6Addr, u8)> for WgAllowedIp {
+    type Error = WgError;
+
+    fn try_from((addr, cidr): (Ipv6Addr, u8)) -> Result<Self, Self::Error> {
+        Self::new(addr.into(), cidr)
+    }
+}
+
 impl Nla for WgAllowedIp {
     fn value_len(&self) -> usize {
         self.0.as_slice().buffer_len()
@@ -79,11 +554,27 @@ impl Deref for WgAllowedIp {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub enum WgPeerAttrs {
     Unspec(Vec<u8>),
-    PublicKey([u8; WG_KEY_LEN]),
-    PresharedKey([u8; WG_KEY_LEN]),
+    PublicKey(
+        #[cfg_attr(
+            feature = "serde",
+            serde(with = "crate::serde_helpers::base64_key")
+        )]
+        #[cfg_attr(feature = "schemars", schemars(with = "String"))]
+        [u8; WG_KEY_LEN],
+    ),
+    PresharedKey(
+        #[cfg_attr(
+            feature = "serde",
+            serde(with = "crate::serde_helpers::base64_key")
+        )]
+        #[cfg_attr(feature = "schemars", schemars(with = "String"))]
+        [u8; WG_KEY_LEN],
+    ),
     Endpoint(SocketAddr),
     PersistentKeepalive(u16),
     LastHandshake(SystemTime),
@@ -94,6 +585,45 @@ pub enum WgPeerAttrs {
     Flags(u32),
 }
 
+/// Like a derived `Debug`, except that the preshared key is shown as
+/// `PresharedKey((hidden))` so that logging a message doesn't leak it.
+impl fmt::Debug for WgPeerAttrs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WgPeerAttrs::Unspec(v) => f.debug_tuple("Unspec").field(v).finish(),
+            WgPeerAttrs::PublicKey(v) => {
+                f.debug_tuple("PublicKey").field(v).finish()
+            }
+            WgPeerAttrs::PresharedKey(_) => f
+                .debug_tuple("PresharedKey")
+                .field(&format_args!("(hidden)"))
+                .finish(),
+            WgPeerAttrs::Endpoint(v) => {
+                f.debug_tuple("Endpoint").field(v).finish()
+            }
+            WgPeerAttrs::PersistentKeepalive(v) => {
+                f.debug_tuple("PersistentKeepalive").field(v).finish()
+            }
+            WgPeerAttrs::LastHandshake(v) => {
+                f.debug_tuple("LastHandshake").field(v).finish()
+            }
+            WgPeerAttrs::RxBytes(v) => {
+                f.debug_tuple("RxBytes").field(v).finish()
+            }
+            WgPeerAttrs::TxBytes(v) => {
+                f.debug_tuple("TxBytes").field(v).finish()
+            }
+            WgPeerAttrs::AllowedIps(v) => {
+                f.debug_tuple("AllowedIps").field(v).finish()
+            }
+            WgPeerAttrs::ProtocolVersion(v) => {
+                f.debug_tuple("ProtocolVersion").field(v).finish()
+            }
+            WgPeerAttrs::Flags(v) => f.debug_tuple("Flags").field(v).finish(),
+        }
+    }
+}
+
 impl Nla for WgPeerAttrs {
     fn value_len(&self) -> usize {
         match self {
@@ -136,7 +666,9 @@ impl Nla for WgPeerAttrs {
 
     fn emit_value(&self, buffer: &mut [u8]) {
         match self {
-            WgPeerAttrs::Unspec(bytes) => buffer.copy_from_slice(bytes),
+            WgPeerAttrs::Unspec(bytes) => {
+                buffer[..bytes.len()].copy_from_slice(bytes)
+            }
             WgPeerAttrs::PublicKey(v) => buffer.copy_from_slice(v),
             WgPeerAttrs::PresharedKey(v) => buffer.copy_from_slice(v),
             WgPeerAttrs::Endpoint(v) => emit_socket_addr(v, buffer),
@@ -171,12 +703,14 @@ impl<'a, T: AsRef<[u8]> + ?Sized> Parseable<NlaBuffer<&'a T>> for WgPeerAttrs {
         Ok(match buf.kind() {
             WGPEER_A_UNSPEC => Self::Unspec(payload.to_vec()),
             WGPEER_A_PUBLIC_KEY => Self::PublicKey(
-                payload.try_into().context("invalid WGPEER_A_PUBLIC_KEY")?,
+                WgKey::try_from(payload)
+                    .context("invalid WGPEER_A_PUBLIC_KEY")?
+                    .into(),
             ),
             WGPEER_A_PRESHARED_KEY => Self::PresharedKey(
-                payload
-                    .try_into()
-                    .context("invalid WGPEER_A_PRESHARED_KEY")?,
+                WgKey::try_from(payload)
+                    .context("invalid WGPEER_A_PRESHARED_KEY")?
+                    .into(),
             ),
             WGPEER_A_ENDPOINT => Self::Endpoint(
                 parse_socket_addr(payload)
@@ -231,3 +765,531 @@ impl<'a, T: AsRef<[u8]> + ?Sized> Parseable<NlaBuffer<&'a T>> for WgPeerAttrs {
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_endpoint_string() {
+        let peer = WgPeer(vec![WgPeerAttrs::Endpoint(
+            "192.168.1.1:51820".parse().unwrap(),
+        )]);
+        assert_eq!(peer.endpoint_string().unwrap(), "192.168.1.1:51820");
+        let peer = WgPeer(vec![WgPeerAttrs::Endpoint(
+            "[fd00::1]:51820".parse().unwrap(),
+        )]);
+        assert_eq!(peer.endpoint_string().unwrap(), "[fd00::1]:51820");
+        assert_eq!(WgPeer(vec![]).endpoint_string(), None);
+    }
+
+    #[test]
+    fn test_allowed_ip_groups() {
+        let ips: Vec<WgAllowedIp> =
+            vec!["10.0.0.0/24".parse().unwrap(), "fd00::/64".parse().unwrap()];
+        let peer = WgPeer(vec![
+            WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN]),
+            WgPeerAttrs::AllowedIps(ips.clone()),
+        ]);
+        assert_eq!(peer.allowed_ip_groups(), ips.as_slice());
+        assert!(WgPeer(vec![]).allowed_ip_groups().is_empty());
+    }
+
+    #[test]
+    fn test_allowed_ip_strings() {
+        let peer = WgPeer(vec![
+            WgPeerAttrs::AllowedIps(vec![
+                "fd00::2".parse().unwrap(),
+                "10.0.0.0/24".parse().unwrap(),
+            ]),
+            WgPeerAttrs::AllowedIps(vec![
+                WgAllowedIp(vec![WgAllowedIpAttrs::Cidr(8)]),
+                "192.168.1.1".parse().unwrap(),
+            ]),
+        ]);
+        assert_eq!(
+            peer.allowed_ip_strings(),
+            ["fd00::2/128", "10.0.0.0/24", "192.168.1.1/32"]
+        );
+        assert!(WgPeer(vec![]).allowed_ip_strings().is_empty());
+    }
+
+    #[test]
+    fn test_last_handshake_relative() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let peer =
+            |handshake| WgPeer(vec![WgPeerAttrs::LastHandshake(handshake)]);
+        assert_eq!(
+            peer(now - Duration::from_secs(123))
+                .last_handshake_relative(now)
+                .unwrap(),
+            "2 minutes, 3 seconds ago"
+        );
+        assert_eq!(
+            peer(now - Duration::from_secs(400 * 24 * 60 * 60 + 3600 + 1))
+                .last_handshake_relative(now)
+                .unwrap(),
+            "1 year, 35 days, 1 hour, 1 second ago"
+        );
+        assert_eq!(
+            peer(now + Duration::from_secs(5))
+                .last_handshake_relative(now)
+                .unwrap(),
+            "Now"
+        );
+        assert_eq!(
+            peer(SystemTime::UNIX_EPOCH).last_handshake_relative(now),
+            None
+        );
+        assert_eq!(WgPeer(vec![]).last_handshake_relative(now), None);
+    }
+
+    #[test]
+    fn test_peer_display() {
+        let peer = WgPeer(vec![
+            WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN]),
+            WgPeerAttrs::PresharedKey([0x02; WG_KEY_LEN]),
+            WgPeerAttrs::Endpoint("[fd00::1]:51820".parse().unwrap()),
+            WgPeerAttrs::AllowedIps(vec![
+                "10.0.0.0/24".parse().unwrap(),
+                "fd00::/64".parse().unwrap(),
+            ]),
+        ]);
+        assert_eq!(
+            peer.to_string(),
+            "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE= \
+             endpoint=[fd00::1]:51820 allowed=2 psk=(hidden)"
+        );
+        assert!(!peer
+            .to_string()
+            .contains(&WgKey::new([0x02; WG_KEY_LEN]).to_base64()));
+
+        let peer = WgPeer(vec![WgPeerAttrs::PersistentKeepalive(25)]);
+        assert_eq!(
+            peer.to_string(),
+            "<no public key> endpoint=(none) allowed=0"
+        );
+    }
+
+    #[test]
+    fn test_endpoint_ip_port() {
+        let peer = WgPeer(vec![WgPeerAttrs::Endpoint(
+            "[fd00::1%3]:51820".parse().unwrap(),
+        )]);
+        assert_eq!(peer.endpoint_ip(), Some("fd00::1".parse().unwrap()));
+        assert_eq!(peer.endpoint_port(), Some(51820));
+        let peer = WgPeer(vec![]);
+        assert_eq!(peer.endpoint_ip(), None);
+        assert_eq!(peer.endpoint_port(), None);
+    }
+
+    #[test]
+    fn test_key_accessors_borrow() {
+        let peer = WgPeer(vec![
+            WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN]),
+            WgPeerAttrs::PresharedKey([0x02; WG_KEY_LEN]),
+        ]);
+        match (&peer.0[0], &peer.0[1]) {
+            (
+                WgPeerAttrs::PublicKey(public),
+                WgPeerAttrs::PresharedKey(psk),
+            ) => {
+                assert!(std::ptr::eq(peer.public_key().unwrap(), public));
+                assert!(std::ptr::eq(peer.preshared_key_bytes().unwrap(), psk));
+            }
+            _ => unreachable!(),
+        }
+        assert_eq!(WgPeer(vec![]).preshared_key_bytes(), None);
+    }
+
+    #[test]
+    fn test_raw_flags() {
+        assert_eq!(WgPeer(vec![]).raw_flags(), None);
+        assert_eq!(WgPeer(vec![WgPeerAttrs::Flags(0)]).raw_flags(), Some(0));
+        let peer = WgPeer(vec![
+            WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN]),
+            WgPeerAttrs::Flags(WGPEER_F_REMOVE_ME),
+        ]);
+        assert_eq!(peer.raw_flags(), Some(WGPEER_F_REMOVE_ME));
+    }
+
+    #[test]
+    fn test_total_bytes() {
+        let peer =
+            WgPeer(vec![WgPeerAttrs::RxBytes(1024), WgPeerAttrs::TxBytes(512)]);
+        assert_eq!(peer.total_bytes(), Some(1536));
+        assert_eq!(
+            WgPeer(vec![WgPeerAttrs::RxBytes(1024)]).total_bytes(),
+            None
+        );
+        assert_eq!(WgPeer(vec![WgPeerAttrs::TxBytes(512)]).total_bytes(), None);
+    }
+
+    #[test]
+    fn test_allowed_ips_len() {
+        let mut peer = WgPeer::add_allowed_ips(
+            [0x01; WG_KEY_LEN],
+            WgAllowedIp::hosts(vec![
+                [10, 0, 0, 1].into(),
+                [10, 0, 0, 2].into(),
+            ]),
+        );
+        assert_eq!(peer.allowed_ips_len(), 2);
+        peer.0
+            .push(WgPeerAttrs::AllowedIps(vec!["fd00::/64".parse().unwrap()]));
+        assert_eq!(peer.allowed_ips_len(), 3);
+        assert_eq!(WgPeer(vec![]).allowed_ips_len(), 0);
+    }
+
+    #[test]
+    fn test_peer_buffer_len() {
+        let peer = WgPeer(vec![
+            WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN]),
+            WgPeerAttrs::Endpoint("192.0.2.1:51820".parse().unwrap()),
+            WgPeerAttrs::AllowedIps(vec![
+                "10.0.0.0/24".parse().unwrap(),
+                "10.0.1.1".parse().unwrap(),
+                "fd00::/64".parse().unwrap(),
+            ]),
+        ]);
+        let mut buf = vec![0; 1024];
+        peer.emit(&mut buf);
+        let emitted = NlaBuffer::new_checked(&buf[..]).unwrap();
+        assert_eq!(peer.buffer_len(), emitted.length() as usize);
+        // Headers, the key, the endpoint, then two IPv4 and one IPv6
+        // allowed IPs.
+        assert_eq!(peer.buffer_len(), 4 + 36 + 20 + 4 + 2 * 28 + 40);
+        assert_eq!(WgPeer(vec![]).buffer_len(), 4);
+    }
+
+    #[test]
+    fn test_keepalive_update() {
+        let peer = WgPeer::keepalive_update([0x01; WG_KEY_LEN], 25);
+        assert_eq!(
+            peer.0,
+            vec![
+                WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN]),
+                WgPeerAttrs::PersistentKeepalive(25),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rotate_psk() {
+        let peer = WgPeer::rotate_psk([0x01; WG_KEY_LEN], [0x02; WG_KEY_LEN]);
+        assert_eq!(
+            peer.0,
+            vec![
+                WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN]),
+                WgPeerAttrs::Flags(WGPEER_F_UPDATE_ONLY),
+                WgPeerAttrs::PresharedKey([0x02; WG_KEY_LEN]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_clear_psk() {
+        let peer = WgPeer::clear_psk([0x01; WG_KEY_LEN]);
+        assert_eq!(
+            peer.0,
+            vec![
+                WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN]),
+                WgPeerAttrs::Flags(WGPEER_F_UPDATE_ONLY),
+                WgPeerAttrs::PresharedKey([0; WG_KEY_LEN]),
+            ]
+        );
+        let mut buf = vec![0xff; peer.buffer_len()];
+        peer.emit(&mut buf);
+        let psk = [
+            &(4 + WG_KEY_LEN as u16).to_ne_bytes()[..],
+            &WGPEER_A_PRESHARED_KEY.to_ne_bytes()[..],
+            &[0; WG_KEY_LEN][..],
+        ]
+        .concat();
+        assert!(buf.windows(psk.len()).any(|w| w == psk));
+    }
+
+    #[test]
+    fn test_allowed_ips_equal() {
+        let peer = |ips: &[&str]| {
+            WgPeer(vec![
+                WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN]),
+                WgPeerAttrs::AllowedIps(
+                    ips.iter().map(|ip| ip.parse().unwrap()).collect(),
+                ),
+            ])
+        };
+        let a = peer(&["10.0.0.0/24", "fd00::/64", "192.168.1.1"]);
+        assert!(a.allowed_ips_equal(&a));
+        assert!(a.allowed_ips_equal(&peer(&[
+            "192.168.1.1/32",
+            "fd00::/64",
+            "10.0.0.1/24",
+            "10.0.0.0/24",
+        ])));
+        assert!(!a.allowed_ips_equal(&peer(&["10.0.0.0/24", "fd00::/64"])));
+        assert!(!a.allowed_ips_equal(&peer(&[
+            "10.0.0.0/16",
+            "fd00::/64",
+            "192.168.1.1",
+        ])));
+        assert!(peer(&[]).allowed_ips_equal(&WgPeer(vec![])));
+    }
+
+    #[test]
+    fn test_add_allowed_ips() {
+        let ip = WgAllowedIp::new([10, 0, 0, 0].into(), 24).unwrap();
+        let peer =
+            WgPeer::add_allowed_ips([0x01; WG_KEY_LEN], vec![ip.clone()]);
+        assert_eq!(
+            peer.0,
+            vec![
+                WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN]),
+                WgPeerAttrs::AllowedIps(vec![ip]),
+            ]
+        );
+        assert!(!peer.iter().any(|nla| matches!(nla, WgPeerAttrs::Flags(_))));
+    }
+
+    #[test]
+    fn test_allowed_ip_new() {
+        let ip = WgAllowedIp::new([10, 0, 0, 0].into(), 24).unwrap();
+        assert_eq!(
+            ip.0,
+            vec![
+                WgAllowedIpAttrs::Family(AF_INET),
+                WgAllowedIpAttrs::IpAddr([10, 0, 0, 0].into()),
+                WgAllowedIpAttrs::Cidr(24),
+            ]
+        );
+        assert!(WgAllowedIp::new("fd00::".parse().unwrap(), 128).is_ok());
+        assert_eq!(
+            WgAllowedIp::new([10, 0, 0, 0].into(), 33),
+            Err(WgError::InvalidCidr {
+                addr: [10, 0, 0, 0].into(),
+                cidr: 33
+            })
+        );
+    }
+
+    #[test]
+    fn test_allowed_ip_new_many() {
+        let v6: IpAddr = "fd00::".parse().unwrap();
+        let ips = WgAllowedIp::new_many(vec![
+            (IpAddr::from([10, 0, 0, 0]), 24),
+            (v6, 64),
+            (IpAddr::from([0, 0, 0, 0]), 0),
+        ])
+        .unwrap();
+        assert_eq!(
+            ips,
+            vec![
+                WgAllowedIp::new([10, 0, 0, 0].into(), 24).unwrap(),
+                WgAllowedIp::new(v6, 64).unwrap(),
+                WgAllowedIp::new([0, 0, 0, 0].into(), 0).unwrap(),
+            ]
+        );
+        assert_eq!(
+            WgAllowedIp::new_many(vec![(v6, 64), (v6, 129)]),
+            Err(WgError::InvalidCidr {
+                addr: v6,
+                cidr: 129
+            })
+        );
+        assert_eq!(WgAllowedIp::new_many(vec![]), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_allowed_ip_from_str() {
+        assert_eq!(
+            "10.0.0.0/24".parse(),
+            WgAllowedIp::new([10, 0, 0, 0].into(), 24)
+        );
+        assert_eq!(
+            "fd00::1".parse(),
+            WgAllowedIp::new("fd00::1".parse().unwrap(), 128)
+        );
+        assert_eq!(
+            "10.0.0.1".parse(),
+            WgAllowedIp::new([10, 0, 0, 1].into(), 32)
+        );
+        assert_eq!(
+            "10.0.0.0/33".parse::<WgAllowedIp>(),
+            Err(WgError::InvalidCidr {
+                addr: [10, 0, 0, 0].into(),
+                cidr: 33
+            })
+        );
+        assert_eq!("default".parse(), "0.0.0.0/0".parse::<WgAllowedIp>());
+        assert_eq!("default6".parse(), "::/0".parse::<WgAllowedIp>());
+        assert_eq!(
+            "default6".parse(),
+            WgAllowedIp::new(Ipv6Addr::UNSPECIFIED.into(), 0)
+        );
+        for s in [
+            "",
+            "10.0.0/24",
+            "10.0.0.0/",
+            "10.0.0.0/x",
+            "/24",
+            "Default",
+            "default/0",
+        ] {
+            assert_eq!(
+                s.parse::<WgAllowedIp>(),
+                Err(WgError::InvalidAllowedIp(s.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_allowed_ip_parse_conf_line() {
+        assert_eq!(
+            WgAllowedIp::parse_conf_line("  10.0.0.0/24,   fd00::/64, "),
+            Ok(vec![
+                WgAllowedIp::new([10, 0, 0, 0].into(), 24).unwrap(),
+                WgAllowedIp::new("fd00::".parse().unwrap(), 64).unwrap(),
+            ])
+        );
+        assert_eq!(
+            WgAllowedIp::parse_conf_line("10.0.0.1,fd00::1"),
+            Ok(vec![
+                WgAllowedIp::new([10, 0, 0, 1].into(), 32).unwrap(),
+                WgAllowedIp::new("fd00::1".parse().unwrap(), 128).unwrap(),
+            ])
+        );
+        assert_eq!(WgAllowedIp::parse_conf_line(" , "), Ok(vec![]));
+        assert_eq!(
+            WgAllowedIp::parse_conf_line("10.0.0.0/24, 10.0.0.0/x"),
+            Err(WgError::InvalidAllowedIp("10.0.0.0/x".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_allowed_ip_buffer_len() {
+        let ip: WgAllowedIp = "fd00::/64".parse().unwrap();
+        let mut buf = vec![0; 64];
+        ip.emit(&mut buf);
+        let emitted = NlaBuffer::new_checked(&buf[..]).unwrap();
+        assert_eq!(ip.buffer_len(), emitted.length() as usize);
+        assert_eq!(ip.buffer_len(), 40);
+        let ip: WgAllowedIp = "10.0.0.0/24".parse().unwrap();
+        assert_eq!(ip.buffer_len(), 28);
+    }
+
+    #[test]
+    fn test_allowed_ip_display() {
+        let ip: WgAllowedIp = "10.0.0.0/24".parse().unwrap();
+        assert_eq!(ip.to_string(), "10.0.0.0/24");
+        let ip: WgAllowedIp = "fd00::/64".parse().unwrap();
+        assert_eq!(ip.to_string(), "fd00::/64");
+        let ip: WgAllowedIp = "::".parse().unwrap();
+        assert_eq!(ip.to_string(), "::/128");
+
+        let ip = WgAllowedIp(vec![
+            WgAllowedIpAttrs::Family(AF_INET),
+            WgAllowedIpAttrs::Cidr(24),
+        ]);
+        assert_eq!(ip.to_string(), "<invalid>");
+        assert_eq!(WgAllowedIp(vec![]).to_string(), "<invalid>");
+    }
+
+    #[test]
+    fn test_allowed_ip_try_from_tuple() {
+        let v4 = Ipv4Addr::new(10, 0, 0, 0);
+        assert_eq!(
+            WgAllowedIp::try_from((v4, 24)),
+            WgAllowedIp::new(v4.into(), 24)
+        );
+        assert_eq!(
+            WgAllowedIp::try_from((v4, 33)),
+            Err(WgError::InvalidCidr {
+                addr: v4.into(),
+                cidr: 33
+            })
+        );
+        let v6: Ipv6Addr = "fd00::".parse().unwrap();
+        assert_eq!(
+            WgAllowedIp::try_from((v6, 128)).unwrap().to_string(),
+            "fd00::/128"
+        );
+        assert_eq!(
+            WgAllowedIp::try_from((v6, 129)),
+            Err(WgError::InvalidCidr {
+                addr: v6.into(),
+                cidr: 129
+            })
+        );
+    }
+
+    #[test]
+    fn test_allowed_ip_with_family() {
+        let addr: IpAddr = [10, 0, 0, 0].into();
+        let ip = WgAllowedIp::with_family(addr, 24, AF_INET6);
+        assert_eq!(
+            ip.0,
+            vec![
+                WgAllowedIpAttrs::Family(AF_INET6),
+                WgAllowedIpAttrs::IpAddr(addr),
+                WgAllowedIpAttrs::Cidr(24),
+            ]
+        );
+        assert_eq!(
+            WgAllowedIp::with_family(addr, 24, AF_INET),
+            WgAllowedIp::new_unchecked(addr, 24)
+        );
+    }
+
+    #[test]
+    fn test_allowed_ip_hosts() {
+        let v4 = IpAddr::from([10, 0, 0, 1]);
+        let v6: IpAddr = "fd00::1".parse().unwrap();
+        assert_eq!(
+            WgAllowedIp::hosts(vec![v4, v6]),
+            vec![
+                WgAllowedIp::new(v4, 32).unwrap(),
+                WgAllowedIp::new(v6, 128).unwrap(),
+            ]
+        );
+        assert_eq!(WgAllowedIp::hosts(vec![]), vec![]);
+    }
+
+    #[test]
+    fn test_allowed_ip_from_interface_addr() {
+        assert_eq!(
+            WgAllowedIp::from_interface_addr([10, 0, 0, 1].into(), 24),
+            WgAllowedIp::new([10, 0, 0, 0].into(), 24)
+        );
+        assert_eq!(
+            WgAllowedIp::from_interface_addr("fd00::1:2".parse().unwrap(), 64),
+            WgAllowedIp::new("fd00::".parse().unwrap(), 64)
+        );
+        assert_eq!(
+            WgAllowedIp::from_interface_addr([10, 0, 0, 1].into(), 0),
+            WgAllowedIp::new([0, 0, 0, 0].into(), 0)
+        );
+        assert_eq!(
+            WgAllowedIp::from_interface_addr([10, 0, 0, 1].into(), 32),
+            WgAllowedIp::new([10, 0, 0, 1].into(), 32)
+        );
+        assert!(
+            WgAllowedIp::from_interface_addr([10, 0, 0, 1].into(), 33).is_err()
+        );
+    }
+
+    #[test]
+    fn test_allowed_ip_new_unchecked_matches_new() {
+        for (addr, cidr) in [
+            (IpAddr::from([10, 0, 0, 0]), 24),
+            (IpAddr::from([0, 0, 0, 0]), 0),
+            ("fd00::".parse().unwrap(), 64),
+        ] {
+            assert_eq!(
+                WgAllowedIp::new_unchecked(addr, cidr),
+                WgAllowedIp::new(addr, cidr).unwrap()
+            );
+        }
+    }
+}
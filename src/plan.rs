@@ -0,0 +1,252 @@
+// SPDX-License-Identifier: MIT
+
+use std::{collections::BTreeSet, net::IpAddr};
+
+use netlink_packet_utils::nla::Nla;
+
+use crate::{
+    constants::*,
+    nlas::{peer::mask, WgDeviceAttrs, WgPeer, WgPeerAttrs},
+    WgKey, Wireguard,
+};
+
+/// What a `SetDevice` message would change on a device, see
+/// [`Wireguard::plan_against`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChangePlan {
+    /// The peers the message creates, in the order of the message.
+    pub added_peers: Vec<WgKey>,
+    /// The peers the message removes, flagged `WGPEER_F_REMOVE_ME` or
+    /// left out of a message flagged `WGDEVICE_F_REPLACE_PEERS`.
+    pub removed_peers: Vec<WgKey>,
+    /// The existing peers whose preshared key, endpoint, keepalive
+    /// interval or allowed IPs change.
+    pub modified_peers: Vec<WgKey>,
+    /// The kinds (`WGDEVICE_A_*`) of the device attributes whose value
+    /// changes: the private key, the listen port and the fwmark.
+    pub modified_device_attrs: Vec<u16>,
+}
+
+impl ChangePlan {
+    /// Whether the message would change nothing.
+    pub fn is_empty(&self) -> bool {
+        self.added_peers.is_empty()
+            && self.removed_peers.is_empty()
+            && self.modified_peers.is_empty()
+            && self.modified_device_attrs.is_empty()
+    }
+}
+
+impl Wireguard {
+    /// Describe what sending this `SetDevice` message would change on the
+    /// device `current_dump` was dumped from, without sending anything.
+    ///
+    /// The flags are interpreted like the kernel does:
+    /// `WGDEVICE_F_REPLACE_PEERS` removes the peers the message doesn't
+    /// list, `WGPEER_F_UPDATE_ONLY` doesn't create missing peers,
+    /// `WGPEER_F_REMOVE_ME` removes the peer and
+    /// `WGPEER_F_REPLACE_ALLOWEDIPS` replaces its allowed IPs instead of
+    /// appending to them. Allowed IPs are compared with their host bits
+    /// cleared. Peers without a public key are ignored.
+    pub fn plan_against(&self, current_dump: &Wireguard) -> ChangePlan {
+        let mut plan = ChangePlan::default();
+        for nla in &self.nlas {
+            if let WgDeviceAttrs::PrivateKey(_)
+            | WgDeviceAttrs::ListenPort(_)
+            | WgDeviceAttrs::Fwmark(_) = nla
+            {
+                let current =
+                    current_dump.nlas.iter().find(|n| n.kind() == nla.kind());
+                if current != Some(nla)
+                    && !plan.modified_device_attrs.contains(&nla.kind())
+                {
+                    plan.modified_device_attrs.push(nla.kind());
+                }
+            }
+        }
+
+        let mut listed = BTreeSet::new();
+        for peer in self.peers() {
+            let key = match peer.public_key() {
+                Some(key) => key,
+                None => continue,
+            };
+            let flags = peer.raw_flags().unwrap_or(0);
+            let current = current_dump.peer_by_key(key);
+            if flags & WGPEER_F_REMOVE_ME != 0 {
+                if current.is_some() {
+                    push_key(&mut plan.removed_peers, key);
+                }
+                continue;
+            }
+            listed.insert(*key);
+            match current {
+                Some(current) => {
+                    if peer_changes(peer, current, flags) {
+                        push_key(&mut plan.modified_peers, key);
+                    }
+                }
+                None if flags & WGPEER_F_UPDATE_ONLY != 0 => (),
+                None => push_key(&mut plan.added_peers, key),
+            }
+        }
+
+        let replace_peers = self.nlas.iter().any(|nla| {
+            matches!(nla, WgDeviceAttrs::Flags(f)
+                if f & WGDEVICE_F_REPLACE_PEERS != 0)
+        });
+        if replace_peers {
+            for key in current_dump.peers().filter_map(WgPeer::public_key) {
+                if !listed.contains(key) {
+                    push_key(&mut plan.removed_peers, key);
+                }
+            }
+        }
+        plan
+    }
+}
+
+fn push_key(keys: &mut Vec<WgKey>, key: &[u8; WG_KEY_LEN]) {
+    let key = WgKey::from(key);
+    if !keys.contains(&key) {
+        keys.push(key);
+    }
+}
+
+fn peer_changes(peer: &WgPeer, current: &WgPeer, flags: u32) -> bool {
+    let settings_change = peer.iter().any(|nla| match nla {
+        WgPeerAttrs::PresharedKey(_)
+        | WgPeerAttrs::Endpoint(_)
+        | WgPeerAttrs::PersistentKeepalive(_) => {
+            current.iter().find(|n| n.kind() == nla.kind()) != Some(nla)
+        }
+        _ => false,
+    });
+    if settings_change {
+        return true;
+    }
+    let wanted = networks(peer);
+    let current = networks(current);
+    if flags & WGPEER_F_REPLACE_ALLOWEDIPS != 0 {
+        wanted != current
+    } else {
+        !wanted.is_subset(&current)
+    }
+}
+
+fn networks(peer: &WgPeer) -> BTreeSet<(IpAddr, u8)> {
+    peer.iter()
+        .filter_map(|nla| match nla {
+            WgPeerAttrs::AllowedIps(ips) => Some(ips),
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|ip| ip.addr_and_cidr())
+        .map(|(addr, cidr)| (mask(addr, cidr), cidr))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{WgDeviceBuilder, WgPeerBuilder, WgPeerFlags};
+
+    fn dump() -> Wireguard {
+        let mut wg = WgDeviceBuilder::new()
+            .ifname("wg0")
+            .private_key([0xaa; WG_KEY_LEN])
+            .listen_port(51820)
+            .fwmark(0)
+            .peer(
+                WgPeerBuilder::new([0x01; WG_KEY_LEN])
+                    .persistent_keepalive(25)
+                    .allowed_ip("10.0.0.1".parse().unwrap())
+                    .build(),
+            )
+            .peer(
+                WgPeerBuilder::new([0x02; WG_KEY_LEN])
+                    .allowed_ip("10.0.0.2".parse().unwrap())
+                    .build(),
+            )
+            .peer(
+                WgPeerBuilder::new([0x03; WG_KEY_LEN])
+                    .allowed_ip("10.0.0.3".parse().unwrap())
+                    .build(),
+            )
+            .build();
+        wg.cmd = crate::WireguardCmd::GetDevice;
+        wg
+    }
+
+    #[test]
+    fn test_plan_against() {
+        let set = WgDeviceBuilder::new()
+            .ifname("wg0")
+            .private_key([0xaa; WG_KEY_LEN])
+            .listen_port(51821)
+            .peer(
+                // Only appends an allowed IP the peer already has.
+                WgPeerBuilder::new([0x01; WG_KEY_LEN])
+                    .persistent_keepalive(25)
+                    .allowed_ip("10.0.0.1".parse().unwrap())
+                    .build(),
+            )
+            .peer(
+                WgPeerBuilder::new([0x02; WG_KEY_LEN])
+                    .flags(WgPeerFlags::REPLACE_ALLOWEDIPS)
+                    .allowed_ip("10.0.1.0/24".parse().unwrap())
+                    .build(),
+            )
+            .peer(
+                WgPeerBuilder::new([0x03; WG_KEY_LEN])
+                    .flags(WgPeerFlags::REMOVE_ME)
+                    .build(),
+            )
+            .peer(WgPeerBuilder::new([0x04; WG_KEY_LEN]).build())
+            .peer(
+                WgPeerBuilder::new([0x05; WG_KEY_LEN])
+                    .flags(WgPeerFlags::UPDATE_ONLY)
+                    .build(),
+            )
+            .build();
+        assert_eq!(
+            set.plan_against(&dump()),
+            ChangePlan {
+                added_peers: vec![WgKey::new([0x04; WG_KEY_LEN])],
+                removed_peers: vec![WgKey::new([0x03; WG_KEY_LEN])],
+                modified_peers: vec![WgKey::new([0x02; WG_KEY_LEN])],
+                modified_device_attrs: vec![WGDEVICE_A_LISTEN_PORT],
+            }
+        );
+    }
+
+    #[test]
+    fn test_plan_against_replace_peers() {
+        let set = WgDeviceBuilder::new()
+            .ifname("wg0")
+            .replace_peers()
+            .peer(
+                WgPeerBuilder::new([0x01; WG_KEY_LEN])
+                    .persistent_keepalive(0)
+                    .build(),
+            )
+            .build();
+        let plan = set.plan_against(&dump());
+        assert_eq!(plan.modified_peers, vec![WgKey::new([0x01; WG_KEY_LEN])]);
+        assert_eq!(
+            plan.removed_peers,
+            vec![
+                WgKey::new([0x02; WG_KEY_LEN]),
+                WgKey::new([0x03; WG_KEY_LEN])
+            ]
+        );
+        assert!(plan.added_peers.is_empty());
+
+        let dump = dump();
+        assert!(dump
+            .clone()
+            .into_set_template()
+            .plan_against(&dump)
+            .is_empty());
+    }
+}
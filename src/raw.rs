@@ -9,7 +9,10 @@ use std::{
 use byteorder::{BigEndian, ByteOrder, NativeEndian};
 use netlink_packet_utils::DecodeError;
 
-use crate::constants::{AF_INET, AF_INET6};
+use crate::{
+    constants::{AF_INET, AF_INET6},
+    WgError,
+};
 
 pub const IPV4_LEN: usize = 4;
 pub const IPV6_LEN: usize = 16;
@@ -111,8 +114,8 @@ pub fn emit_ip(addr: &IpAddr, buf: &mut [u8]) {
 /// ```
 /// Note that this adds 8 bytes of padding so the buffer must be large
 /// enough to account for them.
-fn emit_socket_addr_v4(addr: &SocketAddrV4, buf: &mut [u8]) {
-    NativeEndian::write_u16(&mut buf[..2], AF_INET);
+fn emit_socket_addr_v4<B: ByteOrder>(addr: &SocketAddrV4, buf: &mut [u8]) {
+    B::write_u16(&mut buf[..2], AF_INET);
     BigEndian::write_u16(&mut buf[2..4], addr.port());
     buf[4..8].copy_from_slice(addr.ip().octets().as_slice());
     // padding
@@ -135,52 +138,84 @@ fn emit_socket_addr_v4(addr: &SocketAddrV4, buf: &mut [u8]) {
 /// };
 /// ```
 /// `sockaddr_in6` is 4 bytes aligned (28 bytes) so there's no padding.
-fn emit_socket_addr_v6(addr: &SocketAddrV6, buf: &mut [u8]) {
-    NativeEndian::write_u16(&mut buf[..2], AF_INET6);
+fn emit_socket_addr_v6<B: ByteOrder>(addr: &SocketAddrV6, buf: &mut [u8]) {
+    B::write_u16(&mut buf[..2], AF_INET6);
     BigEndian::write_u16(&mut buf[2..4], addr.port());
-    NativeEndian::write_u32(&mut buf[4..8], addr.flowinfo());
+    B::write_u32(&mut buf[4..8], addr.flowinfo());
     buf[8..24].copy_from_slice(addr.ip().octets().as_slice());
-    NativeEndian::write_u32(&mut buf[24..28], addr.scope_id());
+    B::write_u32(&mut buf[24..28], addr.scope_id());
 }
 
 pub fn emit_socket_addr(addr: &SocketAddr, buf: &mut [u8]) {
+    emit_socket_addr_in::<NativeEndian>(addr, buf)
+}
+
+/// Same as [`emit_socket_addr`], with the family, the flow information and
+/// the scope ID written in the byte order `B`. The port is always written
+/// in network byte order.
+pub(crate) fn emit_socket_addr_in<B: ByteOrder>(
+    addr: &SocketAddr,
+    buf: &mut [u8],
+) {
     match addr {
-        SocketAddr::V4(v4) => emit_socket_addr_v4(v4, buf),
-        SocketAddr::V6(v6) => emit_socket_addr_v6(v6, buf),
+        SocketAddr::V4(v4) => emit_socket_addr_v4::<B>(v4, buf),
+        SocketAddr::V6(v6) => emit_socket_addr_v6::<B>(v6, buf),
     }
 }
 
 pub fn parse_socket_addr(buf: &[u8]) -> Result<SocketAddr, DecodeError> {
-    match buf.len() {
-        SOCKET_ADDR_V4_LEN => Ok(SocketAddr::V4(parse_socket_addr_v4(buf))),
-        SOCKET_ADDR_V6_LEN => Ok(SocketAddr::V6(parse_socket_addr_v6(buf))),
-        _ => Err(format!(
-            "invalid socket address (should be 16 or 28 bytes): {:x?}",
+    parse_sockaddr(buf).map_err(|e| DecodeError::from(e.to_string()))
+}
+
+/// Parse a raw `sockaddr_in` (16 bytes) or `sockaddr_in6` (28 bytes), as
+/// found in `WGPEER_A_ENDPOINT`, e.g. from a packet capture.
+///
+/// The family is read in host endianness and the port in network
+/// endianness, like the kernel writes them. The buffer is rejected if its
+/// length doesn't match its family.
+pub fn parse_sockaddr(buf: &[u8]) -> Result<SocketAddr, WgError> {
+    let family = if buf.len() >= 2 {
+        NativeEndian::read_u16(&buf[..2])
+    } else {
+        0
+    };
+    match (family, buf.len()) {
+        (AF_INET, SOCKET_ADDR_V4_LEN) => {
+            Ok(SocketAddr::V4(parse_socket_addr_v4(buf)))
+        }
+        (AF_INET6, SOCKET_ADDR_V6_LEN) => {
+            Ok(SocketAddr::V6(parse_socket_addr_v6(buf)))
+        }
+        _ => Err(WgError::InvalidSocketAddr(format!(
+            "expected 16 bytes for AF_INET or 28 bytes for AF_INET6, got \
+             {} bytes: {:x?}",
+            buf.len(),
             buf
-        )
-        .into()),
+        ))),
     }
 }
 
 pub fn emit_timespec(time: &SystemTime, buf: &mut [u8]) {
+    emit_timespec_in::<NativeEndian>(time, buf)
+}
+
+/// Same as [`emit_timespec`], in the byte order `B`.
+pub(crate) fn emit_timespec_in<B: ByteOrder>(
+    time: &SystemTime,
+    buf: &mut [u8],
+) {
     match time.duration_since(SystemTime::UNIX_EPOCH) {
         Ok(epoch_elapsed) => {
-            NativeEndian::write_i64(
-                &mut buf[..8],
-                epoch_elapsed.as_secs() as i64,
-            );
-            NativeEndian::write_i64(
-                &mut buf[8..16],
-                epoch_elapsed.subsec_nanos() as i64,
-            );
+            B::write_i64(&mut buf[..8], epoch_elapsed.as_secs() as i64);
+            B::write_i64(&mut buf[8..16], epoch_elapsed.subsec_nanos() as i64);
         }
         Err(e) => {
             // This method is supposed to not fail so just log an
             // error. If we want such errors to be handled by the
             // caller, we shouldn't use `SystemTime`.
             error!("error while emitting timespec: {:?}", e);
-            NativeEndian::write_i64(&mut buf[..8], 0_i64);
-            NativeEndian::write_i64(&mut buf[8..16], 0_i64);
+            B::write_i64(&mut buf[..8], 0_i64);
+            B::write_i64(&mut buf[8..16], 0_i64);
         }
     }
 }
@@ -200,7 +235,6 @@ pub fn parse_timespec(buf: &[u8]) -> Result<SystemTime, DecodeError> {
 }
 
 #[cfg(test)]
-
 mod test {
     use std::str::FromStr;
 
@@ -243,4 +277,60 @@ mod test {
             .into()
         );
     }
+
+    #[test]
+    fn test_socket_addr_in6_flowinfo_round_trip() {
+        let addr = SocketAddr::from(SocketAddrV6::new(
+            "fd00::1".parse().unwrap(),
+            51820,
+            0x000c_0ffe,
+            7,
+        ));
+        let mut buf = [0xff; SOCKET_ADDR_V6_LEN];
+        emit_socket_addr(&addr, &mut buf);
+        assert_eq!(NativeEndian::read_u32(&buf[4..8]), 0x000c_0ffe);
+        let parsed = parse_socket_addr(&buf).unwrap();
+        assert_eq!(parsed, addr);
+        match parsed {
+            SocketAddr::V6(v6) => {
+                assert_eq!(v6.flowinfo(), 0x000c_0ffe);
+                assert_eq!(v6.scope_id(), 7);
+            }
+            SocketAddr::V4(_) => panic!("expected an IPv6 address"),
+        }
+    }
+
+    #[test]
+    fn test_emit_socket_addr_in_zeroes_padding() {
+        // A reused buffer must not leak its old bytes into sin_zero.
+        let mut buf = [0xff; SOCKET_ADDR_V4_LEN];
+        emit_socket_addr(&"192.168.1.1:51820".parse().unwrap(), &mut buf);
+        assert_eq!(&buf[8..], &[0; 8]);
+        assert_eq!(&buf[..], SOCKADDR_IN_BYTES_2);
+    }
+
+    #[test]
+    fn test_parse_sockaddr() {
+        assert_eq!(
+            parse_sockaddr(SOCKADDR_IN_BYTES_2).unwrap(),
+            "192.168.1.1:51820".parse().unwrap()
+        );
+        assert_eq!(
+            parse_sockaddr(SOCKADDR_IN6_BYTES_1).unwrap(),
+            parse_socket_addr(SOCKADDR_IN6_BYTES_1).unwrap()
+        );
+        for buf in [
+            &[][..],
+            &[0x02],
+            &SOCKADDR_IN_BYTES_1[..8],
+            &SOCKADDR_IN6_BYTES_1[..27],
+            // An AF_INET family with the length of a sockaddr_in6.
+            &[&SOCKADDR_IN_BYTES_1[..2], &SOCKADDR_IN6_BYTES_1[2..]].concat(),
+        ] {
+            assert!(matches!(
+                parse_sockaddr(buf),
+                Err(WgError::InvalidSocketAddr(_))
+            ));
+        }
+    }
 }
@@ -0,0 +1,240 @@
+// SPDX-License-Identifier: MIT
+
+//! Helpers for the `serde` feature.
+
+/// (De)serialize a `[u8; 32]` key as a base64 string, the representation
+/// used by `wg(8)`. Use it with `#[serde(with = "base64_key")]`.
+pub mod base64_key {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use crate::{constants::WG_KEY_LEN, WgKey};
+
+    pub fn serialize<S: Serializer>(
+        key: &[u8; WG_KEY_LEN],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&WgKey::from(key).to_base64())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<[u8; WG_KEY_LEN], D::Error> {
+        let s = String::deserialize(deserializer)?;
+        WgKey::from_base64(&s)
+            .map(Into::into)
+            .map_err(D::Error::custom)
+    }
+}
+
+/// (De)serialize a [`WgKey`](crate::WgKey) as an array of 32 bytes, for
+/// compact binary formats such as `bincode` or MessagePack where base64
+/// would waste space. Use it with `#[serde(with = "wg_key_bytes")]`.
+///
+/// [`WgKey`](crate::WgKey) itself is serialized as base64, which suits
+/// human readable formats better.
+pub mod wg_key_bytes {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::{constants::WG_KEY_LEN, WgKey};
+
+    pub fn serialize<S: Serializer>(
+        key: &WgKey,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        key.as_bytes().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<WgKey, D::Error> {
+        <[u8; WG_KEY_LEN]>::deserialize(deserializer).map(WgKey::new)
+    }
+}
+
+/// (De)serialize a [`SocketAddr`](std::net::SocketAddr) endpoint as an
+/// object with separate fields, `{"ip": "192.168.1.1", "port": 51820}`,
+/// instead of the `"192.168.1.1:51820"` string serde uses. Use it with
+/// `#[serde(with = "split_endpoint")]`, or with
+/// `#[serde(with = "split_endpoint::option")]` on an
+/// `Option<SocketAddr>`.
+///
+/// The flow information and scope id of IPv6 endpoints are not kept.
+pub mod split_endpoint {
+    use std::net::{IpAddr, SocketAddr};
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct Endpoint {
+        ip: IpAddr,
+        port: u16,
+    }
+
+    pub fn serialize<S: Serializer>(
+        endpoint: &SocketAddr,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        Endpoint {
+            ip: endpoint.ip(),
+            port: endpoint.port(),
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<SocketAddr, D::Error> {
+        let endpoint = Endpoint::deserialize(deserializer)?;
+        Ok(SocketAddr::new(endpoint.ip, endpoint.port))
+    }
+
+    /// The same representation for an `Option<SocketAddr>`, `None` being
+    /// `null`.
+    pub mod option {
+        use std::net::SocketAddr;
+
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+        use super::Endpoint;
+
+        pub fn serialize<S: Serializer>(
+            endpoint: &Option<SocketAddr>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            endpoint
+                .map(|endpoint| Endpoint {
+                    ip: endpoint.ip(),
+                    port: endpoint.port(),
+                })
+                .serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<SocketAddr>, D::Error> {
+            let endpoint = Option::<Endpoint>::deserialize(deserializer)?;
+            Ok(endpoint
+                .map(|endpoint| SocketAddr::new(endpoint.ip, endpoint.port)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        constants::WG_KEY_LEN, nlas::WgDeviceAttrs, WgDeviceBuilder, WgKey,
+        WgPeerBuilder, Wireguard,
+    };
+
+    #[test]
+    fn test_serde_round_trip() {
+        let wg = WgDeviceBuilder::new()
+            .ifname("wg0")
+            .private_key([0xaa; WG_KEY_LEN])
+            .peer(
+                WgPeerBuilder::new([0x01; WG_KEY_LEN])
+                    .endpoint("10.0.0.1:51820".parse().unwrap())
+                    .build(),
+            )
+            .build();
+        let json = serde_json::to_string(&wg).unwrap();
+        assert!(json.contains(&format!(
+            "\"PrivateKey\":\"{}\"",
+            WgKey::new([0xaa; WG_KEY_LEN]).to_base64()
+        )));
+        let parsed: Wireguard = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, wg);
+    }
+
+    #[test]
+    fn test_deserialize_bad_key_length() {
+        let json = format!(
+            "{{\"PrivateKey\":\"{}\"}}",
+            crate::encoding::encode(&[0xaa; WG_KEY_LEN - 1])
+        );
+        assert!(serde_json::from_str::<WgDeviceAttrs>(&json).is_err());
+    }
+
+    #[cfg(feature = "schemars")]
+    #[test]
+    fn test_json_schema() {
+        let schema = schemars::schema_for!(Wireguard);
+        let schema = serde_json::to_value(&schema).unwrap();
+        let definitions = &schema["definitions"];
+        for name in [
+            "WireguardCmd",
+            "WgDeviceAttrs",
+            "WgPeer",
+            "WgPeerAttrs",
+            "WgAllowedIp",
+            "WgAllowedIpAttrs",
+        ] {
+            assert!(definitions.get(name).is_some(), "{} is missing", name);
+        }
+        let key = definitions["WgDeviceAttrs"]["oneOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find_map(|variant| variant["properties"].get("PrivateKey"))
+            .unwrap();
+        assert_eq!(key["type"], "string");
+    }
+
+    #[test]
+    fn test_wg_key_bytes_bincode() {
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Peer {
+            #[serde(with = "super::wg_key_bytes")]
+            public_key: WgKey,
+            keepalive: u16,
+        }
+
+        let peer = Peer {
+            public_key: WgKey::new([0x01; WG_KEY_LEN]),
+            keepalive: 25,
+        };
+        let bytes = bincode::serialize(&peer).unwrap();
+        assert_eq!(bytes.len(), WG_KEY_LEN + 2);
+        assert_eq!(&bytes[..WG_KEY_LEN], &[0x01; WG_KEY_LEN]);
+        assert_eq!(bincode::deserialize::<Peer>(&bytes).unwrap(), peer);
+        // The base64 form takes a length and 44 characters.
+        assert!(bincode::serialize(&peer.public_key).unwrap().len() > 44);
+        assert!(bincode::deserialize::<Peer>(&bytes[1..]).is_err());
+    }
+
+    #[test]
+    fn test_split_endpoint() {
+        use std::net::SocketAddr;
+
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Peer {
+            #[serde(with = "super::split_endpoint")]
+            endpoint: SocketAddr,
+            #[serde(with = "super::split_endpoint::option", default)]
+            backup: Option<SocketAddr>,
+        }
+
+        let peer = Peer {
+            endpoint: "192.168.1.1:51820".parse().unwrap(),
+            backup: Some("[fd00::1]:51821".parse().unwrap()),
+        };
+        let json = serde_json::to_value(&peer).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "endpoint": {"ip": "192.168.1.1", "port": 51820},
+                "backup": {"ip": "fd00::1", "port": 51821},
+            })
+        );
+        assert_eq!(serde_json::from_value::<Peer>(json).unwrap(), peer);
+
+        let json = serde_json::json!({
+            "endpoint": {"ip": "10.0.0.1", "port": 1},
+            "backup": null,
+        });
+        let parsed: Peer = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.backup, None);
+        let json = serde_json::json!({"endpoint": {"ip": "10.0.0.1"}});
+        assert!(serde_json::from_value::<Peer>(json).is_err());
+    }
+}
@@ -0,0 +1,540 @@
+// SPDX-License-Identifier: MIT
+
+use std::net::IpAddr;
+
+use crate::{
+    constants::*,
+    nlas::{WgAllowedIpAttrs, WgDeviceAttrs, WgPeer, WgPeerAttrs},
+    WgError, WgKey, Wireguard, WireguardCmd,
+};
+
+// The only version of the WireGuard protocol.
+const PROTOCOL_VERSION: u32 = 1;
+
+impl Wireguard {
+    /// Run the checks matching the command of the message
+    /// ([`Wireguard::validate_set`] or [`Wireguard::validate_get`]) and
+    /// report every problem found in a [`WgError::Multiple`].
+    ///
+    /// A non-empty `SetDevice` message is also checked with
+    /// [`Wireguard::validate_identity`], as the kernel rejects it too
+    /// without exactly one device identity.
+    pub fn validate(&self) -> Result<(), WgError> {
+        let issues = match self.cmd {
+            WireguardCmd::GetDevice => self.get_issues(),
+            WireguardCmd::SetDevice if self.is_empty() => self.set_issues(),
+            WireguardCmd::SetDevice => self
+                .identity_issue()
+                .into_iter()
+                .chain(self.set_issues())
+                .collect(),
+        };
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(WgError::Multiple(issues))
+        }
+    }
+
+    /// Run [`Wireguard::validate`] and panic with every problem found, for
+    /// tests building messages.
+    #[cfg(feature = "test-util")]
+    #[track_caller]
+    pub fn assert_valid(&self) {
+        if let Err(e) = self.validate() {
+            panic!("invalid {:?} message: {}\n{:#?}", self.cmd, e, self.nlas);
+        }
+    }
+
+    /// Check a `GetDevice` message, see [`Wireguard::validate_identity`].
+    /// Only the first problem is reported.
+    pub fn validate_get(&self) -> Result<(), WgError> {
+        first_issue(self.get_issues())
+    }
+
+    /// Check that the message identifies the device with exactly one of
+    /// `WGDEVICE_A_IFINDEX` and `WGDEVICE_A_IFNAME`, as the kernel
+    /// requires for both commands.
+    pub fn validate_identity(&self) -> Result<(), WgError> {
+        match self.identity_issue() {
+            Some(issue) => Err(issue),
+            None => Ok(()),
+        }
+    }
+
+    /// Check that a `SetDevice` message is coherent before sending it.
+    ///
+    /// The kernel accepts most combinations of flags, so this only
+    /// reports combinations that are almost certainly a mistake, and
+    /// values the kernel would reject. Only the first problem is reported.
+    pub fn validate_set(&self) -> Result<(), WgError> {
+        first_issue(self.set_issues())
+    }
+
+    /// Check that the message carries at most `max` peers, across all its
+    /// `WGDEVICE_A_PEERS` attributes, e.g. to enforce a per-interface
+    /// limit before sending anything rather than after part of the
+    /// message was applied.
+    pub fn check_peer_limit(&self, max: usize) -> Result<(), WgError> {
+        let count = self.peers().count();
+        if count > max {
+            Err(WgError::TooManyPeers { count, max })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Check every allowed IP of every peer: it must carry a
+    /// `WGALLOWEDIP_A_FAMILY`, a `WGALLOWEDIP_A_IPADDR` and a
+    /// `WGALLOWEDIP_A_CIDR_MASK`, the family must be the one of the
+    /// address and the prefix length must fit the address. Every problem
+    /// found is reported, in the order of the message.
+    pub fn validate_allowed_ips(&self) -> Result<(), Vec<WgError>> {
+        let mut issues = Vec::new();
+        let allowed_ips = self
+            .peers()
+            .flat_map(|peer| peer.iter())
+            .filter_map(|nla| match nla {
+                WgPeerAttrs::AllowedIps(ips) => Some(ips),
+                _ => None,
+            })
+            .flatten();
+        for ip in allowed_ips {
+            let mut family = None;
+            let mut addr = None;
+            let mut cidr = None;
+            for nla in ip.iter() {
+                match nla {
+                    WgAllowedIpAttrs::Family(v) => family = Some(*v),
+                    WgAllowedIpAttrs::IpAddr(v) => addr = Some(*v),
+                    WgAllowedIpAttrs::Cidr(v) => cidr = Some(*v),
+                    WgAllowedIpAttrs::Unspec(_) => (),
+                }
+            }
+            for (kind, present) in [
+                (WGALLOWEDIP_A_FAMILY, family.is_some()),
+                (WGALLOWEDIP_A_IPADDR, addr.is_some()),
+                (WGALLOWEDIP_A_CIDR_MASK, cidr.is_some()),
+            ] {
+                if !present {
+                    issues.push(WgError::MissingAllowedIpAttr { kind });
+                }
+            }
+            let addr = match addr {
+                Some(addr) => addr,
+                None => continue,
+            };
+            let (expected, max) = match addr {
+                IpAddr::V4(_) => (AF_INET, 32),
+                IpAddr::V6(_) => (AF_INET6, 128),
+            };
+            if let Some(family) = family.filter(|f| *f != expected) {
+                issues.push(WgError::AllowedIpFamilyMismatch { addr, family });
+            }
+            if let Some(cidr) = cidr.filter(|c| *c > max) {
+                issues.push(WgError::InvalidCidr { addr, cidr });
+            }
+        }
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(issues)
+        }
+    }
+
+    fn get_issues(&self) -> Vec<WgError> {
+        self.identity_issue().into_iter().collect()
+    }
+
+    fn set_issues(&self) -> Vec<WgError> {
+        if self.is_empty() {
+            return vec![WgError::EmptySetDevice];
+        }
+        let mut issues = Vec::new();
+        let replace_peers = self.nlas.iter().any(|nla| {
+            matches!(nla, WgDeviceAttrs::Flags(f)
+                if f & WGDEVICE_F_REPLACE_PEERS != 0)
+        });
+        for peer in self.peers() {
+            if peer.public_key().is_none() {
+                issues.push(WgError::MissingPeerPublicKey);
+            }
+            if replace_peers && peer_flags(peer) & WGPEER_F_REMOVE_ME != 0 {
+                issues.push(WgError::RedundantPeerRemoval {
+                    public_key: peer.public_key().copied(),
+                });
+            }
+            for nla in peer.iter() {
+                if let WgPeerAttrs::ProtocolVersion(version) = nla {
+                    if *version != PROTOCOL_VERSION {
+                        issues.push(WgError::UnsupportedProtocolVersion {
+                            version: *version,
+                        });
+                    }
+                }
+            }
+        }
+        issues
+    }
+
+    fn identity_issue(&self) -> Option<WgError> {
+        let has_index = self
+            .nlas
+            .iter()
+            .any(|nla| matches!(nla, WgDeviceAttrs::IfIndex(_)));
+        let has_name = self
+            .nlas
+            .iter()
+            .any(|nla| matches!(nla, WgDeviceAttrs::IfName(_)));
+        match (has_index, has_name) {
+            (false, false) => Some(WgError::MissingIdentity),
+            (true, true) => Some(WgError::AmbiguousIdentity),
+            _ => None,
+        }
+    }
+
+    /// Check that the device keys were not swapped by mistake.
+    ///
+    /// Private keys generated by `wg genkey` are clamped, so an unclamped
+    /// `WGDEVICE_A_PRIVATE_KEY` most likely holds a public key. This is
+    /// only a heuristic: the kernel clamps the key itself and accepts any
+    /// 32 bytes.
+    ///
+    /// With the `crypto` feature, when both `WGDEVICE_A_PRIVATE_KEY` and
+    /// `WGDEVICE_A_PUBLIC_KEY` are present, this also derives the public
+    /// key and compares it.
+    pub fn check_key_consistency(&self) -> Result<(), WgError> {
+        let private_key = self.nlas.iter().find_map(|nla| match nla {
+            WgDeviceAttrs::PrivateKey(key) => Some(key),
+            _ => None,
+        });
+        let private_key = match private_key {
+            Some(key) => key,
+            None => return Ok(()),
+        };
+        let mut clamped = WgKey::new(*private_key);
+        clamped.clamp_private();
+        if clamped.as_bytes() != private_key {
+            return Err(WgError::UnclampedPrivateKey);
+        }
+        #[cfg(feature = "crypto")]
+        {
+            let public_key = self.nlas.iter().find_map(|nla| match nla {
+                WgDeviceAttrs::PublicKey(key) => Some(key),
+                _ => None,
+            });
+            if let Some(public_key) = public_key {
+                let secret = x25519_dalek::StaticSecret::from(*private_key);
+                let derived = x25519_dalek::PublicKey::from(&secret);
+                if derived.as_bytes() != public_key {
+                    return Err(WgError::KeyMismatch);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn first_issue(issues: Vec<WgError>) -> Result<(), WgError> {
+    match issues.into_iter().next() {
+        Some(issue) => Err(issue),
+        None => Ok(()),
+    }
+}
+
+fn peer_flags(peer: &WgPeer) -> u32 {
+    peer.iter()
+        .filter_map(|nla| match nla {
+            WgPeerAttrs::Flags(f) => Some(*f),
+            _ => None,
+        })
+        .fold(0, |acc, f| acc | f)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn set_device(flags: u32, peer_flags: u32) -> Wireguard {
+        Wireguard {
+            cmd: WireguardCmd::SetDevice,
+            nlas: vec![
+                WgDeviceAttrs::IfName("wg0".to_string()),
+                WgDeviceAttrs::Flags(flags),
+                WgDeviceAttrs::Peers(vec![WgPeer(vec![
+                    WgPeerAttrs::PublicKey([0x01; WG_KEY_LEN]),
+                    WgPeerAttrs::Flags(peer_flags),
+                ])]),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_validate_set_redundant_remove() {
+        let wg = set_device(WGDEVICE_F_REPLACE_PEERS, WGPEER_F_REMOVE_ME);
+        assert_eq!(
+            wg.validate_set(),
+            Err(WgError::RedundantPeerRemoval {
+                public_key: Some([0x01; WG_KEY_LEN])
+            })
+        );
+    }
+
+    #[test]
+    fn test_validate_set_keyless_peer() {
+        let mut wg = set_device(0, 0);
+        wg.extend(vec![WgPeer(vec![WgPeerAttrs::PersistentKeepalive(25)])]);
+        assert_eq!(wg.validate_set(), Err(WgError::MissingPeerPublicKey));
+    }
+
+    #[test]
+    fn test_validate_set_remove_without_replace() {
+        let wg = set_device(0, WGPEER_F_REMOVE_ME);
+        assert_eq!(wg.validate_set(), Ok(()));
+        let wg = set_device(WGDEVICE_F_REPLACE_PEERS, 0);
+        assert_eq!(wg.validate_set(), Ok(()));
+    }
+
+    // RFC 7748 section 6.1, the private key is clamped.
+    const PRIVATE_KEY: [u8; WG_KEY_LEN] = [
+        0x70, 0x07, 0x6d, 0x0a, 0x73, 0x18, 0xa5, 0x7d, 0x3c, 0x16, 0xc1, 0x72,
+        0x51, 0xb2, 0x66, 0x45, 0xdf, 0x4c, 0x2f, 0x87, 0xeb, 0xc0, 0x99, 0x2a,
+        0xb1, 0x77, 0xfb, 0xa5, 0x1d, 0xb9, 0x2c, 0x6a,
+    ];
+    const PUBLIC_KEY: [u8; WG_KEY_LEN] = [
+        0x85, 0x20, 0xf0, 0x09, 0x89, 0x30, 0xa7, 0x54, 0x74, 0x8b, 0x7d, 0xdc,
+        0xb4, 0x3e, 0xf7, 0x5a, 0x0d, 0xbf, 0x3a, 0x0d, 0x26, 0x38, 0x1a, 0xf4,
+        0xeb, 0xa4, 0xa9, 0x8e, 0xaa, 0x9b, 0x4e, 0x6a,
+    ];
+
+    fn device_keys(private_key: [u8; WG_KEY_LEN]) -> Wireguard {
+        Wireguard {
+            cmd: WireguardCmd::GetDevice,
+            nlas: vec![
+                WgDeviceAttrs::PrivateKey(private_key),
+                WgDeviceAttrs::PublicKey(PUBLIC_KEY),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_check_peer_limit() {
+        let mut wg = set_device(0, 0);
+        wg.nlas.push(WgDeviceAttrs::Peers(vec![
+            WgPeer(vec![WgPeerAttrs::PublicKey([0x02; WG_KEY_LEN])]),
+            WgPeer(vec![WgPeerAttrs::PublicKey([0x03; WG_KEY_LEN])]),
+        ]));
+        assert_eq!(wg.check_peer_limit(3), Ok(()));
+        assert_eq!(wg.check_peer_limit(4), Ok(()));
+        let err = wg.check_peer_limit(1).unwrap_err();
+        assert_eq!(err, WgError::TooManyPeers { count: 3, max: 1 });
+        assert_eq!(
+            err.to_string(),
+            "too many peers: 3 peers for a limit of 1 (2 over)"
+        );
+        // The fields are public, a value built by hand may be under the
+        // limit.
+        assert_eq!(
+            WgError::TooManyPeers { count: 1, max: 3 }.to_string(),
+            "too many peers: 1 peers for a limit of 3 (0 over)"
+        );
+        assert_eq!(Wireguard::dump_all().check_peer_limit(0), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_allowed_ips() {
+        use crate::nlas::WgAllowedIp;
+
+        let mut wg = set_device(0, 0);
+        wg.extend(vec![
+            WgPeer(vec![
+                WgPeerAttrs::PublicKey([0x02; WG_KEY_LEN]),
+                WgPeerAttrs::AllowedIps(vec![
+                    "10.0.0.0/24".parse().unwrap(),
+                    "fd00::/64".parse().unwrap(),
+                ]),
+            ]),
+            WgPeer(vec![
+                WgPeerAttrs::PublicKey([0x03; WG_KEY_LEN]),
+                WgPeerAttrs::AllowedIps(vec![
+                    WgAllowedIp::with_family(
+                        [10, 0, 1, 0].into(),
+                        24,
+                        AF_INET6,
+                    ),
+                    WgAllowedIp::new_unchecked("fd00::".parse().unwrap(), 129),
+                    WgAllowedIp(vec![WgAllowedIpAttrs::Cidr(24)]),
+                ]),
+            ]),
+        ]);
+        let issues = wg.validate_allowed_ips().unwrap_err();
+        assert_eq!(
+            issues,
+            vec![
+                WgError::AllowedIpFamilyMismatch {
+                    addr: [10, 0, 1, 0].into(),
+                    family: AF_INET6,
+                },
+                WgError::InvalidCidr {
+                    addr: "fd00::".parse().unwrap(),
+                    cidr: 129,
+                },
+                WgError::MissingAllowedIpAttr {
+                    kind: WGALLOWEDIP_A_FAMILY,
+                },
+                WgError::MissingAllowedIpAttr {
+                    kind: WGALLOWEDIP_A_IPADDR,
+                },
+            ]
+        );
+        assert_eq!(
+            issues[0].to_string(),
+            format!(
+                "the allowed IP 10.0.1.0 has the address family {}",
+                AF_INET6
+            )
+        );
+        assert_eq!(
+            issues[3].to_string(),
+            "an allowed IP has no WGALLOWEDIP_A_IPADDR"
+        );
+
+        wg.retain_peers(|peer| peer.public_key() != Some(&[0x03; WG_KEY_LEN]));
+        assert_eq!(wg.validate_allowed_ips(), Ok(()));
+    }
+
+    #[test]
+    fn test_check_key_consistency() {
+        assert_eq!(device_keys(PRIVATE_KEY).check_key_consistency(), Ok(()));
+        assert_eq!(
+            device_keys(PUBLIC_KEY).check_key_consistency(),
+            Err(WgError::UnclampedPrivateKey)
+        );
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_check_key_consistency_mismatch() {
+        let mut other = PRIVATE_KEY;
+        other[1] ^= 0x01;
+        assert_eq!(
+            device_keys(other).check_key_consistency(),
+            Err(WgError::KeyMismatch)
+        );
+    }
+
+    #[test]
+    fn test_validate_set_protocol_version() {
+        let mut wg = set_device(0, 0);
+        if let WgDeviceAttrs::Peers(peers) = &mut wg.nlas[2] {
+            peers[0].0.push(WgPeerAttrs::ProtocolVersion(1));
+        }
+        assert_eq!(wg.validate_set(), Ok(()));
+        if let WgDeviceAttrs::Peers(peers) = &mut wg.nlas[2] {
+            peers[0].0.push(WgPeerAttrs::ProtocolVersion(2));
+        }
+        assert_eq!(
+            wg.validate_set(),
+            Err(WgError::UnsupportedProtocolVersion { version: 2 })
+        );
+    }
+
+    #[test]
+    fn test_validate_valid_set() {
+        assert_eq!(set_device(0, 0).validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_empty_set() {
+        let wg = Wireguard {
+            cmd: WireguardCmd::SetDevice,
+            nlas: vec![],
+        };
+        assert!(wg.is_empty());
+        assert_eq!(
+            wg.validate(),
+            Err(WgError::Multiple(vec![WgError::EmptySetDevice]))
+        );
+        assert_eq!(wg.validate_set(), Err(WgError::EmptySetDevice));
+    }
+
+    #[test]
+    fn test_validate_identity() {
+        let mut wg = set_device(0, 0);
+        wg.nlas.remove(0);
+        assert_eq!(wg.validate_identity(), Err(WgError::MissingIdentity));
+        // validate_set() leaves the identity to validate_identity().
+        assert_eq!(wg.validate_set(), Ok(()));
+        assert_eq!(
+            wg.validate(),
+            Err(WgError::Multiple(vec![WgError::MissingIdentity]))
+        );
+
+        wg.nlas.push(WgDeviceAttrs::IfIndex(3));
+        assert_eq!(wg.validate_identity(), Ok(()));
+        assert_eq!(wg.validate(), Ok(()));
+
+        wg.nlas.push(WgDeviceAttrs::IfName("wg0".to_string()));
+        assert_eq!(wg.validate_identity(), Err(WgError::AmbiguousIdentity));
+        assert_eq!(wg.validate_set(), Ok(()));
+        assert_eq!(
+            wg.validate(),
+            Err(WgError::Multiple(vec![WgError::AmbiguousIdentity]))
+        );
+    }
+
+    #[test]
+    fn test_validate_valid_get() {
+        let wg = Wireguard {
+            cmd: WireguardCmd::GetDevice,
+            nlas: vec![WgDeviceAttrs::IfIndex(3)],
+        };
+        assert_eq!(wg.validate(), Ok(()));
+        assert_eq!(
+            Wireguard::dump_all().validate(),
+            Err(WgError::Multiple(vec![WgError::MissingIdentity]))
+        );
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn test_assert_valid() {
+        set_device(WGDEVICE_F_REPLACE_PEERS, 0).assert_valid();
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    #[should_panic(expected = "invalid SetDevice message: both \
+                               WGDEVICE_A_IFINDEX and WGDEVICE_A_IFNAME are \
+                               set; peer 01010101.. is flagged")]
+    fn test_assert_valid_panics() {
+        let mut wg = set_device(WGDEVICE_F_REPLACE_PEERS, WGPEER_F_REMOVE_ME);
+        wg.nlas.push(WgDeviceAttrs::IfIndex(3));
+        wg.assert_valid();
+    }
+
+    #[test]
+    fn test_validate_multiple_issues() {
+        let mut wg = set_device(WGDEVICE_F_REPLACE_PEERS, WGPEER_F_REMOVE_ME);
+        wg.nlas.push(WgDeviceAttrs::IfIndex(3));
+        if let WgDeviceAttrs::Peers(peers) = &mut wg.nlas[2] {
+            peers[0].0.push(WgPeerAttrs::ProtocolVersion(2));
+        }
+        assert_eq!(
+            wg.validate(),
+            Err(WgError::Multiple(vec![
+                WgError::AmbiguousIdentity,
+                WgError::RedundantPeerRemoval {
+                    public_key: Some([0x01; WG_KEY_LEN])
+                },
+                WgError::UnsupportedProtocolVersion { version: 2 },
+            ]))
+        );
+        assert_eq!(
+            wg.validate_set(),
+            Err(WgError::RedundantPeerRemoval {
+                public_key: Some([0x01; WG_KEY_LEN])
+            })
+        );
+    }
+}